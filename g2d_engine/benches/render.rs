@@ -0,0 +1,98 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use g2d_engine::render::pipeline::{ImageLayer, RenderPipeline, Sprite, SpriteLayer, TileLayer};
+use g2d_engine::tilemap::{Tile, TileMap};
+use image::{Rgba, RgbaImage};
+use std::hint::black_box;
+use std::sync::Arc;
+
+const WIDTH: u32 = 1024;
+const HEIGHT: u32 = 768;
+
+fn background() -> RgbaImage {
+    RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| Rgba([(x % 256) as u8, (y % 256) as u8, 128, 255]))
+}
+
+fn tiles() -> TileLayer {
+    let mut map = TileMap::new(32, 24, 32.);
+    for x in 0..32 {
+        map.set(x, 23, Tile::Solid);
+        if x % 5 == 0 {
+            map.set(x, 15, Tile::Solid);
+        }
+    }
+    let tile = RgbaImage::from_fn(32, 32, |x, y| Rgba([90, (x * 8) as u8, (y * 8) as u8, 220]));
+    TileLayer {
+        map,
+        color: [0, 0, 0, 255],
+        tile: Some(tile),
+    }
+}
+
+fn sprites(frame: u32) -> SpriteLayer {
+    let image = Arc::new(RgbaImage::from_fn(32, 32, |x, y| {
+        Rgba([255, 0, 0, if (x + y) % 2 == 0 { 255 } else { 128 }])
+    }));
+    SpriteLayer {
+        sprites: (0..50)
+            .map(|i| Sprite {
+                image: image.clone(),
+                x: ((i * 97 + frame * 3) % (WIDTH - 32)) as i32,
+                y: ((i * 53) % (HEIGHT - 32)) as i32,
+            })
+            .collect(),
+    }
+}
+
+fn pipeline(parallel: bool) -> RenderPipeline {
+    let mut pipeline = RenderPipeline::new(WIDTH, HEIGHT);
+    pipeline.set_parallel(parallel);
+    pipeline.add_static_layer(ImageLayer::new(background()));
+    pipeline.add_static_layer(tiles());
+    pipeline
+}
+
+fn bench_render(c: &mut Criterion) {
+    let mut frame = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+    let layer = sprites(0);
+    let mut group = c.benchmark_group("render_1024x768");
+
+    // Every layer redrawn on every frame, the way the engine drew before the pipeline.
+    for (name, parallel) in [("full_sequential", false), ("full_parallel", true)] {
+        let mut pipeline = pipeline(parallel);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                pipeline.invalidate_static();
+                pipeline.render(black_box(&mut frame), &[&layer]);
+            })
+        });
+    }
+
+    // Static layers cached, whole frame composed.
+    for (name, parallel) in [("cached_sequential", false), ("cached_parallel", true)] {
+        let mut pipeline = pipeline(parallel);
+        pipeline.render(&mut frame, &[&layer]);
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                pipeline.mark_all_dirty();
+                pipeline.render(black_box(&mut frame), &[&layer]);
+            })
+        });
+    }
+
+    // Static layers cached, only the areas of the moving sprites composed.
+    let mut pipeline = pipeline(true);
+    pipeline.render(&mut frame, &[&layer]);
+    let (before, after) = (sprites(0), sprites(1));
+    group.bench_function("dirty_sprites_parallel", |b| {
+        b.iter(|| {
+            for sprite in before.sprites.iter().chain(after.sprites.iter()) {
+                pipeline.mark_dirty(sprite.bounds());
+            }
+            pipeline.render(black_box(&mut frame), &[&after]);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_render);
+criterion_main!(benches);
@@ -0,0 +1,605 @@
+use crate::controller::{CharacterController, ControllerParams};
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::geometry::Vec2;
+use crate::nav::{FollowStatus, NavGraph, PathFollower};
+use crate::rng::{AI_STREAM, Pcg32, Rng};
+use crate::tilemap::TileMap;
+use crate::world::{EntityInstance, World};
+use std::collections::BTreeMap;
+
+/// Seconds between two path searches of a chasing enemy.
+const REPLAN_INTERVAL: f32 = 0.5;
+
+/// State of the enemy state machine.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum AiState {
+    Idle,
+    Patrol,
+    Chase,
+    Jump,
+    Attack,
+}
+
+/// `EntityState` animation played in each AI state.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AiAnimations {
+    pub idle: String,
+    pub patrol: String,
+    pub chase: String,
+    pub jump: String,
+    pub attack: String,
+}
+
+impl Default for AiAnimations {
+    fn default() -> Self {
+        Self {
+            idle: String::from("idle"),
+            patrol: String::from("walk"),
+            chase: String::from("run"),
+            jump: String::from("jump"),
+            attack: String::from("attack"),
+        }
+    }
+}
+
+impl AiAnimations {
+    pub fn state_name(&self, state: AiState) -> &str {
+        match state {
+            AiState::Idle => &self.idle,
+            AiState::Patrol => &self.patrol,
+            AiState::Chase => &self.chase,
+            AiState::Jump => &self.jump,
+            AiState::Attack => &self.attack,
+        }
+    }
+}
+
+/// Ranged attack of an enemy.
+#[derive(PartialEq, Debug, Clone)]
+pub struct RangedAttack {
+    /// Entity kind spawned as projectile.
+    pub projectile: String,
+    pub speed: f32,
+    pub range: f32,
+    /// Seconds between two shots.
+    pub cooldown: f32,
+    /// Seconds before the projectile disappears.
+    pub lifetime: f32,
+}
+
+/// Tuning of an enemy behaviour.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AiConfig {
+    pub patrol_speed: f32,
+    pub chase_speed: f32,
+    /// Distance at which the target is spotted, if in line of sight.
+    pub sight_range: f32,
+    /// Distance at which a chased target is lost.
+    pub lose_range: f32,
+    /// Jump over gaps and low walls while chasing.
+    pub jumps: bool,
+    pub ranged: Option<RangedAttack>,
+    /// Average pause at each end of the patrol, in seconds. Each pause lasts between half and one
+    /// and a half times this.
+    pub idle_seconds: f32,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        Self {
+            patrol_speed: 60.,
+            chase_speed: 120.,
+            sight_range: 200.,
+            lose_range: 300.,
+            jumps: true,
+            ranged: None,
+            idle_seconds: 1.,
+        }
+    }
+}
+
+/// Published when an enemy changes state.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AiStateChanged {
+    pub entity: EntityId,
+    pub from: AiState,
+    pub to: AiState,
+}
+
+/// Published when an enemy fires a projectile.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ProjectileFired {
+    pub shooter: EntityId,
+    pub projectile: EntityId,
+}
+
+/// Finite state machine driving one enemy.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AiAgent {
+    pub config: AiConfig,
+    pub animations: AiAnimations,
+    pub controller: CharacterController,
+    state: AiState,
+    /// Facing direction, 1 for right and -1 for left.
+    direction: f32,
+    cooldown: f32,
+    /// Remaining pause before patrolling again.
+    idle: f32,
+    path: Option<PathFollower>,
+    replan: f32,
+}
+
+impl AiAgent {
+    pub fn new(config: AiConfig, params: ControllerParams) -> Self {
+        Self {
+            config,
+            animations: AiAnimations::default(),
+            controller: CharacterController::new(params),
+            state: AiState::Patrol,
+            direction: 1.,
+            cooldown: 0.,
+            idle: 0.,
+            path: None,
+            replan: 0.,
+        }
+    }
+
+    pub fn state(&self) -> AiState {
+        self.state
+    }
+
+    pub fn direction(&self) -> f32 {
+        self.direction
+    }
+
+    fn sees(&self, entity: &EntityInstance, target: &EntityInstance, map: &TileMap, range: f32) -> bool {
+        let (from, to) = (entity.bounds().center(), target.bounds().center());
+        from.distance(to) <= range && map.line_of_sight(from, to)
+    }
+
+    /// Next state, given what the enemy perceives this tick.
+    fn think(&mut self, entity: &EntityInstance, target: Option<&EntityInstance>, map: &TileMap) -> AiState {
+        let visible = |range| target.is_some_and(|target| self.sees(entity, target, map, range));
+        match self.state {
+            AiState::Jump if !self.controller.on_ground => AiState::Jump,
+            AiState::Idle | AiState::Patrol | AiState::Jump => {
+                if visible(self.config.sight_range) {
+                    AiState::Chase
+                } else if self.state == AiState::Idle && self.idle > 0. {
+                    AiState::Idle
+                } else {
+                    AiState::Patrol
+                }
+            }
+            AiState::Chase | AiState::Attack => {
+                if !visible(self.config.lose_range) {
+                    return AiState::Patrol;
+                }
+                let target = target.unwrap();
+                let distance = entity.bounds().center().distance(target.bounds().center());
+                match &self.config.ranged {
+                    Some(ranged) if distance <= ranged.range => AiState::Attack,
+                    _ => AiState::Chase,
+                }
+            }
+        }
+    }
+
+    /// Chase along a path of the navigation graph. Returns `false` if there is no path to follow.
+    fn follow_path(
+        &mut self,
+        entity: &mut EntityInstance,
+        target: &EntityInstance,
+        map: &TileMap,
+        nav: &NavGraph,
+        dt: f32,
+    ) -> bool {
+        self.replan -= dt;
+        if self.controller.on_ground && (self.path.is_none() || self.replan <= 0.) {
+            self.replan = REPLAN_INTERVAL;
+            self.path = nav.node_of(entity, map).zip(nav.node_of(target, map)).and_then(|(from, to)| {
+                nav.find_path(from, to).map(|steps| PathFollower::new(from, steps))
+            });
+        }
+        let Some(path) = self.path.as_mut() else {
+            return false;
+        };
+        if path.update(entity, &mut self.controller, map, dt) != FollowStatus::Moving {
+            self.path = None;
+        }
+        if entity.velocity.x != 0. {
+            self.direction = entity.velocity.x.signum();
+        }
+        let state = if self.controller.on_ground { AiState::Chase } else { AiState::Jump };
+        self.set_state(entity, state);
+        true
+    }
+
+    /// Run one tick of the state machine and move the entity. Chasing enemies follow paths of
+    /// `nav` when given, and go straight to the target otherwise. The pauses are drawn from `rng`.
+    pub fn update(
+        &mut self,
+        entity: &mut EntityInstance,
+        target: Option<&EntityInstance>,
+        map: &TileMap,
+        nav: Option<&NavGraph>,
+        rng: &mut Pcg32,
+        dt: f32,
+    ) -> Option<ProjectileRequest> {
+        self.cooldown = (self.cooldown - dt).max(0.);
+        self.idle = (self.idle - dt).max(0.);
+        let mut state = self.think(entity, target, map);
+        if state != AiState::Chase {
+            self.path = None;
+        }
+        if let (AiState::Chase, Some(target), Some(nav)) = (state, target, nav)
+            && self.follow_path(entity, target, map, nav, dt)
+        {
+            return None;
+        }
+        let mut projectile = None;
+        match state {
+            AiState::Idle => entity.velocity.x = 0.,
+            AiState::Patrol => {
+                if wall_ahead(entity, map, self.direction) || !ground_ahead(entity, map, self.direction) {
+                    self.direction = -self.direction;
+                    if self.config.idle_seconds > 0. {
+                        self.idle = self.config.idle_seconds * rng.range_f32(0.5, 1.5);
+                        state = AiState::Idle;
+                    }
+                }
+                entity.velocity.x = match state {
+                    AiState::Idle => 0.,
+                    _ => self.direction * self.config.patrol_speed,
+                };
+            }
+            AiState::Chase | AiState::Attack => {
+                let target = target.unwrap();
+                let dx = target.bounds().center().x - entity.bounds().center().x;
+                self.direction = if dx < 0. { -1. } else { 1. };
+                entity.velocity.x = if state == AiState::Attack {
+                    0.
+                } else {
+                    self.direction * self.config.chase_speed
+                };
+                if state == AiState::Chase
+                    && self.controller.on_ground
+                    && (wall_ahead(entity, map, self.direction) || !ground_ahead(entity, map, self.direction))
+                {
+                    if self.config.jumps && self.can_clear(entity, map) && self.controller.jump(entity) {
+                        self.set_state(entity, AiState::Jump);
+                        self.controller.step(entity, map, dt);
+                        return None;
+                    }
+                    entity.velocity.x = 0.;
+                }
+                if let (AiState::Attack, Some(ranged)) = (state, &self.config.ranged)
+                    && self.cooldown <= 0.
+                {
+                    self.cooldown = ranged.cooldown;
+                    let from = entity.bounds().center();
+                    let to = target.bounds().center();
+                    let distance = from.distance(to).max(0.001);
+                    projectile = Some(ProjectileRequest {
+                        kind: ranged.projectile.clone(),
+                        position: from,
+                        velocity: (to - from) * (ranged.speed / distance),
+                        lifetime: ranged.lifetime,
+                    });
+                }
+            }
+            AiState::Jump => entity.velocity.x = self.direction * self.config.chase_speed,
+        }
+        self.set_state(entity, state);
+        self.controller.step(entity, map, dt);
+        projectile
+    }
+
+    fn set_state(&mut self, entity: &mut EntityInstance, state: AiState) {
+        self.state = state;
+        entity.set_state(self.animations.state_name(state));
+    }
+
+    /// A jump from here lands on ground, over the gap or wall in front of the enemy.
+    fn can_clear(&self, entity: &EntityInstance, map: &TileMap) -> bool {
+        let tile = map.tile_size();
+        let params = &self.controller.params;
+        let reach = (params.jump_distance() / tile).floor() as i32;
+        let rise = (params.jump_height() / tile).floor() as i32;
+        let (front, feet) = front_cell(entity, map, self.direction);
+        (1..=reach).any(|dx| {
+            let x = front + dx * self.direction as i32;
+            // Landing spots from the highest reachable one down to the take off height.
+            (0..=rise).rev().any(|dy| {
+                let y = feet - dy;
+                map.is_solid(x, y + 1) && !map.is_solid(x, y) && !map.is_solid(x, y - 1)
+            })
+        })
+    }
+}
+
+/// Projectile an agent wants to fire.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ProjectileRequest {
+    pub kind: String,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub lifetime: f32,
+}
+
+/// Cell in front of the entity at its feet level.
+fn front_cell(entity: &EntityInstance, map: &TileMap, direction: f32) -> (i32, i32) {
+    let bounds = entity.bounds();
+    let x = if direction > 0. { bounds.right() + 1. } else { bounds.x - 1. };
+    map.cell_at(Vec2::new(x, bounds.bottom() - 1.))
+}
+
+fn wall_ahead(entity: &EntityInstance, map: &TileMap, direction: f32) -> bool {
+    let (x, y) = front_cell(entity, map, direction);
+    map.is_solid(x, y)
+}
+
+fn ground_ahead(entity: &EntityInstance, map: &TileMap, direction: f32) -> bool {
+    let (x, y) = front_cell(entity, map, direction);
+    map.is_solid(x, y + 1)
+}
+
+/// Enemies of the level and the projectiles they fired.
+#[derive(Default)]
+pub struct AiSystem {
+    agents: BTreeMap<EntityId, AiAgent>,
+    projectiles: BTreeMap<EntityId, f32>,
+    nav: Option<NavGraph>,
+    /// Movement and height in tiles the navigation graphs are built for.
+    nav_agent: Option<(ControllerParams, i32)>,
+}
+
+impl AiSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entity: EntityId, agent: AiAgent) {
+        self.agents.insert(entity, agent);
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> Option<AiAgent> {
+        self.agents.remove(&entity)
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&AiAgent> {
+        self.agents.get(&entity)
+    }
+
+    /// Navigation graph of the level, rebuild it when the tile map changes.
+    pub fn set_nav_graph(&mut self, nav: Option<NavGraph>) {
+        self.nav = nav;
+    }
+
+    pub fn nav_graph(&self) -> Option<&NavGraph> {
+        self.nav.as_ref()
+    }
+
+    /// Build the navigation graph of every level for enemies moving with `params`, `height` tiles
+    /// tall. Without it, enemies chase in a straight line.
+    pub fn set_nav_agent(&mut self, agent: Option<(ControllerParams, i32)>) {
+        self.nav_agent = agent;
+    }
+
+    /// Rebuild the navigation graph for a new tile map.
+    pub fn rebuild_nav_graph(&mut self, map: &TileMap) {
+        self.nav = self.nav_agent.map(|(params, height)| NavGraph::build(map, params, height));
+    }
+
+    pub fn clear(&mut self) {
+        self.agents.clear();
+        self.projectiles.clear();
+    }
+
+    /// Update every enemy against `target`, usually the player, then move the projectiles. The
+    /// enemies draw from the [`AI_STREAM`] of `rng`.
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        map: &TileMap,
+        target: Option<EntityId>,
+        rng: &mut Rng,
+        bus: &mut EventBus,
+        dt: f32,
+    ) {
+        let rng = rng.stream(AI_STREAM);
+        self.agents.retain(|id, _| world.get(*id).is_some());
+        let target = target.and_then(|id| world.get(id)).cloned();
+        for (id, agent) in self.agents.iter_mut() {
+            let Some(entity) = world.get_mut(*id) else {
+                continue;
+            };
+            let from = agent.state();
+            let request = agent.update(entity, target.as_ref(), map, self.nav.as_ref(), rng, dt);
+            if from != agent.state() {
+                bus.publish(AiStateChanged {
+                    entity: *id,
+                    from,
+                    to: agent.state(),
+                });
+            }
+            if let Some(request) = request {
+                let projectile = world.spawn(&request.kind, request.position);
+                if let Some(instance) = world.get_mut(projectile) {
+                    // Spawned centered on the shooter.
+                    instance.position = request.position - instance.size * 0.5;
+                    instance.velocity = request.velocity;
+                }
+                self.projectiles.insert(projectile, request.lifetime);
+                bus.publish(ProjectileFired {
+                    shooter: *id,
+                    projectile,
+                });
+            }
+        }
+        let mut expired = Vec::new();
+        for (id, lifetime) in self.projectiles.iter_mut() {
+            *lifetime -= dt;
+            match world.get_mut(*id) {
+                Some(projectile) => {
+                    let velocity = projectile.velocity;
+                    projectile.position += velocity * dt;
+                    if *lifetime <= 0. || map.is_solid_at(projectile.bounds().center()) {
+                        expired.push(*id);
+                    }
+                }
+                None => expired.push(*id),
+            }
+        }
+        for id in expired {
+            self.projectiles.remove(&id);
+            world.despawn(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: f32 = 32.;
+
+    #[test]
+    fn patrol_pauses_at_each_end() {
+        let map = TileMap::from_rows(&["#    #", "######"], TILE);
+        let mut world = World::new();
+        let enemy = world.spawn("slime", Vec2::new(TILE, 0.));
+        let mut agent = AiAgent::new(AiConfig::default(), ControllerParams::default());
+        let mut rng = Pcg32::new(1, 0);
+        let mut states = Vec::new();
+        for _ in 0..80 {
+            agent.update(world.get_mut(enemy).unwrap(), None, &map, None, &mut rng, 0.1);
+            states.push(agent.state());
+        }
+        let idle = states.iter().position(|state| *state == AiState::Idle).expect("never idle");
+        let patrol = idle + states[idle..].iter().position(|state| *state == AiState::Patrol).unwrap();
+        // Paused between 0.5 and 1.5 seconds.
+        assert!((5..=15).contains(&(patrol - idle)), "{:?}", states);
+        let next_idle = patrol + states[patrol..].iter().position(|state| *state == AiState::Idle).unwrap();
+        assert!(next_idle > patrol + 5, "{:?}", states);
+    }
+
+    #[test]
+    fn nav_graph_follows_the_tile_map() {
+        let mut ai = AiSystem::new();
+        let map = TileMap::from_rows(&["    ", "####"], TILE);
+        ai.rebuild_nav_graph(&map);
+        assert!(ai.nav_graph().is_none());
+
+        ai.set_nav_agent(Some((ControllerParams::default(), 1)));
+        ai.rebuild_nav_graph(&map);
+        assert!(ai.nav_graph().unwrap().is_node((3, 0)));
+        ai.rebuild_nav_graph(&TileMap::from_rows(&["  ", "##"], TILE));
+        assert!(!ai.nav_graph().unwrap().is_node((3, 0)));
+    }
+
+    fn scene(rows: &[&str], enemy: Vec2, target: Vec2) -> (TileMap, World, EntityId, EntityId) {
+        let mut world = World::new();
+        let enemy = world.spawn("slime", enemy);
+        let target = world.spawn("joueur", target);
+        (TileMap::from_rows(rows, TILE), world, enemy, target)
+    }
+
+    #[test]
+    fn solid_tiles_block_the_sight() {
+        let mut rng = Pcg32::new(1, 0);
+        for (rows, seen) in [(["   #   ", "#######"], false), (["       ", "#######"], true)] {
+            let (map, mut world, enemy, target) = scene(&rows, Vec2::ZERO, Vec2::new(6. * TILE, 0.));
+            let target = world.get(target).cloned();
+            let mut agent = AiAgent::new(AiConfig::default(), ControllerParams::default());
+            agent.update(world.get_mut(enemy).unwrap(), target.as_ref(), &map, None, &mut rng, 0.1);
+            assert_eq!(agent.state() == AiState::Chase, seen, "{:?}", rows);
+        }
+    }
+
+    #[test]
+    fn chase_starts_in_sight_range_and_stops_past_lose_range() {
+        let (map, mut world, enemy, target) = scene(&[&" ".repeat(20), &"#".repeat(20)], Vec2::ZERO, Vec2::ZERO);
+        let mut agent = AiAgent::new(AiConfig::default(), ControllerParams::default());
+        let mut rng = Pcg32::new(1, 0);
+        let mut tick = |world: &mut World, x: f32| {
+            let enemy_x = world.get(enemy).unwrap().position.x;
+            world.get_mut(target).unwrap().position.x = enemy_x + x;
+            let target = world.get(target).cloned();
+            agent.update(world.get_mut(enemy).unwrap(), target.as_ref(), &map, None, &mut rng, 0.1);
+            agent.state()
+        };
+        assert_eq!(tick(&mut world, 250.), AiState::Patrol);
+        assert_eq!(tick(&mut world, 150.), AiState::Chase);
+        // Between the sight and the lose ranges, a chased target is kept.
+        assert_eq!(tick(&mut world, 250.), AiState::Chase);
+        assert_eq!(tick(&mut world, 350.), AiState::Patrol);
+    }
+
+    #[test]
+    fn chase_jumps_over_a_gap_with_the_nav_graph() {
+        let rows = ["        ", "###  ###", "########"];
+        let (map, mut world, enemy, target) = scene(&rows, Vec2::ZERO, Vec2::new(7. * TILE, 0.));
+        let mut ai = AiSystem::new();
+        ai.set_nav_agent(Some((ControllerParams::default(), 1)));
+        ai.rebuild_nav_graph(&map);
+        let config = AiConfig {
+            sight_range: 400.,
+            lose_range: 500.,
+            ..AiConfig::default()
+        };
+        ai.add(enemy, AiAgent::new(config, ControllerParams::default()));
+        let mut rng = Rng::new(1);
+        let mut bus = EventBus::new();
+        let mut states = Vec::new();
+        for _ in 0..180 {
+            ai.update(&mut world, &map, Some(target), &mut rng, &mut bus, 1. / 60.);
+            bus.dispatch();
+            states.extend(bus.read::<AiStateChanged>().iter().map(|changed| changed.to));
+        }
+        assert!(states.contains(&AiState::Jump), "{:?}", states);
+        let enemy = world.get(enemy).unwrap();
+        // Landed on the far platform, not in the gap.
+        assert!(enemy.position.x >= 5. * TILE, "{:?}", enemy.position);
+        assert!(enemy.position.y <= 0.5, "{:?}", enemy.position);
+    }
+
+    #[test]
+    fn ranged_attack_waits_for_its_cooldown() {
+        let rows = [" ".repeat(16), "#".repeat(16)];
+        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+        let (map, mut world, enemy, target) = scene(&rows, Vec2::ZERO, Vec2::new(3. * TILE, 0.));
+        let mut ai = AiSystem::new();
+        let config = AiConfig {
+            ranged: Some(RangedAttack {
+                projectile: String::from("fleche"),
+                speed: 200.,
+                range: 150.,
+                cooldown: 1.,
+                lifetime: 0.5,
+            }),
+            ..AiConfig::default()
+        };
+        ai.add(enemy, AiAgent::new(config, ControllerParams::default()));
+        let mut rng = Rng::new(1);
+        let mut bus = EventBus::new();
+        let mut shots = Vec::new();
+        for tick in 0..15 {
+            ai.update(&mut world, &map, Some(target), &mut rng, &mut bus, 0.1);
+            bus.dispatch();
+            for fired in bus.read::<ProjectileFired>() {
+                assert_eq!(fired.shooter, enemy);
+                let projectile = world.get(fired.projectile).unwrap();
+                assert_eq!(projectile.kind, "fleche");
+                assert!(projectile.velocity.x > 0., "{:?}", projectile.velocity);
+                shots.push(tick);
+            }
+        }
+        assert_eq!(ai.get(enemy).unwrap().state(), AiState::Attack);
+        assert_eq!(shots.len(), 2, "{:?}", shots);
+        // Spotted on the first tick, shot at on the next one.
+        assert_eq!(shots[0], 1);
+        assert!((11..=12).contains(&shots[1]), "{:?}", shots);
+    }
+}
@@ -0,0 +1,52 @@
+use image::{ImageError, RgbaImage};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Images loaded from the game resources directory, by path relative to it.
+pub struct Assets {
+    root: PathBuf,
+    images: HashMap<String, RgbaImage>,
+}
+
+impl Assets {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            images: HashMap::new(),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Load an image, or return the already loaded one.
+    pub fn load_image(&mut self, name: &str) -> Result<&RgbaImage, ImageError> {
+        if !self.images.contains_key(name) {
+            let image = image::open(self.root.join(name))?.to_rgba8();
+            self.images.insert(String::from(name), image);
+        }
+        Ok(&self.images[name])
+    }
+
+    pub fn image(&self, name: &str) -> Option<&RgbaImage> {
+        self.images.get(name)
+    }
+
+    /// Name of the loaded image stored at `path`, if any.
+    pub fn image_name(&self, path: &Path) -> Option<String> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.images.keys().find_map(|name| {
+            let candidate = self.root.join(name);
+            let candidate = candidate.canonicalize().unwrap_or(candidate);
+            (candidate == path).then(|| name.clone())
+        })
+    }
+
+    /// Decode the image again from disk. The previous image is kept if decoding fails.
+    pub fn reload_image(&mut self, name: &str) -> Result<(), ImageError> {
+        let image = image::open(self.root.join(name))?.to_rgba8();
+        self.images.insert(String::from(name), image);
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+use crate::entity::EntityId;
+
+/// Request to play a sound, published on the event bus for the audio backend.
+#[derive(PartialEq, Debug, Clone)]
+pub struct PlaySound {
+    pub name: String,
+    /// Entity emitting the sound, if any.
+    pub source: Option<EntityId>,
+}
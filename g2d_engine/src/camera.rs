@@ -0,0 +1,54 @@
+use crate::geometry::Vec2;
+
+/// Top left corner of the view in the world, with smooth pans.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Camera {
+    pub position: Vec2,
+    pan: Option<Pan>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+struct Pan {
+    from: Vec2,
+    to: Vec2,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move to `to` in `seconds`, easing in and out.
+    pub fn pan_to(&mut self, to: Vec2, seconds: f32) {
+        if seconds <= 0. {
+            self.position = to;
+            self.pan = None;
+            return;
+        }
+        self.pan = Some(Pan {
+            from: self.position,
+            to,
+            duration: seconds,
+            elapsed: 0.,
+        });
+    }
+
+    pub fn is_panning(&self) -> bool {
+        self.pan.is_some()
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let Some(pan) = self.pan.as_mut() else {
+            return;
+        };
+        pan.elapsed += dt;
+        let t = (pan.elapsed / pan.duration).min(1.);
+        let eased = t * t * (3. - 2. * t);
+        self.position = pan.from + (pan.to - pan.from) * eased;
+        if t >= 1. {
+            self.pan = None;
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::geometry::{Rect, Vec2};
+use crate::world::{EntityInstance, World};
+use log::info;
+use std::collections::{BTreeMap, HashMap};
+
+/// Side of a fighter, hitboxes only hurt fighters of the other teams.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub enum Team {
+    Player,
+    Enemy,
+    Neutral,
+}
+
+/// Attacking box, relative to the top left corner of the entity.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Hitbox {
+    pub area: Rect,
+    pub damage: u32,
+    /// Horizontal push away from the attacker and upward push, in pixels per second.
+    pub knockback: Vec2,
+    /// Only hits while the attacker falls onto the target, which bounces the attacker.
+    pub stomp: bool,
+}
+
+impl Hitbox {
+    pub fn new(area: Rect, damage: u32) -> Self {
+        Self {
+            area,
+            damage,
+            knockback: Vec2::new(200., 200.),
+            stomp: false,
+        }
+    }
+
+    pub fn with_knockback(mut self, knockback: Vec2) -> Self {
+        self.knockback = knockback;
+        self
+    }
+
+    pub fn stomp(mut self) -> Self {
+        self.stomp = true;
+        self
+    }
+}
+
+/// Hitboxes and hurtboxes of one animation frame.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct FrameBoxes {
+    pub hitboxes: Vec<Hitbox>,
+    /// Vulnerable areas, relative to the top left corner of the entity.
+    pub hurtboxes: Vec<Rect>,
+}
+
+/// Combat boxes of the animation frames, by entity kind and state.
+///
+/// Frames without boxes have no hitbox and the whole entity bounds as hurtbox. A state with fewer
+/// frames than its animation reuses its last boxes.
+#[derive(Default)]
+pub struct CombatBoxes {
+    frames: HashMap<(String, String), Vec<FrameBoxes>>,
+}
+
+impl CombatBoxes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_frames(&mut self, kind: &str, state: &str, frames: Vec<FrameBoxes>) {
+        self.frames.insert((String::from(kind), String::from(state)), frames);
+    }
+
+    /// Boxes of the current frame of the entity.
+    pub fn get(&self, entity: &EntityInstance) -> Option<&FrameBoxes> {
+        let frames = self.frames.get(&(entity.kind.clone(), entity.state.clone()))?;
+        frames.get(entity.frame).or(frames.last())
+    }
+
+    fn hitboxes(&self, entity: &EntityInstance) -> Vec<Hitbox> {
+        let Some(boxes) = self.get(entity) else {
+            return Vec::new();
+        };
+        boxes
+            .hitboxes
+            .iter()
+            .map(|hitbox| Hitbox {
+                area: hitbox.area.translate(entity.position),
+                ..hitbox.clone()
+            })
+            .collect()
+    }
+
+    fn hurtboxes(&self, entity: &EntityInstance) -> Vec<Rect> {
+        match self.get(entity) {
+            Some(boxes) => boxes.hurtboxes.iter().map(|rect| rect.translate(entity.position)).collect(),
+            None => vec![entity.bounds()],
+        }
+    }
+}
+
+/// Health of an entity taking part in fights.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Fighter {
+    pub team: Team,
+    pub health: u32,
+    /// Fighters without health, like projectiles, only deal damage.
+    pub max_health: u32,
+    /// Remaining invincibility, in seconds.
+    pub invincible: f32,
+}
+
+impl Fighter {
+    pub fn new(team: Team, max_health: u32) -> Self {
+        Self {
+            team,
+            health: max_health,
+            max_health,
+            invincible: 0.,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.max_health > 0 && self.health == 0
+    }
+
+    fn vulnerable(&self) -> bool {
+        self.max_health > 0 && self.health > 0 && self.invincible <= 0.
+    }
+
+    pub fn heal(&mut self, amount: u32) {
+        if !self.is_dead() {
+            self.health = (self.health + amount).min(self.max_health);
+        }
+    }
+}
+
+/// Tuning of the combat rules.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CombatConfig {
+    /// Invincibility after being hurt, in seconds.
+    pub invincibility: f32,
+    /// Period of the blinking of invincible entities, in seconds.
+    pub blink_period: f32,
+    /// Upward speed given to an entity stomping on another.
+    pub stomp_bounce: f32,
+    /// `EntityState` played by dead entities.
+    pub death_state: String,
+}
+
+impl Default for CombatConfig {
+    fn default() -> Self {
+        Self {
+            invincibility: 1.,
+            blink_period: 0.1,
+            stomp_bounce: 300.,
+            death_state: String::from("death"),
+        }
+    }
+}
+
+/// Published when a hitbox hurts a fighter.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Damaged {
+    pub attacker: EntityId,
+    pub target: EntityId,
+    pub amount: u32,
+    pub health: u32,
+    pub knockback: Vec2,
+}
+
+/// Published when a fighter health drops to zero.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Died {
+    pub entity: EntityId,
+    pub killer: EntityId,
+}
+
+/// Fighters of the level and the hits between them.
+#[derive(Default)]
+pub struct Combat {
+    pub config: CombatConfig,
+    pub boxes: CombatBoxes,
+    fighters: BTreeMap<EntityId, Fighter>,
+}
+
+impl Combat {
+    pub fn new(config: CombatConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn add(&mut self, entity: EntityId, fighter: Fighter) {
+        self.fighters.insert(entity, fighter);
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> Option<Fighter> {
+        self.fighters.remove(&entity)
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&Fighter> {
+        self.fighters.get(&entity)
+    }
+
+    pub fn get_mut(&mut self, entity: EntityId) -> Option<&mut Fighter> {
+        self.fighters.get_mut(&entity)
+    }
+
+    pub fn clear(&mut self) {
+        self.fighters.clear();
+    }
+
+    /// Entities are hidden on every other blink period while invincible.
+    pub fn is_visible(&self, entity: EntityId) -> bool {
+        match self.fighters.get(&entity) {
+            Some(fighter) if fighter.invincible > 0. && !fighter.is_dead() => {
+                ((fighter.invincible / self.config.blink_period) as u32).is_multiple_of(2)
+            }
+            _ => true,
+        }
+    }
+
+    /// Resolve the hits of this tick. Each fighter is hurt at most once per tick, by the attacker
+    /// with the lowest id, and never by the fighter it stomps on. Returns the entities that died.
+    pub fn update(&mut self, world: &mut World, bus: &mut EventBus, dt: f32) -> Vec<EntityId> {
+        self.fighters.retain(|id, _| world.get(*id).is_some());
+        for fighter in self.fighters.values_mut() {
+            fighter.invincible = (fighter.invincible - dt).max(0.);
+        }
+
+        let mut hits = Vec::new();
+        for (attacker_id, attacker) in self.fighters.iter().filter(|(_, fighter)| !fighter.is_dead()) {
+            let attacker_entity = world.get(*attacker_id).unwrap();
+            let hitboxes = self.boxes.hitboxes(attacker_entity);
+            if hitboxes.is_empty() {
+                continue;
+            }
+            for (target_id, target) in self.fighters.iter() {
+                if target.team == attacker.team || !target.vulnerable() || hits.iter().any(|(_, t, _)| t == target_id) {
+                    continue;
+                }
+                let target_entity = world.get(*target_id).unwrap();
+                let hurtboxes = self.boxes.hurtboxes(target_entity);
+                let hit = hitboxes.iter().find(|hitbox| {
+                    (!hitbox.stomp || stomps(attacker_entity, target_entity))
+                        && hurtboxes.iter().any(|hurtbox| hitbox.area.intersects(hurtbox))
+                });
+                if let Some(hitbox) = hit {
+                    hits.push((*attacker_id, *target_id, hitbox.clone()));
+                }
+            }
+        }
+
+        // A stomped fighter does not hurt the one stomping on it.
+        let stomps_back: Vec<(EntityId, EntityId)> =
+            hits.iter().filter(|(_, _, hitbox)| hitbox.stomp).map(|(a, t, _)| (*t, *a)).collect();
+        hits.retain(|(a, t, _)| !stomps_back.contains(&(*a, *t)));
+
+        let mut dead = Vec::new();
+        for (attacker_id, target_id, hitbox) in hits {
+            let attacker_center = world.get(attacker_id).unwrap().bounds().center();
+            if hitbox.stomp
+                && let Some(attacker) = world.get_mut(attacker_id)
+            {
+                attacker.velocity.y = -self.config.stomp_bounce;
+            }
+            let target = self.fighters.get_mut(&target_id).unwrap();
+            let entity = world.get_mut(target_id).unwrap();
+            let side = if entity.bounds().center().x < attacker_center.x { -1. } else { 1. };
+            let knockback = Vec2::new(side * hitbox.knockback.x, -hitbox.knockback.y);
+            target.health = target.health.saturating_sub(hitbox.damage);
+            target.invincible = self.config.invincibility;
+            entity.velocity = knockback;
+            bus.publish(Damaged {
+                attacker: attacker_id,
+                target: target_id,
+                amount: hitbox.damage,
+                health: target.health,
+                knockback,
+            });
+            if target.is_dead() {
+                info!("COMBAT:{:?} killed by {:?}", target_id, attacker_id);
+                entity.set_state(&self.config.death_state);
+                entity.velocity.x = 0.;
+                bus.publish(Died {
+                    entity: target_id,
+                    killer: attacker_id,
+                });
+                dead.push(target_id);
+            }
+        }
+        dead
+    }
+}
+
+/// The attacker falls onto the target from above.
+fn stomps(attacker: &EntityInstance, target: &EntityInstance) -> bool {
+    attacker.velocity.y > 0. && attacker.bounds().center().y < target.bounds().y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::Animation;
+
+    /// Boxes of the `default` state, the one of spawned entities, hurt on their whole bounds.
+    fn always(combat: &mut Combat, kind: &str, hitbox: Hitbox) {
+        let boxes = FrameBoxes {
+            hitboxes: vec![hitbox],
+            hurtboxes: vec![Rect::new(0., 0., 32., 32.)],
+        };
+        combat.boxes.set_frames(kind, "default", vec![boxes]);
+    }
+
+    #[test]
+    fn knockback_pushes_away_from_the_attacker() {
+        let mut world = World::new();
+        let spikes = world.spawn("pique", Vec2::new(100., 0.));
+        let left = world.spawn("joueur", Vec2::new(64., 0.));
+        let right = world.spawn("joueur", Vec2::new(140., 0.));
+        let mut combat = Combat::default();
+        let hitbox = Hitbox::new(Rect::new(-40., 0., 112., 32.), 1).with_knockback(Vec2::new(150., 50.));
+        always(&mut combat, "pique", hitbox);
+        combat.add(spikes, Fighter::new(Team::Neutral, 0));
+        combat.add(left, Fighter::new(Team::Player, 3));
+        combat.add(right, Fighter::new(Team::Player, 3));
+        let mut bus = EventBus::new();
+
+        combat.update(&mut world, &mut bus, 0.1);
+        bus.dispatch();
+        assert_eq!(world.get(left).unwrap().velocity, Vec2::new(-150., -50.));
+        assert_eq!(world.get(right).unwrap().velocity, Vec2::new(150., -50.));
+        let knockbacks: Vec<_> = bus.read::<Damaged>().iter().map(|d| (d.target, d.knockback)).collect();
+        assert_eq!(knockbacks, [(left, Vec2::new(-150., -50.)), (right, Vec2::new(150., -50.))]);
+        assert_eq!(combat.get(spikes).unwrap().health, 0, "fighters without health are never hurt");
+    }
+
+    #[test]
+    fn invincibility_blocks_hits_and_blinks() {
+        let mut world = World::new();
+        let slime = world.spawn("slime", Vec2::new(0., 0.));
+        let player = world.spawn("joueur", Vec2::new(10., 0.));
+        let mut combat = Combat::default();
+        always(&mut combat, "slime", Hitbox::new(Rect::new(0., 0., 32., 32.), 1));
+        combat.add(slime, Fighter::new(Team::Enemy, 1));
+        combat.add(player, Fighter::new(Team::Player, 3));
+        let mut bus = EventBus::new();
+
+        combat.update(&mut world, &mut bus, 0.1);
+        assert_eq!(combat.get(player).unwrap().health, 2);
+        assert_eq!(combat.get(player).unwrap().invincible, 1.);
+        combat.update(&mut world, &mut bus, 0.1);
+        assert_eq!(combat.get(player).unwrap().health, 2, "the second hit is blocked");
+
+        combat.get_mut(player).unwrap().invincible = 0.25;
+        assert!(combat.is_visible(player));
+        combat.get_mut(player).unwrap().invincible = 0.15;
+        assert!(!combat.is_visible(player));
+        assert!(combat.is_visible(slime));
+
+        combat.update(&mut world, &mut bus, 0.2);
+        assert_eq!(combat.get(player).unwrap().health, 1, "hurt again once the invincibility ends");
+    }
+
+    #[test]
+    fn stomp_hurts_the_enemy_and_bounces_the_player() {
+        let mut world = World::new();
+        let player = world.spawn("joueur", Vec2::new(0., 0.));
+        let slime = world.spawn("slime", Vec2::new(0., 20.));
+        world.get_mut(player).unwrap().velocity = Vec2::new(0., 100.);
+        let mut combat = Combat::default();
+        always(&mut combat, "joueur", Hitbox::new(Rect::new(0., 24., 32., 8.), 1).stomp());
+        always(&mut combat, "slime", Hitbox::new(Rect::new(0., 0., 32., 32.), 1));
+        combat.add(player, Fighter::new(Team::Player, 3));
+        combat.add(slime, Fighter::new(Team::Enemy, 2));
+        let mut bus = EventBus::new();
+
+        combat.update(&mut world, &mut bus, 0.1);
+        assert_eq!(combat.get(slime).unwrap().health, 1);
+        assert_eq!(combat.get(player).unwrap().health, 3);
+        assert_eq!(world.get(player).unwrap().velocity.y, -300.);
+
+        // Going up, the stomp hitbox does not hit and the slime hurts the player.
+        world.get_mut(slime).unwrap().position = Vec2::new(0., 20.);
+        combat.get_mut(slime).unwrap().invincible = 0.;
+        combat.update(&mut world, &mut bus, 0.1);
+        assert_eq!(combat.get(slime).unwrap().health, 1);
+        assert_eq!(combat.get(player).unwrap().health, 2);
+    }
+
+    #[test]
+    fn fighter_at_zero_health_dies() {
+        let mut world = World::new();
+        let player = world.spawn("joueur", Vec2::new(0., 0.));
+        let slime = world.spawn("slime", Vec2::new(10., 0.));
+        let mut combat = Combat::default();
+        always(&mut combat, "joueur", Hitbox::new(Rect::new(0., 0., 48., 32.), 5));
+        always(&mut combat, "slime", Hitbox::new(Rect::new(0., 0., 32., 32.), 1));
+        combat.add(player, Fighter::new(Team::Player, 3));
+        combat.add(slime, Fighter::new(Team::Enemy, 2));
+        let mut bus = EventBus::new();
+
+        assert_eq!(combat.update(&mut world, &mut bus, 0.1), [slime]);
+        bus.dispatch();
+        assert!(combat.get(slime).unwrap().is_dead());
+        assert_eq!(combat.get(slime).unwrap().health, 0);
+        let entity = world.get(slime).unwrap();
+        assert_eq!(entity.state, "death");
+        assert_eq!(entity.velocity.x, 0.);
+        assert_eq!(
+            bus.read::<Died>(),
+            [Died {
+                entity: slime,
+                killer: player,
+            }]
+        );
+
+        // Both hit each other during the tick of the death.
+        assert_eq!(combat.get(player).unwrap().health, 2);
+        assert!(combat.update(&mut world, &mut bus, 2.).is_empty());
+        assert_eq!(combat.get(player).unwrap().health, 2, "dead fighters do not attack");
+    }
+
+    #[test]
+    fn hitbox_hurts_only_on_its_active_frame() {
+        let mut world = World::new();
+        world.set_animation(
+            "knight",
+            "attack",
+            Animation {
+                frames: 3,
+                frame_duration: 0.1,
+            },
+        );
+        let knight = world.spawn("knight", Vec2::new(0., 0.));
+        let slime = world.spawn("slime", Vec2::new(40., 0.));
+        world.get_mut(knight).unwrap().set_state("attack");
+        let mut combat = Combat::new(CombatConfig {
+            invincibility: 0.,
+            ..Default::default()
+        });
+        let swing = FrameBoxes {
+            hitboxes: vec![Hitbox::new(Rect::new(32., 0., 16., 32.), 1)],
+            hurtboxes: Vec::new(),
+        };
+        combat
+            .boxes
+            .set_frames("knight", "attack", vec![FrameBoxes::default(), swing, FrameBoxes::default()]);
+        combat.add(knight, Fighter::new(Team::Player, 3));
+        combat.add(slime, Fighter::new(Team::Enemy, 3));
+        let mut bus = EventBus::new();
+
+        let mut health = Vec::new();
+        for _ in 0..4 {
+            combat.update(&mut world, &mut bus, 0.05);
+            health.push(combat.get(slime).unwrap().health);
+            world.animate(0.1);
+        }
+        assert_eq!(health, vec![3, 2, 2, 2]);
+        assert_eq!(world.get(knight).unwrap().frame, 1, "the animation loops");
+    }
+}
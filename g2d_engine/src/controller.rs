@@ -0,0 +1,109 @@
+use crate::geometry::Vec2;
+use crate::tilemap::TileMap;
+use crate::world::EntityInstance;
+
+/// Movement parameters of a platformer character, in pixels and seconds.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ControllerParams {
+    pub run_speed: f32,
+    /// Initial upward speed of a jump.
+    pub jump_velocity: f32,
+    pub gravity: f32,
+    pub max_fall_speed: f32,
+}
+
+impl Default for ControllerParams {
+    fn default() -> Self {
+        Self {
+            run_speed: 160.,
+            jump_velocity: 420.,
+            gravity: 1200.,
+            max_fall_speed: 600.,
+        }
+    }
+}
+
+impl ControllerParams {
+    /// Highest point of a jump above the take off height.
+    pub fn jump_height(&self) -> f32 {
+        self.jump_velocity * self.jump_velocity / (2. * self.gravity)
+    }
+
+    /// Horizontal distance covered at full speed by a jump landing at the take off height.
+    pub fn jump_distance(&self) -> f32 {
+        self.run_speed * 2. * self.jump_velocity / self.gravity
+    }
+}
+
+/// Kinematic character moving on a tile map with gravity.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct CharacterController {
+    pub params: ControllerParams,
+    pub on_ground: bool,
+    pub hit_wall: bool,
+}
+
+impl CharacterController {
+    pub fn new(params: ControllerParams) -> Self {
+        Self {
+            params,
+            on_ground: false,
+            hit_wall: false,
+        }
+    }
+
+    /// Start a jump if standing on the ground.
+    pub fn jump(&mut self, entity: &mut EntityInstance) -> bool {
+        if !self.on_ground {
+            return false;
+        }
+        entity.velocity.y = -self.params.jump_velocity;
+        self.on_ground = false;
+        true
+    }
+
+    /// Apply gravity and move the entity by its velocity, stopping on solid tiles.
+    pub fn step(&mut self, entity: &mut EntityInstance, map: &TileMap, dt: f32) {
+        entity.velocity.y = (entity.velocity.y + self.params.gravity * dt).min(self.params.max_fall_speed);
+
+        // Horizontal then vertical, so corners resolve the same way every time.
+        self.hit_wall = false;
+        entity.position.x += entity.velocity.x * dt;
+        let cells = map.solid_cells(&entity.bounds());
+        let blocking = if entity.velocity.x > 0. {
+            cells.iter().map(|(x, _)| *x).min()
+        } else {
+            cells.iter().map(|(x, _)| *x).max()
+        };
+        if let Some(x) = blocking.filter(|_| entity.velocity.x != 0.) {
+            let cell = map.cell_rect(x, 0);
+            entity.position.x = if entity.velocity.x > 0. { cell.x - entity.size.x } else { cell.right() };
+            entity.velocity.x = 0.;
+            self.hit_wall = true;
+        }
+
+        self.on_ground = false;
+        entity.position.y += entity.velocity.y * dt;
+        let cells = map.solid_cells(&entity.bounds());
+        let blocking = if entity.velocity.y > 0. {
+            cells.iter().map(|(_, y)| *y).min()
+        } else {
+            cells.iter().map(|(_, y)| *y).max()
+        };
+        if let Some(y) = blocking.filter(|_| entity.velocity.y != 0.) {
+            let cell = map.cell_rect(0, y);
+            if entity.velocity.y > 0. {
+                entity.position.y = cell.y - entity.size.y;
+                self.on_ground = true;
+            } else {
+                entity.position.y = cell.bottom();
+            }
+            entity.velocity.y = 0.;
+        }
+        if !self.on_ground {
+            // Resting exactly on a tile does not overlap it, probe one pixel below.
+            let feet = entity.bounds().translate(Vec2::new(0., 1.));
+            self.on_ground = entity.velocity.y >= 0. && !map.solid_cells(&feet).is_empty();
+        }
+    }
+}
@@ -0,0 +1,382 @@
+use crate::camera::Camera;
+use crate::dialogue::{Dialogue, DialogueConfig, DialogueRunner};
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::geometry::Vec2;
+use crate::input::InputActions;
+use crate::render::post_process::{Color, EffectId, Fade, PostProcess};
+use crate::world::World;
+use gwen2d_project::db::project_repository;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Step of a cutscene. Entities are referenced by kind, the first instance of the kind is used.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CutsceneStep {
+    /// Play a dialogue and wait for its end.
+    Dialogue { name: String },
+    /// Walk an entity to a position at `speed` pixels per second.
+    MoveEntity { entity: String, x: f32, y: f32, speed: f32 },
+    /// Switch the animation state of an entity.
+    PlayAnimation { entity: String, state: String },
+    Wait { seconds: f32 },
+    CameraPan { x: f32, y: f32, seconds: f32 },
+    /// Fade the screen out to `color`, or back in from it.
+    Fade { fade_out: bool, seconds: f32, color: Color },
+}
+
+/// Sequence of steps played one after the other.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+pub struct Cutscene {
+    pub steps: Vec<CutsceneStep>,
+}
+
+/// Dialogue or cutscene, as stored in the project database.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StoryAsset {
+    Dialogue(Dialogue),
+    Cutscene(Cutscene),
+}
+
+/// Dialogues and cutscenes of the game, by name.
+#[derive(Default)]
+pub struct StoryLibrary {
+    dialogues: HashMap<String, Dialogue>,
+    cutscenes: HashMap<String, Cutscene>,
+}
+
+impl StoryLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: &str, asset: StoryAsset) {
+        match asset {
+            StoryAsset::Dialogue(dialogue) => {
+                self.dialogues.insert(String::from(name), dialogue);
+            }
+            StoryAsset::Cutscene(cutscene) => {
+                self.cutscenes.insert(String::from(name), cutscene);
+            }
+        }
+    }
+
+    /// Add a dialogue or cutscene from its JSON.
+    pub fn insert_json(&mut self, name: &str, json: &str) -> Result<(), String> {
+        let asset = serde_json::from_str(json).map_err(|e| format!("{} : {}", name, e))?;
+        self.insert(name, asset);
+        Ok(())
+    }
+
+    /// Load every dialogue and cutscene of the project database.
+    pub async fn load_db(&mut self, pool: &Pool<Sqlite>) -> Result<(), String> {
+        let stories = project_repository::stories(pool).await.map_err(|e| e.to_string())?;
+        for (name, data) in stories.iter() {
+            self.insert_json(name, data)?;
+        }
+        info!("STORY:{} dialogues and {} cutscenes loaded", self.dialogues.len(), self.cutscenes.len());
+        Ok(())
+    }
+
+    pub fn dialogue(&self, name: &str) -> Option<&Dialogue> {
+        self.dialogues.get(name)
+    }
+
+    pub fn cutscene(&self, name: &str) -> Option<&Cutscene> {
+        self.cutscenes.get(name)
+    }
+}
+
+/// Published when a cutscene played all its steps.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CutsceneFinished {
+    pub cutscene: String,
+}
+
+struct RunningCutscene {
+    name: String,
+    steps: VecDeque<CutsceneStep>,
+    started: bool,
+    elapsed: f32,
+}
+
+/// Plays the dialogues and cutscenes. Gameplay input is blocked while one is active.
+#[derive(Default)]
+pub struct CutscenePlayer {
+    pub library: StoryLibrary,
+    pub config: DialogueConfig,
+    cutscene: Option<RunningCutscene>,
+    dialogue: Option<DialogueRunner>,
+    /// Fade out pushed by a cutscene, covering the screen until a fade in step.
+    fade: Option<EffectId>,
+}
+
+impl CutscenePlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.cutscene.is_some() || self.dialogue.is_some()
+    }
+
+    pub fn dialogue(&self) -> Option<&DialogueRunner> {
+        self.dialogue.as_ref()
+    }
+
+    /// Start a dialogue outside of any cutscene.
+    pub fn start_dialogue(&mut self, name: &str) -> Result<(), String> {
+        let dialogue = self.library.dialogue(name).ok_or(format!("unknown dialogue {}", name))?;
+        self.dialogue = Some(DialogueRunner::new(name, dialogue.clone())?);
+        Ok(())
+    }
+
+    pub fn start_cutscene(&mut self, name: &str) -> Result<(), String> {
+        let cutscene = self.library.cutscene(name).ok_or(format!("unknown cutscene {}", name))?;
+        info!("STORY:cutscene {} started", name);
+        self.cutscene = Some(RunningCutscene {
+            name: String::from(name),
+            steps: cutscene.steps.iter().cloned().collect(),
+            started: false,
+            elapsed: 0.,
+        });
+        Ok(())
+    }
+
+    /// Stop the running cutscene and dialogue, removing the fade out left by the cutscene.
+    pub fn stop(&mut self, post_process: &mut PostProcess) {
+        self.cutscene = None;
+        self.dialogue = None;
+        if let Some(fade) = self.fade.take() {
+            post_process.remove(fade);
+        }
+    }
+
+    /// Play the current step and the dialogue. Returns `true` while active.
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        camera: &mut Camera,
+        post_process: &mut PostProcess,
+        input: &InputActions,
+        bus: &mut EventBus,
+        dt: f32,
+    ) -> bool {
+        if let Some(dialogue) = self.dialogue.as_mut() {
+            dialogue.update(input, &self.config, bus, dt);
+            if dialogue.is_finished() {
+                self.dialogue = None;
+            }
+        }
+        while let Some(running) = self.cutscene.as_mut() {
+            let Some(step) = running.steps.front().cloned() else {
+                info!("STORY:cutscene {} finished", running.name);
+                bus.publish(CutsceneFinished {
+                    cutscene: running.name.clone(),
+                });
+                self.cutscene = None;
+                break;
+            };
+            let first_tick = !running.started;
+            running.started = true;
+            running.elapsed += dt;
+            let done = match step {
+                CutsceneStep::Dialogue { name } => {
+                    if first_tick && let Err(e) = self.start_dialogue(&name) {
+                        warn!("STORY:{}", e);
+                    }
+                    self.dialogue.is_none()
+                }
+                CutsceneStep::MoveEntity { entity, x, y, speed } => match find(world, &entity) {
+                    Some(id) => {
+                        let instance = world.get_mut(id).unwrap();
+                        let target = Vec2::new(x, y);
+                        let distance = instance.position.distance(target);
+                        let travel = speed * dt;
+                        instance.velocity = Vec2::ZERO;
+                        if distance <= travel {
+                            instance.position = target;
+                            true
+                        } else {
+                            instance.position += (target - instance.position) * (travel / distance);
+                            false
+                        }
+                    }
+                    None => true,
+                },
+                CutsceneStep::PlayAnimation { entity, state } => {
+                    if let Some(id) = find(world, &entity) {
+                        world.get_mut(id).unwrap().set_state(&state);
+                    }
+                    true
+                }
+                CutsceneStep::Wait { seconds } => running.elapsed >= seconds,
+                CutsceneStep::CameraPan { x, y, seconds } => {
+                    if first_tick {
+                        camera.pan_to(Vec2::new(x, y), seconds);
+                    }
+                    !camera.is_panning()
+                }
+                CutsceneStep::Fade {
+                    fade_out,
+                    seconds,
+                    color,
+                } => {
+                    if first_tick {
+                        let duration = Duration::from_secs_f32(seconds.max(0.));
+                        // The fade out keeps the screen covered until removed.
+                        if let Some(fade) = self.fade.take() {
+                            post_process.remove(fade);
+                        }
+                        if fade_out {
+                            self.fade = Some(post_process.push(Fade::to_color(color, duration)));
+                        } else {
+                            post_process.push(Fade::from_color(color, duration));
+                        }
+                    }
+                    running.elapsed >= seconds
+                }
+            };
+            if !done {
+                break;
+            }
+            let running = self.cutscene.as_mut().unwrap();
+            running.steps.pop_front();
+            running.started = false;
+            running.elapsed = 0.;
+        }
+        self.is_active()
+    }
+}
+
+/// First entity of a kind.
+fn find(world: &World, kind: &str) -> Option<EntityId> {
+    let found = world.iter().find(|entity| entity.kind == kind).map(|entity| entity.id);
+    if found.is_none() {
+        warn!("STORY:no entity of kind {}", kind);
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::post_process::Scanlines;
+
+    struct Scene {
+        world: World,
+        camera: Camera,
+        post_process: PostProcess,
+        input: InputActions,
+        bus: EventBus,
+    }
+
+    impl Scene {
+        fn new() -> Self {
+            Self {
+                world: World::new(),
+                camera: Camera::new(),
+                post_process: PostProcess::new(),
+                input: InputActions::new(),
+                bus: EventBus::new(),
+            }
+        }
+
+        fn update(&mut self, player: &mut CutscenePlayer, dt: f32) -> bool {
+            let active = player.update(
+                &mut self.world,
+                &mut self.camera,
+                &mut self.post_process,
+                &self.input,
+                &mut self.bus,
+                dt,
+            );
+            self.bus.dispatch();
+            active
+        }
+    }
+
+    fn player_with(name: &str, steps: Vec<CutsceneStep>) -> CutscenePlayer {
+        let mut player = CutscenePlayer::new();
+        player.library.insert(name, StoryAsset::Cutscene(Cutscene { steps }));
+        player.start_cutscene(name).unwrap();
+        player
+    }
+
+    #[test]
+    fn wait_lasts_its_duration() {
+        let mut player = player_with("pause", vec![CutsceneStep::Wait { seconds: 0.5 }]);
+        let mut scene = Scene::new();
+        let mut updates = 1;
+        while scene.update(&mut player, 0.1) {
+            assert!(scene.bus.read::<CutsceneFinished>().is_empty());
+            updates += 1;
+        }
+        assert_eq!(updates, 5);
+        assert_eq!(
+            scene.bus.read::<CutsceneFinished>(),
+            [CutsceneFinished {
+                cutscene: String::from("pause")
+            }]
+        );
+    }
+
+    #[test]
+    fn stop_removes_the_cutscene_fade() {
+        let mut player = player_with(
+            "night",
+            vec![
+                CutsceneStep::Fade {
+                    fade_out: true,
+                    seconds: 0.,
+                    color: [0, 0, 0],
+                },
+                CutsceneStep::Wait { seconds: 10. },
+            ],
+        );
+        let mut scene = Scene::new();
+        assert!(scene.update(&mut player, 0.1));
+        scene.post_process.update(Duration::from_secs(1));
+        let mut pixels = [100, 100, 100, 255];
+        scene.post_process.apply(&mut pixels, 1, 1);
+        assert_eq!(pixels, [0, 0, 0, 255]);
+
+        player.stop(&mut scene.post_process);
+        assert!(!player.is_active());
+        let mut pixels = [100, 100, 100, 255];
+        scene.post_process.apply(&mut pixels, 1, 1);
+        assert_eq!(pixels, [100, 100, 100, 255]);
+    }
+
+    #[test]
+    fn fade_in_removes_only_the_cutscene_fade() {
+        let mut player = CutscenePlayer::new();
+        let fade = |fade_out| CutsceneStep::Fade {
+            fade_out,
+            seconds: 0.,
+            color: [0, 0, 0],
+        };
+        player.library.insert(
+            "night",
+            StoryAsset::Cutscene(Cutscene {
+                steps: vec![fade(true), fade(false)],
+            }),
+        );
+        let (mut world, mut camera, input) = (World::new(), Camera::new(), InputActions::new());
+        let (mut post_process, mut bus) = (PostProcess::new(), EventBus::new());
+        post_process.push(Scanlines::new(0.5));
+
+        player.start_cutscene("night").unwrap();
+        player.update(&mut world, &mut camera, &mut post_process, &input, &mut bus, 0.1);
+        assert!(!player.is_active());
+        post_process.update(Duration::from_secs(1));
+        let mut pixels = [[100, 100, 100, 255], [100, 100, 100, 255]].concat();
+        post_process.apply(&mut pixels, 1, 2);
+        assert_eq!(pixels, [[100, 100, 100, 255], [50, 50, 50, 255]].concat());
+    }
+}
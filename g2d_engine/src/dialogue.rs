@@ -0,0 +1,367 @@
+use crate::assets::Assets;
+use crate::event::bus::EventBus;
+use crate::input::InputActions;
+use egui::{Align2, ColorImage, Context, Id, RichText, TextureHandle, TextureOptions};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Branching conversation, a graph of lines identified by name.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Dialogue {
+    /// Name of the first line.
+    pub start: String,
+    pub lines: BTreeMap<String, DialogueLine>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DialogueLine {
+    pub speaker: String,
+    /// Image of the speaker, relative to the resources directory.
+    pub portrait: Option<String>,
+    pub text: String,
+    /// Answers offered once the text is shown. The line `next` is ignored when there are some.
+    pub choices: Vec<DialogueChoice>,
+    /// Line shown after this one, the dialogue ends if `None`.
+    pub next: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct DialogueChoice {
+    pub text: String,
+    pub next: Option<String>,
+}
+
+impl Dialogue {
+    /// Check that every line referenced exists.
+    pub fn validate(&self) -> Result<(), String> {
+        let missing = |name: &String| !self.lines.contains_key(name);
+        if missing(&self.start) {
+            return Err(format!("start line {} does not exist", self.start));
+        }
+        for (name, line) in self.lines.iter() {
+            let targets = line.next.iter().chain(line.choices.iter().filter_map(|choice| choice.next.as_ref()));
+            if let Some(target) = targets.into_iter().find(|target| missing(target)) {
+                return Err(format!("line {} leads to unknown line {}", name, target));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Tuning of the dialogue boxes.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DialogueConfig {
+    /// Typewriter speed.
+    pub chars_per_second: f32,
+    /// Action showing the whole line, then going to the next one.
+    pub advance: String,
+    pub previous_choice: String,
+    pub next_choice: String,
+}
+
+impl Default for DialogueConfig {
+    fn default() -> Self {
+        Self {
+            chars_per_second: 40.,
+            advance: String::from("jump"),
+            previous_choice: String::from("up"),
+            next_choice: String::from("down"),
+        }
+    }
+}
+
+/// What the dialogue box shows, published every tick while a dialogue runs.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DialogueView {
+    pub dialogue: String,
+    pub speaker: String,
+    pub portrait: Option<String>,
+    /// Part of the text already typed.
+    pub text: String,
+    /// Offered once the whole text is typed.
+    pub choices: Vec<String>,
+    pub selected: usize,
+}
+
+impl DialogueView {
+    /// Draw the dialogue box with egui, at the bottom of the screen. The portrait is read from
+    /// `assets` the first time it is shown.
+    pub fn ui(&self, ctx: &Context, assets: &mut Assets) {
+        let portrait = self.portrait.as_ref().and_then(|name| portrait_texture(ctx, assets, name));
+        egui::Window::new("dialogue")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_BOTTOM, [0., -16.])
+            .show(ctx, |ui| {
+                ui.set_min_width(480.);
+                ui.horizontal(|ui| {
+                    if let Some(texture) = portrait.as_ref() {
+                        ui.add(egui::Image::new(texture).fit_to_exact_size(egui::vec2(PORTRAIT_SIZE, PORTRAIT_SIZE)));
+                    }
+                    if !self.speaker.is_empty() {
+                        ui.label(RichText::new(&self.speaker).strong());
+                    }
+                });
+                ui.label(&self.text);
+                for (i, choice) in self.choices.iter().enumerate() {
+                    let marker = if i == self.selected { "▶" } else { "  " };
+                    ui.label(format!("{} {}", marker, choice));
+                }
+            });
+    }
+}
+
+/// Side of the square the portraits are drawn in, in points.
+const PORTRAIT_SIZE: f32 = 64.;
+
+/// Texture of a portrait, kept in the egui memory. An image that failed to load is not retried.
+fn portrait_texture(ctx: &Context, assets: &mut Assets, name: &str) -> Option<TextureHandle> {
+    let id = Id::new(("dialogue portrait", name));
+    if let Some(texture) = ctx.data(|data| data.get_temp::<Option<TextureHandle>>(id)) {
+        return texture;
+    }
+    let texture = match assets.load_image(name) {
+        Ok(image) => {
+            let size = [image.width() as usize, image.height() as usize];
+            let image = ColorImage::from_rgba_unmultiplied(size, image.as_raw());
+            Some(ctx.load_texture(name, image, TextureOptions::NEAREST))
+        }
+        Err(e) => {
+            warn!("DIALOGUE:portrait {} not loaded : {}", name, e);
+            None
+        }
+    };
+    ctx.data_mut(|data| data.insert_temp(id, texture.clone()));
+    texture
+}
+
+/// Published when the player picks an answer.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DialogueChosen {
+    pub dialogue: String,
+    pub line: String,
+    pub choice: usize,
+}
+
+/// Published when a dialogue ends.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DialogueFinished {
+    pub dialogue: String,
+}
+
+/// Plays a dialogue: types the lines, then waits for the player to go on or choose an answer.
+#[derive(PartialEq, Debug, Clone)]
+pub struct DialogueRunner {
+    name: String,
+    dialogue: Dialogue,
+    line: Option<String>,
+    typed: f32,
+    selected: usize,
+}
+
+impl DialogueRunner {
+    pub fn new(name: &str, dialogue: Dialogue) -> Result<Self, String> {
+        dialogue.validate()?;
+        Ok(Self {
+            name: String::from(name),
+            line: Some(dialogue.start.clone()),
+            dialogue,
+            typed: 0.,
+            selected: 0,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.line.is_none()
+    }
+
+    fn current(&self) -> Option<&DialogueLine> {
+        self.line.as_ref().map(|name| &self.dialogue.lines[name])
+    }
+
+    pub fn view(&self) -> Option<DialogueView> {
+        let line = self.current()?;
+        let typed = self.typed as usize;
+        let complete = typed >= line.text.chars().count();
+        Some(DialogueView {
+            dialogue: self.name.clone(),
+            speaker: line.speaker.clone(),
+            portrait: line.portrait.clone(),
+            text: line.text.chars().take(typed).collect(),
+            choices: match complete {
+                true => line.choices.iter().map(|choice| choice.text.clone()).collect(),
+                false => Vec::new(),
+            },
+            selected: self.selected,
+        })
+    }
+
+    fn go_to(&mut self, line: Option<String>, bus: &mut EventBus) {
+        self.line = line;
+        self.typed = 0.;
+        self.selected = 0;
+        if self.line.is_none() {
+            info!("DIALOGUE:{} finished", self.name);
+            bus.publish(DialogueFinished {
+                dialogue: self.name.clone(),
+            });
+        }
+    }
+
+    pub fn update(&mut self, input: &InputActions, config: &DialogueConfig, bus: &mut EventBus, dt: f32) {
+        let Some(line) = self.current().cloned() else {
+            return;
+        };
+        let length = line.text.chars().count() as f32;
+        let complete = self.typed >= length;
+        if !complete {
+            self.typed = (self.typed + config.chars_per_second * dt).min(length);
+            if input.is_pressed(&config.advance) {
+                self.typed = length;
+            }
+        } else if !line.choices.is_empty() {
+            let count = line.choices.len();
+            if input.is_pressed(&config.previous_choice) {
+                self.selected = (self.selected + count - 1) % count;
+            }
+            if input.is_pressed(&config.next_choice) {
+                self.selected = (self.selected + 1) % count;
+            }
+            if input.is_pressed(&config.advance) {
+                bus.publish(DialogueChosen {
+                    dialogue: self.name.clone(),
+                    line: self.line.clone().unwrap(),
+                    choice: self.selected,
+                });
+                let next = line.choices[self.selected].next.clone();
+                self.go_to(next, bus);
+            }
+        } else if input.is_pressed(&config.advance) {
+            self.go_to(line.next.clone(), bus);
+        }
+        if let Some(view) = self.view() {
+            bus.publish(view);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    fn line(text: &str, choices: &[(&str, &str)], next: Option<&str>) -> DialogueLine {
+        DialogueLine {
+            speaker: String::from("Gwen"),
+            text: String::from(text),
+            choices: choices
+                .iter()
+                .map(|(text, next)| DialogueChoice {
+                    text: String::from(*text),
+                    next: Some(String::from(*next)),
+                })
+                .collect(),
+            next: next.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    fn runner() -> DialogueRunner {
+        let dialogue = Dialogue {
+            start: String::from("question"),
+            lines: BTreeMap::from([
+                (String::from("question"), line("On y va ?", &[("Oui", "oui"), ("Non", "non")], None)),
+                (String::from("oui"), line("En route.", &[], None)),
+                (String::from("non"), line("Tant pis.", &[], Some("fin"))),
+                (String::from("fin"), line("Au revoir.", &[], None)),
+            ]),
+        };
+        DialogueRunner::new("depart", dialogue).unwrap()
+    }
+
+    /// One tick with the actions pressed.
+    fn tick(runner: &mut DialogueRunner, bus: &mut EventBus, pressed: &[&str], dt: f32) {
+        let mut input = InputActions::new();
+        for action in pressed {
+            input.press(action);
+        }
+        runner.update(&input, &DialogueConfig::default(), bus, dt);
+        bus.dispatch();
+    }
+
+    #[test]
+    fn text_is_typed_and_advance_skips_the_typing() {
+        let mut runner = runner();
+        let mut bus = EventBus::new();
+        // 40 characters per second.
+        tick(&mut runner, &mut bus, &[], 0.1);
+        let view = runner.view().unwrap();
+        assert_eq!(view.text, "On y");
+        assert!(view.choices.is_empty(), "the choices wait for the whole text");
+        assert_eq!(bus.read::<DialogueView>(), [view]);
+
+        tick(&mut runner, &mut bus, &["jump"], 0.);
+        let view = runner.view().unwrap();
+        assert_eq!(view.text, "On y va ?");
+        assert_eq!(view.choices, ["Oui", "Non"]);
+        assert!(bus.read::<DialogueChosen>().is_empty(), "skipping the typing chooses nothing");
+    }
+
+    #[test]
+    fn choice_leads_to_its_branch() {
+        let mut runner = runner();
+        let mut bus = EventBus::new();
+        tick(&mut runner, &mut bus, &["jump"], 0.);
+        tick(&mut runner, &mut bus, &["down"], 0.);
+        assert_eq!(runner.view().unwrap().selected, 1);
+        tick(&mut runner, &mut bus, &["down"], 0.);
+        tick(&mut runner, &mut bus, &["up"], 0.);
+        tick(&mut runner, &mut bus, &["jump"], 0.);
+        assert_eq!(
+            bus.read::<DialogueChosen>(),
+            [DialogueChosen {
+                dialogue: String::from("depart"),
+                line: String::from("question"),
+                choice: 1,
+            }]
+        );
+        assert_eq!(runner.view().unwrap().text, "");
+
+        tick(&mut runner, &mut bus, &[], 1.);
+        assert_eq!(runner.view().unwrap().text, "Tant pis.");
+        tick(&mut runner, &mut bus, &["jump"], 0.);
+        tick(&mut runner, &mut bus, &[], 1.);
+        assert_eq!(runner.view().unwrap().text, "Au revoir.");
+        tick(&mut runner, &mut bus, &["jump"], 0.);
+        assert!(runner.is_finished());
+        assert_eq!(
+            bus.read::<DialogueFinished>(),
+            [DialogueFinished {
+                dialogue: String::from("depart")
+            }]
+        );
+    }
+
+    #[test]
+    fn portrait_is_loaded_once_from_the_assets() {
+        let dir = std::env::temp_dir().join(format!("g2d_dialogue_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(4, 2, Rgba([255, 0, 0, 255])).save(dir.join("hero.png")).unwrap();
+        let mut assets = Assets::new(&dir);
+        let ctx = Context::default();
+
+        let texture = portrait_texture(&ctx, &mut assets, "hero.png").unwrap();
+        assert_eq!(texture.size(), [4, 2]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let cached = portrait_texture(&ctx, &mut assets, "hero.png").unwrap();
+        assert_eq!(cached.id(), texture.id());
+        assert!(portrait_texture(&ctx, &mut assets, "missing.png").is_none());
+    }
+}
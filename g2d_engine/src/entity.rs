@@ -0,0 +1,3 @@
+/// Identifier of a runtime entity.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct EntityId(pub u32);
@@ -0,0 +1,88 @@
+use gwen2d_project::db::engine_db::DbError;
+use image::ImageError;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use winit::error::{EventLoopError, OsError};
+
+/// Failure of the engine, reported to the game instead of panicking.
+#[derive(Debug)]
+pub enum G2dError {
+    /// The event loop or the window could not be created.
+    Window(String),
+    /// The GPU surface could not be created, resized or drawn.
+    Render(pixels::Error),
+    /// An image of the game could not be loaded.
+    Asset { path: PathBuf, source: ImageError },
+    /// The engine was given invalid settings.
+    Config(String),
+    /// The project database could not be read.
+    Database(DbError),
+}
+
+impl G2dError {
+    pub fn asset(path: impl Into<PathBuf>, source: ImageError) -> Self {
+        G2dError::Asset {
+            path: path.into(),
+            source,
+        }
+    }
+}
+
+impl Display for G2dError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            G2dError::Window(reason) => write!(f, "window error : {}", reason),
+            G2dError::Render(e) => write!(f, "render error : {}", e),
+            G2dError::Asset { path, source } => write!(f, "cannot load {} : {}", path.display(), source),
+            G2dError::Config(reason) => write!(f, "invalid configuration : {}", reason),
+            G2dError::Database(e) => write!(f, "database error : {}", e),
+        }
+    }
+}
+
+impl std::error::Error for G2dError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            G2dError::Render(e) => Some(e),
+            G2dError::Asset { source, .. } => Some(source),
+            G2dError::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<pixels::Error> for G2dError {
+    fn from(e: pixels::Error) -> Self {
+        G2dError::Render(e)
+    }
+}
+
+impl From<pixels::TextureError> for G2dError {
+    fn from(e: pixels::TextureError) -> Self {
+        G2dError::Render(e.into())
+    }
+}
+
+impl From<EventLoopError> for G2dError {
+    fn from(e: EventLoopError) -> Self {
+        G2dError::Window(e.to_string())
+    }
+}
+
+impl From<OsError> for G2dError {
+    fn from(e: OsError) -> Self {
+        G2dError::Window(e.to_string())
+    }
+}
+
+impl From<DbError> for G2dError {
+    fn from(e: DbError) -> Self {
+        G2dError::Database(e)
+    }
+}
+
+impl From<sqlx::Error> for G2dError {
+    fn from(e: sqlx::Error) -> Self {
+        G2dError::Database(e.into())
+    }
+}
@@ -0,0 +1,184 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+/// Handle returned by [`EventBus::subscribe`], used to unsubscribe.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct SubscriptionId(u64);
+
+type Handler<E> = Box<dyn FnMut(&E)>;
+
+struct Channel<E> {
+    pending: Vec<E>,
+    delivered: Vec<E>,
+    subscribers: Vec<(SubscriptionId, Handler<E>)>,
+}
+
+impl<E> Channel<E> {
+    fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            delivered: Vec::new(),
+            subscribers: Vec::new(),
+        }
+    }
+}
+
+trait AnyChannel {
+    fn dispatch(&mut self);
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<E: 'static> AnyChannel for Channel<E> {
+    fn dispatch(&mut self) {
+        self.delivered = std::mem::take(&mut self.pending);
+        for event in self.delivered.iter() {
+            for (_, handler) in self.subscribers.iter_mut() {
+                handler(event);
+            }
+        }
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let count = self.subscribers.len();
+        self.subscribers.retain(|(subscription, _)| *subscription != id);
+        count != self.subscribers.len()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Typed publish/subscribe bus.
+///
+/// Events published during a tick are queued and delivered all at once by [`EventBus::dispatch`],
+/// which the engine calls once per update. Delivery is deterministic: event types are processed
+/// in the order they were first used, events in publish order and subscribers in subscription
+/// order. Delivered events stay readable through [`EventBus::read`] until the next dispatch, for
+/// listeners polling the bus rather than registering a handler.
+#[derive(Default)]
+pub struct EventBus {
+    tick: u64,
+    next_subscription: u64,
+    index: HashMap<TypeId, usize>,
+    channels: Vec<Box<dyn AnyChannel>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of dispatches done so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Queue an event, delivered on the next dispatch.
+    pub fn publish<E: 'static>(&mut self, event: E) {
+        self.channel_mut::<E>().pending.push(event);
+    }
+
+    /// Register a handler called for every delivered event of type `E`.
+    pub fn subscribe<E: 'static>(&mut self, handler: impl FnMut(&E) + 'static) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription);
+        self.next_subscription += 1;
+        self.channel_mut::<E>().subscribers.push((id, Box::new(handler)));
+        id
+    }
+
+    /// Remove a handler, returns `false` if it was not registered.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.channels.iter_mut().any(|channel| channel.unsubscribe(id))
+    }
+
+    /// Events of type `E` delivered by the last dispatch.
+    pub fn read<E: 'static>(&self) -> &[E] {
+        self.index
+            .get(&TypeId::of::<E>())
+            .and_then(|i| self.channels[*i].as_any().downcast_ref::<Channel<E>>())
+            .map(|channel| channel.delivered.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Deliver all queued events and start a new tick.
+    pub fn dispatch(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.dispatch();
+        }
+        self.tick += 1;
+    }
+
+    fn channel_mut<E: 'static>(&mut self) -> &mut Channel<E> {
+        let channels = &mut self.channels;
+        let i = *self.index.entry(TypeId::of::<E>()).or_insert_with(|| {
+            channels.push(Box::new(Channel::<E>::new()));
+            channels.len() - 1
+        });
+        self.channels[i]
+            .as_any_mut()
+            .downcast_mut::<Channel<E>>()
+            .expect("event channel type mismatch")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn events_are_delivered_on_dispatch_in_publish_order() {
+        let mut bus = EventBus::new();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        for subscriber in ["a", "b"] {
+            let received = received.clone();
+            bus.subscribe(move |event: &u32| received.borrow_mut().push((subscriber, *event)));
+        }
+        bus.publish(1u32);
+        bus.publish(2u32);
+        assert!(received.borrow().is_empty(), "events wait for the dispatch");
+        bus.dispatch();
+        assert_eq!(*received.borrow(), [("a", 1), ("b", 1), ("a", 2), ("b", 2)]);
+        assert_eq!(bus.read::<u32>(), [1, 2]);
+        assert_eq!(bus.tick(), 1);
+
+        bus.dispatch();
+        assert!(bus.read::<u32>().is_empty());
+        assert_eq!(received.borrow().len(), 4);
+    }
+
+    #[test]
+    fn event_types_are_dispatched_in_first_use_order() {
+        let mut bus = EventBus::new();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let log = received.clone();
+        bus.subscribe(move |event: &&str| log.borrow_mut().push(event.to_string()));
+        let log = received.clone();
+        bus.subscribe(move |event: &u32| log.borrow_mut().push(event.to_string()));
+        bus.publish(7u32);
+        bus.publish("first");
+        bus.dispatch();
+        assert_eq!(*received.borrow(), ["first", "7"]);
+    }
+
+    #[test]
+    fn unsubscribed_handlers_are_not_called() {
+        let mut bus = EventBus::new();
+        let count = Rc::new(RefCell::new(0));
+        let counter = count.clone();
+        let id = bus.subscribe(move |_: &u32| *counter.borrow_mut() += 1);
+        assert!(bus.unsubscribe(id));
+        assert!(!bus.unsubscribe(id));
+        bus.publish(1u32);
+        bus.dispatch();
+        assert_eq!(*count.borrow(), 0);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::geometry::Rect;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Identifier of a trigger zone.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct TriggerId(pub u32);
+
+/// Area of the level reacting to entities entering it (door, checkpoint, death pit...).
+#[derive(PartialEq, Debug, Clone)]
+pub struct TriggerZone {
+    pub area: Rect,
+    /// Free form tag telling gameplay code what the zone is for.
+    pub tag: String,
+    pub enabled: bool,
+}
+
+impl TriggerZone {
+    pub fn new(area: Rect, tag: &str) -> Self {
+        Self {
+            area,
+            tag: String::from(tag),
+            enabled: true,
+        }
+    }
+}
+
+/// Contact between a trigger zone and an entity.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TriggerContact {
+    pub trigger: TriggerId,
+    pub tag: String,
+    pub entity: EntityId,
+}
+
+/// Event published on the bus by [`Triggers::update`].
+#[derive(PartialEq, Debug, Clone)]
+pub enum TriggerEvent {
+    Enter(TriggerContact),
+    Stay(TriggerContact),
+    Exit(TriggerContact),
+}
+
+impl TriggerEvent {
+    pub fn contact(&self) -> &TriggerContact {
+        match self {
+            TriggerEvent::Enter(contact) | TriggerEvent::Stay(contact) | TriggerEvent::Exit(contact) => contact,
+        }
+    }
+}
+
+/// Set of trigger zones and the entities currently overlapping them.
+#[derive(Default)]
+pub struct Triggers {
+    next_id: u32,
+    zones: BTreeMap<TriggerId, TriggerZone>,
+    contacts: BTreeSet<(TriggerId, EntityId)>,
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Id given to the next added zone, ids only grow.
+    pub fn next_id(&self) -> TriggerId {
+        TriggerId(self.next_id)
+    }
+
+    pub fn add(&mut self, zone: TriggerZone) -> TriggerId {
+        let id = TriggerId(self.next_id);
+        self.next_id += 1;
+        self.zones.insert(id, zone);
+        id
+    }
+
+    /// Remove a zone, entities inside it do not receive an exit event.
+    pub fn remove(&mut self, id: TriggerId) -> Option<TriggerZone> {
+        self.contacts.retain(|(trigger, _)| *trigger != id);
+        self.zones.remove(&id)
+    }
+
+    pub fn get_mut(&mut self, id: TriggerId) -> Option<&mut TriggerZone> {
+        self.zones.get_mut(&id)
+    }
+
+    pub fn ids(&self) -> Vec<TriggerId> {
+        self.zones.keys().copied().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.zones.clear();
+        self.contacts.clear();
+    }
+
+    /// Test the entity colliders against every enabled zone and publish the matching
+    /// [`TriggerEvent`]s: exits first, then enters, then stays, each sorted by trigger then entity.
+    pub fn update(&mut self, colliders: impl IntoIterator<Item = (EntityId, Rect)>, bus: &mut EventBus) {
+        let colliders: Vec<(EntityId, Rect)> = colliders.into_iter().collect();
+        let mut contacts = BTreeSet::new();
+        for (id, zone) in self.zones.iter().filter(|(_, zone)| zone.enabled) {
+            for (entity, collider) in colliders.iter() {
+                if zone.area.intersects(collider) {
+                    contacts.insert((*id, *entity));
+                }
+            }
+        }
+        let previous = std::mem::replace(&mut self.contacts, contacts);
+        for (trigger, entity) in previous.difference(&self.contacts) {
+            bus.publish(TriggerEvent::Exit(self.contact(*trigger, *entity)));
+        }
+        for (trigger, entity) in self.contacts.difference(&previous) {
+            bus.publish(TriggerEvent::Enter(self.contact(*trigger, *entity)));
+        }
+        for (trigger, entity) in self.contacts.intersection(&previous) {
+            bus.publish(TriggerEvent::Stay(self.contact(*trigger, *entity)));
+        }
+    }
+
+    fn contact(&self, trigger: TriggerId, entity: EntityId) -> TriggerContact {
+        TriggerContact {
+            trigger,
+            tag: self.zones.get(&trigger).map(|zone| zone.tag.clone()).unwrap_or_default(),
+            entity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYER: EntityId = EntityId(1);
+
+    fn tick(triggers: &mut Triggers, bus: &mut EventBus, colliders: &[(EntityId, Rect)]) -> Vec<TriggerEvent> {
+        triggers.update(colliders.iter().copied(), bus);
+        bus.dispatch();
+        bus.read::<TriggerEvent>().to_vec()
+    }
+
+    #[test]
+    fn enter_stay_exit_across_three_ticks() {
+        let mut bus = EventBus::new();
+        let mut triggers = Triggers::new();
+        let door = triggers.add(TriggerZone::new(Rect::new(0., 0., 10., 10.), "door"));
+        let contact = TriggerContact {
+            trigger: door,
+            tag: String::from("door"),
+            entity: PLAYER,
+        };
+        let inside = [(PLAYER, Rect::new(2., 2., 4., 4.))];
+        let outside = [(PLAYER, Rect::new(20., 2., 4., 4.))];
+        assert_eq!(tick(&mut triggers, &mut bus, &inside), [TriggerEvent::Enter(contact.clone())]);
+        assert_eq!(tick(&mut triggers, &mut bus, &inside), [TriggerEvent::Stay(contact.clone())]);
+        assert_eq!(tick(&mut triggers, &mut bus, &outside), [TriggerEvent::Exit(contact)]);
+        assert!(tick(&mut triggers, &mut bus, &outside).is_empty());
+    }
+
+    #[test]
+    fn exits_come_before_enters_then_stays() {
+        let mut bus = EventBus::new();
+        let mut triggers = Triggers::new();
+        let left = triggers.add(TriggerZone::new(Rect::new(0., 0., 10., 10.), "left"));
+        let right = triggers.add(TriggerZone::new(Rect::new(10., 0., 10., 10.), "right"));
+        let other = EntityId(2);
+        tick(&mut triggers, &mut bus, &[(PLAYER, Rect::new(2., 2., 2., 2.)), (other, Rect::new(12., 2., 2., 2.))]);
+        let events = tick(
+            &mut triggers,
+            &mut bus,
+            &[(PLAYER, Rect::new(14., 2., 2., 2.)), (other, Rect::new(12., 2., 2., 2.))],
+        );
+        let kinds: Vec<(&str, TriggerId, EntityId)> = events
+            .iter()
+            .map(|event| {
+                let kind = match event {
+                    TriggerEvent::Enter(_) => "enter",
+                    TriggerEvent::Stay(_) => "stay",
+                    TriggerEvent::Exit(_) => "exit",
+                };
+                (kind, event.contact().trigger, event.contact().entity)
+            })
+            .collect();
+        assert_eq!(kinds, [("exit", left, PLAYER), ("enter", right, PLAYER), ("stay", right, other)]);
+    }
+
+    #[test]
+    fn disabled_zones_are_ignored() {
+        let mut bus = EventBus::new();
+        let mut triggers = Triggers::new();
+        let id = triggers.add(TriggerZone::new(Rect::new(0., 0., 10., 10.), "pit"));
+        triggers.get_mut(id).unwrap().enabled = false;
+        assert!(tick(&mut triggers, &mut bus, &[(PLAYER, Rect::new(2., 2., 4., 4.))]).is_empty());
+    }
+}
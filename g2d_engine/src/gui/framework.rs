@@ -1,3 +1,4 @@
+use crate::event::bus::EventBus;
 use crate::gui::gui::Gui;
 use egui::{ClippedPrimitive, Context, TexturesDelta, ViewportId};
 use egui_wgpu::{Renderer, ScreenDescriptor};
@@ -68,6 +69,11 @@ impl Framework {
         self.screen_descriptor.pixels_per_point = scale_factor as f32;
     }
 
+    /// Let the game gui read the events of the tick.
+    pub(crate) fn on_events(&mut self, events: &EventBus) {
+        self.gui.on_events(events);
+    }
+
     /// Prepare egui.
     pub(crate) fn prepare(&mut self, window: &Window) {
         // Run the egui frame and create all paint jobs to prepare for rendering.
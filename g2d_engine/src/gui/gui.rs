@@ -1,5 +1,9 @@
+use crate::event::bus::EventBus;
 use egui::Context;
 
 pub trait Gui {
     fn ui(&mut self, ctx: &Context);
+
+    /// Called once per tick, after the engine dispatched the events of the tick.
+    fn on_events(&mut self, _events: &EventBus) {}
 }
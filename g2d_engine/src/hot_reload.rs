@@ -0,0 +1,90 @@
+use crate::script::SCRIPT_EXTENSION;
+use log::{error, info};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+
+/// Time without new change on a file before it is reloaded, editors often write in several steps.
+const DEBOUNCE: Duration = Duration::from_millis(150);
+
+/// Kind of asset behind a changed file.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub enum AssetKind {
+    Image,
+    Project,
+    Script,
+}
+
+impl AssetKind {
+    pub fn from_path(path: &Path) -> Option<AssetKind> {
+        let extension = path.extension()?.to_string_lossy().to_lowercase();
+        match extension.as_str() {
+            "png" | "jpg" | "jpeg" | "bmp" | "gif" => Some(AssetKind::Image),
+            "db" | "sqlite" => Some(AssetKind::Project),
+            ext if ext == SCRIPT_EXTENSION => Some(AssetKind::Script),
+            _ => None,
+        }
+    }
+}
+
+/// Event published on the bus once an asset has been reloaded.
+#[derive(PartialEq, Debug, Clone)]
+pub struct AssetReloaded {
+    pub kind: AssetKind,
+    pub path: PathBuf,
+}
+
+/// Watches asset directories during development and reports the changed files.
+pub struct HotReloader {
+    // Dropping the watcher stops the notifications.
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<notify::Event>>,
+    pending: BTreeMap<PathBuf, (AssetKind, Instant)>,
+}
+
+impl HotReloader {
+    pub fn watch(dirs: &[PathBuf]) -> notify::Result<Self> {
+        let (sender, receiver) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        for dir in dirs {
+            watcher.watch(dir, RecursiveMode::Recursive)?;
+            info!("HOT RELOAD:watching {}", dir.display());
+        }
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// Changed assets whose files have been stable for a while, sorted by kind then path.
+    pub fn poll(&mut self) -> Vec<(AssetKind, PathBuf)> {
+        let now = Instant::now();
+        for result in self.receiver.try_iter() {
+            match result {
+                Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                    for path in event.paths {
+                        if let Some(kind) = AssetKind::from_path(&path) {
+                            self.pending.insert(path, (kind, now));
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => error!("HOT RELOAD:watch error : {}", e),
+            }
+        }
+        let mut ready: Vec<(AssetKind, PathBuf)> = self
+            .pending
+            .iter()
+            .filter(|(_, (_, changed))| now.duration_since(*changed) >= DEBOUNCE)
+            .map(|(path, (kind, _))| (*kind, path.clone()))
+            .collect();
+        for (_, path) in ready.iter() {
+            self.pending.remove(path);
+        }
+        ready.sort();
+        ready
+    }
+}
@@ -0,0 +1,228 @@
+use crate::combat::{Damaged, Fighter};
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::pickup::{Collected, Inventory, Item};
+use crate::render::bitmap::{Canvas, PixelRect};
+use egui::{Align2, Color32, Context, RichText};
+use image::RgbaImage;
+use std::collections::BTreeSet;
+
+const TEXT_COLOR: [u8; 4] = [255, 255, 255, 255];
+
+/// Values shown by the HUD widgets.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct HudState {
+    pub coins: u32,
+    pub health: u32,
+    pub max_health: u32,
+    pub keys: BTreeSet<String>,
+}
+
+impl HudState {
+    /// Snapshot of a player inventory and health.
+    pub fn from_player(inventory: &Inventory, fighter: Option<&Fighter>) -> Self {
+        Self {
+            coins: inventory.coins(),
+            health: fighter.map_or(0, |fighter| fighter.health),
+            max_health: fighter.map_or(0, |fighter| fighter.max_health),
+            keys: inventory.keys().map(String::from).collect(),
+        }
+    }
+
+    /// Follow the pickups and damages of `player` from the events of the tick, for guis which
+    /// cannot read the engine state.
+    pub fn on_events(&mut self, events: &EventBus, player: EntityId) {
+        for collected in events.read::<Collected>().iter().filter(|c| c.collector == player) {
+            match &collected.item {
+                Item::Coin => self.coins += collected.amount,
+                Item::Key(name) => {
+                    self.keys.insert(name.clone());
+                }
+                Item::PowerUp(_) => {}
+            }
+        }
+        if let Some(damaged) = events.read::<Damaged>().iter().rev().find(|d| d.target == player) {
+            self.health = damaged.health;
+        }
+    }
+
+    /// Draw the widgets with egui, in the top left corner.
+    pub fn ui(&self, ctx: &Context) {
+        egui::Area::new("hud")
+            .anchor(Align2::LEFT_TOP, [8., 8.])
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    for i in 0..self.max_health {
+                        let color = if i < self.health { Color32::RED } else { Color32::DARK_GRAY };
+                        ui.label(RichText::new("♥").size(20.).color(color));
+                    }
+                });
+                ui.label(RichText::new(format!("● {}", self.coins)).size(18.).color(Color32::GOLD));
+                ui.horizontal(|ui| {
+                    for key in &self.keys {
+                        ui.label(RichText::new(format!("🔑 {}", key)).color(Color32::WHITE));
+                    }
+                });
+            });
+    }
+}
+
+/// Images of the bitmap HUD.
+pub struct HudIcons {
+    pub heart_full: RgbaImage,
+    pub heart_empty: RgbaImage,
+    pub coin: RgbaImage,
+    pub key: RgbaImage,
+}
+
+/// HUD drawn in the frame with the bitmap renderer: hearts, then the coin counter, then the keys.
+pub struct BitmapHud {
+    pub icons: HudIcons,
+    /// Distance to the frame borders and between the rows, in pixels.
+    pub margin: i32,
+    /// Size of a pixel of the digit font.
+    pub digit_scale: u32,
+}
+
+impl BitmapHud {
+    pub fn new(icons: HudIcons) -> Self {
+        Self {
+            icons,
+            margin: 8,
+            digit_scale: 3,
+        }
+    }
+
+    /// Frame area covered by the HUD for `state`.
+    pub fn area(&self, state: &HudState) -> PixelRect {
+        let margin = self.margin;
+        let row_width = |count: u32, icon: &RgbaImage| count as i32 * (icon.width() as i32 + margin / 2);
+        let digits = state.coins.to_string().len() as i32 * 4 * self.digit_scale as i32;
+        let width = row_width(state.max_health, &self.icons.heart_full)
+            .max(margin + self.icons.coin.width() as i32 + digits)
+            .max(row_width(state.keys.len() as u32, &self.icons.key));
+        let mut height = (self.icons.coin.height() as i32).max(5 * self.digit_scale as i32) + margin;
+        if state.max_health > 0 {
+            height += self.icons.heart_full.height() as i32 + margin;
+        }
+        if !state.keys.is_empty() {
+            height += self.icons.key.height() as i32;
+        }
+        PixelRect::new(margin, margin, width as u32, height as u32)
+    }
+
+    pub fn draw(&self, state: &HudState, canvas: &mut Canvas) {
+        let margin = self.margin;
+        let mut y = margin;
+
+        let mut x = margin;
+        for i in 0..state.max_health {
+            let heart = if i < state.health { &self.icons.heart_full } else { &self.icons.heart_empty };
+            canvas.blit(heart, x, y);
+            x += heart.width() as i32 + margin / 2;
+        }
+        if state.max_health > 0 {
+            y += self.icons.heart_full.height() as i32 + margin;
+        }
+
+        canvas.blit(&self.icons.coin, margin, y);
+        let digit_height = 5 * self.digit_scale as i32;
+        // Digits taller than the coin start at the top of the row, inside `area`.
+        let digit_y = y + (self.icons.coin.height() as i32 - digit_height).max(0) / 2;
+        let text_x = margin * 2 + self.icons.coin.width() as i32;
+        canvas.draw_number(state.coins, text_x, digit_y, self.digit_scale, TEXT_COLOR);
+        y += (self.icons.coin.height() as i32).max(digit_height) + margin;
+
+        let mut x = margin;
+        for _ in &state.keys {
+            canvas.blit(&self.icons.key, x, y);
+            x += self.icons.key.width() as i32 + margin / 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat::Damaged;
+    use crate::geometry::Vec2;
+    use image::Rgba;
+
+    fn collected(collector: u32, item: Item, amount: u32) -> Collected {
+        Collected {
+            collector: EntityId(collector),
+            item,
+            amount,
+            unique_id: None,
+        }
+    }
+
+    fn damaged(target: u32, health: u32) -> Damaged {
+        Damaged {
+            attacker: EntityId(9),
+            target: EntityId(target),
+            amount: 1,
+            health,
+            knockback: Vec2::ZERO,
+        }
+    }
+
+    #[test]
+    fn events_of_the_player_update_the_state() {
+        let mut state = HudState {
+            coins: 2,
+            health: 5,
+            max_health: 5,
+            keys: BTreeSet::new(),
+        };
+        let mut bus = EventBus::new();
+        bus.publish(collected(1, Item::Coin, 3));
+        bus.publish(collected(2, Item::Coin, 10));
+        bus.publish(collected(1, Item::Key(String::from("cave")), 1));
+        bus.publish(collected(1, Item::PowerUp(String::from("saut")), 1));
+        bus.publish(damaged(1, 4));
+        bus.publish(damaged(1, 3));
+        bus.publish(damaged(2, 0));
+        bus.dispatch();
+        state.on_events(&bus, EntityId(1));
+        assert_eq!(state.coins, 5);
+        assert_eq!(state.health, 3);
+        assert_eq!(state.keys, BTreeSet::from([String::from("cave")]));
+    }
+
+    #[test]
+    fn draw_stays_inside_the_area() {
+        let icon = |width, height| RgbaImage::from_pixel(width, height, Rgba([200, 0, 0, 255]));
+        let hud = BitmapHud::new(HudIcons {
+            heart_full: icon(6, 6),
+            heart_empty: icon(6, 6),
+            // Shorter than the digits.
+            coin: icon(4, 4),
+            key: icon(5, 8),
+        });
+        let keys = BTreeSet::from([String::from("cave"), String::from("tour")]);
+        // Without hearts, the coin row is the first one.
+        for (health, max_health) in [(2, 3), (0, 0)] {
+            let state = HudState {
+                coins: 1234,
+                health,
+                max_health,
+                keys: keys.clone(),
+            };
+            let (width, height) = (120, 80);
+            let mut frame = vec![0; (width * height * 4) as usize];
+            hud.draw(&state, &mut Canvas::new(&mut frame, width, height));
+            let area = hud.area(&state);
+            let mut drawn = 0;
+            for (i, pixel) in frame.chunks(4).enumerate() {
+                if pixel[3] != 0 {
+                    let (x, y) = ((i as u32 % width) as i32, (i as u32 / width) as i32);
+                    assert!(area.contains(x, y), "pixel {}, {} drawn outside {:?}", x, y, area);
+                    drawn += 1;
+                }
+            }
+            assert!(drawn > 0);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{BTreeSet, HashMap};
+use winit::keyboard::KeyCode;
+use winit_input_helper::WinitInputHelper;
+
+/// Named input actions ("left", "jump"...) bound to keys.
+pub struct InputActions {
+    bindings: HashMap<KeyCode, String>,
+    held: BTreeSet<String>,
+    pressed: BTreeSet<String>,
+}
+
+impl Default for InputActions {
+    fn default() -> Self {
+        let mut actions = Self {
+            bindings: HashMap::new(),
+            held: BTreeSet::new(),
+            pressed: BTreeSet::new(),
+        };
+        actions.bind(KeyCode::ArrowLeft, "left");
+        actions.bind(KeyCode::ArrowRight, "right");
+        actions.bind(KeyCode::ArrowUp, "up");
+        actions.bind(KeyCode::ArrowDown, "down");
+        actions.bind(KeyCode::Space, "jump");
+        actions.bind(KeyCode::KeyX, "attack");
+        actions
+    }
+}
+
+impl InputActions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bind(&mut self, key: KeyCode, action: &str) {
+        self.bindings.insert(key, String::from(action));
+    }
+
+    pub fn unbind(&mut self, key: KeyCode) {
+        self.bindings.remove(&key);
+    }
+
+    /// Refresh the action states from the keyboard.
+    pub fn update(&mut self, input: &WinitInputHelper) {
+        self.held.clear();
+        self.pressed.clear();
+        for (key, action) in self.bindings.iter() {
+            if input.key_held(*key) {
+                self.held.insert(action.clone());
+            }
+            if input.key_pressed(*key) {
+                self.pressed.insert(action.clone());
+            }
+        }
+    }
+
+    /// Force an action state, for scripted input or replays.
+    pub fn set_held(&mut self, action: &str, held: bool) {
+        if held {
+            self.held.insert(String::from(action));
+        } else {
+            self.held.remove(action);
+        }
+    }
+
+    /// Force a press of an action, until the next refresh, for scripted input or replays.
+    pub fn press(&mut self, action: &str) {
+        self.held.insert(String::from(action));
+        self.pressed.insert(String::from(action));
+    }
+
+    /// Release every action, for the ticks where gameplay input is blocked.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+        self.pressed.clear();
+    }
+
+    /// Actions whose key is down.
+    pub fn held(&self) -> &BTreeSet<String> {
+        &self.held
+    }
+
+    /// Actions whose key went down during this tick.
+    pub fn pressed(&self) -> &BTreeSet<String> {
+        &self.pressed
+    }
+
+    /// The action key is down.
+    pub fn is_held(&self, action: &str) -> bool {
+        self.held.contains(action)
+    }
+
+    /// The action key went down during this tick.
+    pub fn is_pressed(&self, action: &str) -> bool {
+        self.pressed.contains(action)
+    }
+}
@@ -0,0 +1,594 @@
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::event::trigger::{TriggerEvent, TriggerId, TriggerZone, Triggers};
+use crate::geometry::{Rect, Vec2};
+use crate::render::post_process::{Color, EffectId, Fade, PostProcess};
+use crate::tilemap::TileMap;
+use crate::world::World;
+use gwen2d_project::db::engine_db::{self, DbError};
+use gwen2d_project::db::project_repository;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+
+/// Trigger zones tagged `door:<index>` are the doors of the current level.
+pub const DOOR_TAG_PREFIX: &str = "door:";
+
+/// Spawn point used when entering a level without naming one.
+pub const DEFAULT_SPAWN: &str = "start";
+
+/// Identifier of a level, the primary key of the project `level` table.
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct LevelId(pub u32);
+
+/// Entity placed in a level by the editor, spawned when the level loads.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Placement {
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Area leading to a spawn point of another level.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Door {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub target_level: LevelId,
+    pub target_spawn: String,
+}
+
+/// Content of a level, stored as JSON in the `data` column of the project `level` table.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct LevelData {
+    pub name: String,
+    pub tile_size: f32,
+    /// Collision map, one string per row with `#` for solid tiles.
+    pub tiles: Vec<String>,
+    pub placements: Vec<Placement>,
+    /// Player positions by name.
+    pub spawn_points: BTreeMap<String, (f32, f32)>,
+    pub doors: Vec<Door>,
+}
+
+impl LevelData {
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    pub fn tilemap(&self) -> TileMap {
+        let rows: Vec<&str> = self.tiles.iter().map(String::as_str).collect();
+        TileMap::from_rows(&rows, self.tile_size)
+    }
+}
+
+/// Where levels are read from. Sources are called from the streaming threads.
+pub trait LevelSource: Send + Sync {
+    fn load(&self, id: LevelId) -> Result<LevelData, String>;
+}
+
+/// Levels stored as `<id>.json` files in a directory.
+pub struct DirLevelSource {
+    dir: PathBuf,
+}
+
+impl DirLevelSource {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+}
+
+impl LevelSource for DirLevelSource {
+    fn load(&self, id: LevelId) -> Result<LevelData, String> {
+        let path = self.dir.join(format!("{}.json", id.0));
+        let json = std::fs::read_to_string(&path).map_err(|e| format!("{} : {}", path.display(), e))?;
+        LevelData::from_json(&json)
+    }
+}
+
+/// Levels of the project database, read-only. Every load opens its own connection, so it can run
+/// on any thread.
+pub struct DbLevelSource {
+    path: PathBuf,
+}
+
+impl DbLevelSource {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf() }
+    }
+
+    async fn query(&self, id: LevelId) -> Result<Option<String>, DbError> {
+        let pool = engine_db::connect_read_only(&self.path).await?;
+        let data = project_repository::level_data(&pool, id.0 as i64).await;
+        pool.close().await;
+        data
+    }
+}
+
+impl LevelSource for DbLevelSource {
+    fn load(&self, id: LevelId) -> Result<LevelData, String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let json = runtime.block_on(self.query(id)).map_err(|e| e.to_string())?;
+        LevelData::from_json(&json.ok_or_else(|| format!("level {} has no description", id.0))?)
+    }
+}
+
+/// Published once a level is entered.
+#[derive(PartialEq, Debug, Clone)]
+pub struct LevelLoaded {
+    pub level: LevelId,
+    pub spawn: String,
+    /// Entities spawned from the placements, in placement order.
+    pub spawned: Vec<EntityId>,
+}
+
+/// Published when a level could not be loaded, the current level is kept.
+#[derive(PartialEq, Debug, Clone)]
+pub struct LevelLoadFailed {
+    pub level: LevelId,
+    pub reason: String,
+}
+
+/// Engine state changed by a level switch.
+pub struct LevelContext<'a> {
+    pub world: &'a mut World,
+    pub tilemap: &'a mut TileMap,
+    pub triggers: &'a mut Triggers,
+    pub post_process: &'a mut PostProcess,
+    pub events: &'a mut EventBus,
+    /// Kept across levels and moved to the spawn point.
+    pub player: Option<EntityId>,
+}
+
+/// Screen transition between two levels.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TransitionConfig {
+    pub fade_seconds: f32,
+    pub color: Color,
+}
+
+impl Default for TransitionConfig {
+    fn default() -> Self {
+        Self {
+            fade_seconds: 0.4,
+            color: [0, 0, 0],
+        }
+    }
+}
+
+struct CurrentLevel {
+    id: LevelId,
+    spawn: String,
+    /// Entities and zones created from this id on belong to the level, whoever created them.
+    first_entity: EntityId,
+    first_trigger: TriggerId,
+    doors: HashMap<TriggerId, Door>,
+    /// Doors the player stood in when entering the level, usable again once left.
+    disarmed: HashSet<TriggerId>,
+}
+
+struct Transition {
+    target: LevelId,
+    spawn: String,
+    elapsed: f32,
+}
+
+/// Loads the levels of the project, streaming them on background threads, and moves the player
+/// through the doors with a fade transition.
+pub struct LevelManager {
+    source: Arc<dyn LevelSource>,
+    pub transition: TransitionConfig,
+    current: Option<CurrentLevel>,
+    loaded: HashMap<LevelId, LevelData>,
+    loading: HashMap<LevelId, Receiver<Result<LevelData, String>>>,
+    pending: Option<Transition>,
+    /// Fade out covering the screen during the transition.
+    fade: Option<EffectId>,
+}
+
+impl LevelManager {
+    pub fn new(source: impl LevelSource + 'static) -> Self {
+        Self {
+            source: Arc::new(source),
+            transition: TransitionConfig::default(),
+            current: None,
+            loaded: HashMap::new(),
+            loading: HashMap::new(),
+            pending: None,
+            fade: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<LevelId> {
+        self.current.as_ref().map(|level| level.id)
+    }
+
+    pub fn is_transitioning(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Start loading a level on a background thread, if not loaded or loading yet.
+    pub fn preload(&mut self, id: LevelId) {
+        if self.loaded.contains_key(&id) || self.loading.contains_key(&id) {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        let source = self.source.clone();
+        std::thread::spawn(move || {
+            let _ = sender.send(source.load(id));
+        });
+        self.loading.insert(id, receiver);
+    }
+
+    /// Fade out, then enter `level` at `spawn` once it is streamed in.
+    pub fn request(&mut self, level: LevelId, spawn: &str, ctx: &mut LevelContext) {
+        if self.pending.is_some() {
+            return;
+        }
+        info!("LEVEL:transition to {:?} at {}", level, spawn);
+        self.preload(level);
+        let duration = Duration::from_secs_f32(self.transition.fade_seconds);
+        self.fade = Some(ctx.post_process.push(Fade::to_color(self.transition.color, duration)));
+        self.pending = Some(Transition {
+            target: level,
+            spawn: String::from(spawn),
+            elapsed: 0.,
+        });
+    }
+
+    /// Load a level and enter it right away, without transition.
+    pub fn load(&mut self, level: LevelId, spawn: &str, ctx: &mut LevelContext) -> Result<(), String> {
+        let data = match self.loaded.remove(&level) {
+            Some(data) => data,
+            None => self.source.load(level)?,
+        };
+        self.enter(level, data, spawn, ctx);
+        Ok(())
+    }
+
+    /// Read the current level again from the source and re-enter it at the spawn point it was
+    /// entered at, when the project changed during development. Levels streamed in before are
+    /// dropped as they may be outdated.
+    pub fn reload(&mut self, ctx: &mut LevelContext) -> Result<(), String> {
+        self.loaded.clear();
+        self.loading.clear();
+        if let Some(target) = self.pending.as_ref().map(|pending| pending.target) {
+            self.preload(target);
+        }
+        let Some((id, spawn)) = self.current.as_ref().map(|level| (level.id, level.spawn.clone())) else {
+            return Ok(());
+        };
+        let data = self.source.load(id)?;
+        self.enter(id, data, &spawn, ctx);
+        Ok(())
+    }
+
+    /// Collect the levels streamed in. A failed load cancels the transition waiting for it.
+    fn receive(&mut self, ctx: &mut LevelContext) {
+        let mut received = Vec::new();
+        for (id, receiver) in self.loading.iter() {
+            match receiver.try_recv() {
+                Ok(result) => received.push((*id, result)),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => received.push((*id, Err(String::from("loader thread died")))),
+            }
+        }
+        for (id, result) in received {
+            self.loading.remove(&id);
+            match result {
+                Ok(data) => {
+                    self.loaded.insert(id, data);
+                }
+                Err(reason) => {
+                    error!("LEVEL:{:?} load failed : {}", id, reason);
+                    if self.pending.take_if(|pending| pending.target == id).is_some() {
+                        self.fade_in(ctx);
+                    }
+                    ctx.events.publish(LevelLoadFailed { level: id, reason });
+                }
+            }
+        }
+    }
+
+    fn fade_in(&mut self, ctx: &mut LevelContext) {
+        let duration = Duration::from_secs_f32(self.transition.fade_seconds);
+        // The fade out keeps the screen covered until removed.
+        if let Some(fade) = self.fade.take() {
+            ctx.post_process.remove(fade);
+        }
+        ctx.post_process.push(Fade::from_color(self.transition.color, duration));
+    }
+
+    /// Follow the doors entered by the player during the last tick and run the transition.
+    /// Returns `true` on the tick a new level is entered.
+    pub fn update(&mut self, ctx: &mut LevelContext, dt: f32) -> bool {
+        self.receive(ctx);
+
+        if self.pending.is_none()
+            && let Some(door) = self.entered_door(ctx)
+        {
+            self.request(door.target_level, &door.target_spawn, ctx);
+        }
+
+        let Some(pending) = self.pending.as_mut() else {
+            return false;
+        };
+        pending.elapsed += dt;
+        if pending.elapsed < self.transition.fade_seconds || !self.loaded.contains_key(&pending.target) {
+            return false;
+        }
+        let pending = self.pending.take().unwrap();
+        let data = self.loaded.remove(&pending.target).unwrap();
+        self.enter(pending.target, data, &pending.spawn, ctx);
+        self.fade_in(ctx);
+        true
+    }
+
+    fn entered_door(&mut self, ctx: &LevelContext) -> Option<Door> {
+        let (current, player) = (self.current.as_mut()?, ctx.player?);
+        let mut entered = None;
+        for event in ctx.events.read::<TriggerEvent>() {
+            match event {
+                TriggerEvent::Exit(contact) if contact.entity == player => {
+                    current.disarmed.remove(&contact.trigger);
+                }
+                TriggerEvent::Enter(contact) if contact.entity == player && !current.disarmed.contains(&contact.trigger) => {
+                    entered = entered.or(current.doors.get(&contact.trigger).cloned());
+                }
+                _ => {}
+            }
+        }
+        entered
+    }
+
+    /// Despawn every entity and remove every trigger zone created while the current level was
+    /// active, by the level itself, the scripts or the game. The player is kept.
+    pub fn unload(&mut self, ctx: &mut LevelContext) {
+        let Some(level) = self.current.take() else {
+            return;
+        };
+        for id in ctx.world.ids() {
+            if id >= level.first_entity && Some(id) != ctx.player {
+                ctx.world.despawn(id);
+            }
+        }
+        for trigger in ctx.triggers.ids() {
+            if trigger >= level.first_trigger {
+                ctx.triggers.remove(trigger);
+            }
+        }
+        *ctx.tilemap = TileMap::default();
+        info!("LEVEL:{:?} unloaded", level.id);
+    }
+
+    fn enter(&mut self, id: LevelId, data: LevelData, spawn: &str, ctx: &mut LevelContext) {
+        self.unload(ctx);
+        let (first_entity, first_trigger) = (ctx.world.next_id(), ctx.triggers.next_id());
+        *ctx.tilemap = data.tilemap();
+        let spawned: Vec<EntityId> = data
+            .placements
+            .iter()
+            .map(|placement| ctx.world.spawn(&placement.kind, Vec2::new(placement.x, placement.y)))
+            .collect();
+        let doors: HashMap<TriggerId, Door> = data
+            .doors
+            .iter()
+            .enumerate()
+            .map(|(i, door)| {
+                let area = Rect::new(door.x, door.y, door.width, door.height);
+                let trigger = ctx.triggers.add(TriggerZone::new(area, &format!("{}{}", DOOR_TAG_PREFIX, i)));
+                (trigger, door.clone())
+            })
+            .collect();
+        let position = data.spawn_points.get(spawn).or_else(|| {
+            warn!("LEVEL:{:?} has no spawn point {}", id, spawn);
+            data.spawn_points.values().next()
+        });
+        if let (Some(player), Some((x, y))) = (ctx.player.and_then(|id| ctx.world.get_mut(id)), position) {
+            player.position = Vec2::new(*x, *y);
+            player.velocity = Vec2::ZERO;
+        }
+        let player_bounds = ctx.player.and_then(|id| ctx.world.get(id)).map(|player| player.bounds());
+        let disarmed = doors
+            .iter()
+            .filter(|(_, door)| {
+                let area = Rect::new(door.x, door.y, door.width, door.height);
+                player_bounds.is_some_and(|bounds| bounds.intersects(&area))
+            })
+            .map(|(trigger, _)| *trigger)
+            .collect();
+        info!("LEVEL:{:?} {} entered at {}", id, data.name, spawn);
+        self.current = Some(CurrentLevel {
+            id,
+            spawn: String::from(spawn),
+            first_entity,
+            first_trigger,
+            doors,
+            disarmed,
+        });
+        ctx.events.publish(LevelLoaded {
+            level: id,
+            spawn: String::from(spawn),
+            spawned,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::post_process::Scanlines;
+    use std::sync::Mutex;
+
+    /// Levels kept in memory, editable while the manager uses them.
+    #[derive(Clone, Default)]
+    struct MemorySource(Arc<Mutex<HashMap<LevelId, LevelData>>>);
+
+    impl MemorySource {
+        fn set(&self, id: LevelId, data: LevelData) {
+            self.0.lock().unwrap().insert(id, data);
+        }
+    }
+
+    impl LevelSource for MemorySource {
+        fn load(&self, id: LevelId) -> Result<LevelData, String> {
+            self.0.lock().unwrap().get(&id).cloned().ok_or(format!("no level {}", id.0))
+        }
+    }
+
+    fn level(placements: &[&str]) -> LevelData {
+        LevelData {
+            name: String::from("test"),
+            tile_size: 16.,
+            tiles: vec![String::from("..."), String::from("###")],
+            placements: placements
+                .iter()
+                .map(|kind| Placement {
+                    kind: String::from(*kind),
+                    x: 0.,
+                    y: 0.,
+                })
+                .collect(),
+            spawn_points: BTreeMap::from([(String::from(DEFAULT_SPAWN), (8., 8.))]),
+            doors: Vec::new(),
+        }
+    }
+
+    fn door_to(target: LevelId) -> Door {
+        Door {
+            x: 100.,
+            y: 100.,
+            width: 16.,
+            height: 16.,
+            target_level: target,
+            target_spawn: String::from(DEFAULT_SPAWN),
+        }
+    }
+
+    struct State {
+        world: World,
+        tilemap: TileMap,
+        triggers: Triggers,
+        post_process: PostProcess,
+        events: EventBus,
+    }
+
+    impl State {
+        fn new() -> Self {
+            Self {
+                world: World::new(),
+                tilemap: TileMap::default(),
+                triggers: Triggers::new(),
+                post_process: PostProcess::new(),
+                events: EventBus::new(),
+            }
+        }
+
+        fn ctx(&mut self) -> LevelContext<'_> {
+            LevelContext {
+                world: &mut self.world,
+                tilemap: &mut self.tilemap,
+                triggers: &mut self.triggers,
+                post_process: &mut self.post_process,
+                events: &mut self.events,
+                player: None,
+            }
+        }
+    }
+
+    fn kinds(world: &World) -> Vec<String> {
+        let mut kinds: Vec<String> = world.iter().map(|entity| entity.kind.clone()).collect();
+        kinds.sort();
+        kinds
+    }
+
+    #[test]
+    fn reload_reenters_the_current_level_with_its_new_content() {
+        let source = MemorySource::default();
+        source.set(LevelId(1), level(&["slime"]));
+        let mut levels = LevelManager::new(source.clone());
+        let mut state = State::new();
+        levels.load(LevelId(1), DEFAULT_SPAWN, &mut state.ctx()).unwrap();
+        assert_eq!(kinds(&state.world), vec!["slime"]);
+
+        source.set(LevelId(1), level(&["bat", "coin"]));
+        levels.reload(&mut state.ctx()).unwrap();
+        assert_eq!(levels.current(), Some(LevelId(1)));
+        assert_eq!(kinds(&state.world), vec!["bat", "coin"]);
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_current_level() {
+        let source = MemorySource::default();
+        source.set(LevelId(1), level(&["slime"]));
+        let mut levels = LevelManager::new(source.clone());
+        let mut state = State::new();
+        levels.load(LevelId(1), DEFAULT_SPAWN, &mut state.ctx()).unwrap();
+
+        source.0.lock().unwrap().clear();
+        assert!(levels.reload(&mut state.ctx()).is_err());
+        assert_eq!(levels.current(), Some(LevelId(1)));
+        assert_eq!(kinds(&state.world), vec!["slime"]);
+    }
+
+    #[test]
+    fn unload_removes_everything_created_during_the_level() {
+        let source = MemorySource::default();
+        let mut first = level(&["slime"]);
+        first.doors.push(door_to(LevelId(2)));
+        source.set(LevelId(1), first);
+        source.set(LevelId(2), level(&[]));
+        let mut levels = LevelManager::new(source);
+        let mut state = State::new();
+        let kept = state.world.spawn("npc", Vec2::ZERO);
+        let kept_zone = state.triggers.add(TriggerZone::new(Rect::new(0., 0., 1., 1.), "global"));
+        levels.load(LevelId(1), DEFAULT_SPAWN, &mut state.ctx()).unwrap();
+        let player = state.world.spawn("player", Vec2::ZERO);
+        state.world.spawn("coin", Vec2::ZERO);
+        state.triggers.add(TriggerZone::new(Rect::new(0., 0., 1., 1.), "script"));
+
+        let mut ctx = state.ctx();
+        ctx.player = Some(player);
+        levels.unload(&mut ctx);
+        assert_eq!(state.world.ids(), vec![kept, player]);
+        assert_eq!(state.triggers.ids(), vec![kept_zone]);
+        assert_eq!(levels.current(), None);
+    }
+
+    #[test]
+    fn transition_keeps_the_other_post_effects() {
+        let source = MemorySource::default();
+        source.set(LevelId(1), level(&[]));
+        source.set(LevelId(2), level(&[]));
+        let mut levels = LevelManager::new(source);
+        levels.transition.fade_seconds = 0.;
+        let mut state = State::new();
+        state.post_process.push(Scanlines::new(0.5));
+        levels.load(LevelId(1), DEFAULT_SPAWN, &mut state.ctx()).unwrap();
+
+        levels.request(LevelId(2), DEFAULT_SPAWN, &mut state.ctx());
+        let mut entered = false;
+        for _ in 0..200 {
+            entered = levels.update(&mut state.ctx(), 0.1);
+            if entered {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(entered);
+        assert_eq!(levels.current(), Some(LevelId(2)));
+        state.post_process.update(Duration::from_secs(1));
+        let mut pixels = [[100, 100, 100, 255], [100, 100, 100, 255]].concat();
+        state.post_process.apply(&mut pixels, 1, 2);
+        assert_eq!(pixels, [[100, 100, 100, 255], [50, 50, 50, 255]].concat());
+    }
+}
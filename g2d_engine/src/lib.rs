@@ -1,54 +1,545 @@
+use crate::ai::AiSystem;
+use crate::assets::Assets;
+use crate::camera::Camera;
+use crate::combat::Combat;
+use crate::cutscene::{CutscenePlayer, StoryLibrary};
+use crate::entity::EntityId;
+use crate::error::G2dError;
+use crate::event::bus::EventBus;
+use crate::event::trigger::Triggers;
+use crate::geometry::Rect;
 use crate::gui::gui::Gui;
-use error_iter::ErrorIter;
+use crate::hud::{BitmapHud, HudState};
+use crate::hot_reload::{AssetKind, AssetReloaded, HotReloader};
+use crate::input::InputActions;
+use crate::level::{LevelContext, LevelId, LevelManager};
+use crate::pickup::Pickups;
+use crate::profiler::{FrameProfiler, ProfilerLayer, SharedProfiler};
+use crate::render::bitmap::{Canvas, PixelRect};
+use crate::render::pipeline::{ImageLayer, RenderPipeline, Sprite, SpriteLayer};
+use crate::render::post_process::PostProcess;
+use crate::rng::Rng;
+use crate::save::SaveGame;
+use crate::script::ScriptHost;
+use crate::tilemap::TileMap;
+use crate::world::World;
+use gwen2d_project::db::engine_db;
 use image::{imageops::resize, imageops::FilterType, ImageBuffer, Rgba, RgbaImage};
 use log::{error, info};
-use pixels::{Error, Pixels, SurfaceTexture};
+use pixels::{Pixels, SurfaceTexture};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info_span, instrument};
+use tracing_subscriber::layer::SubscriberExt;
 use winit::dpi::LogicalSize;
 use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
 use winit::event_loop::EventLoop;
-use winit::keyboard;
-use winit::keyboard::KeyCode::F11;
-use winit::keyboard::{Key, KeyCode, NamedKey, PhysicalKey};
-use winit::platform::scancode::PhysicalKeyExtScancode;
+use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{Fullscreen, WindowBuilder};
 use winit_input_helper::WinitInputHelper;
 
+pub mod ai;
+pub mod assets;
+pub mod audio;
+pub mod camera;
+pub mod combat;
+pub mod controller;
+pub mod cutscene;
+pub mod dialogue;
+pub mod entity;
+pub mod error;
+pub mod geometry;
+pub mod hot_reload;
+pub mod hud;
+pub mod input;
+pub mod level;
+pub mod nav;
+pub mod pickup;
+pub mod profiler;
+pub mod project;
+pub mod rng;
+pub mod save;
+pub mod script;
+pub mod tilemap;
+pub mod world;
+
+pub mod event {
+    pub mod bus;
+    pub mod trigger;
+}
+
 pub mod gui {
     pub mod framework;
+    #[allow(clippy::module_inception)]
     pub mod gui;
 }
 
+pub mod render {
+    pub mod bitmap;
+    pub mod pipeline;
+    pub mod post_process;
+}
+
+/// Directory of the game resources, relative to the working directory.
+pub const RESOURCES_DIR: &str = "./resources";
+
 pub struct G2dEngine {
     screen_width: u32,
     screen_height: u32,
     playing: bool,
     background: RgbaImage,
+    post_process: PostProcess,
+    pipeline: Option<RenderPipeline>,
+    /// Post effects or HUD changed the frame outside of the pipeline last draw.
+    overdrawn: Option<PixelRect>,
+    /// Screen bounds of the sprites drawn last time, redrawn with the background once they move.
+    drawn_sprites: Vec<PixelRect>,
+    /// Animation frames by entity kind and state.
+    entity_frames: HashMap<(String, String), Vec<Arc<RgbaImage>>>,
+    events: EventBus,
+    triggers: Triggers,
+    world: World,
+    scripts: ScriptHost,
+    input: InputActions,
+    assets: Assets,
+    hot_reload: Option<HotReloader>,
+    tilemap: TileMap,
+    ai: AiSystem,
+    combat: Combat,
+    pickups: Pickups,
+    hud: Option<BitmapHud>,
+    camera: Camera,
+    cutscenes: CutscenePlayer,
+    levels: Option<LevelManager>,
+    player: Option<EntityId>,
+    profiler: Option<SharedProfiler>,
+    rng: Rng,
 }
 
 impl G2dEngine {
-    pub fn new(width: u32, height: u32, background: RgbaImage) -> Self {
-        Self {
+    pub fn new(width: u32, height: u32, background: RgbaImage) -> Result<Self, G2dError> {
+        if width == 0 || height == 0 {
+            return Err(G2dError::Config(format!("screen size {}x{} is empty", width, height)));
+        }
+        if background.width() == 0 || background.height() == 0 {
+            return Err(G2dError::Config(String::from("background image is empty")));
+        }
+        Ok(Self {
             screen_width: width,
             screen_height: height,
             playing: false,
             background,
+            post_process: PostProcess::new(),
+            pipeline: None,
+            overdrawn: None,
+            drawn_sprites: Vec::new(),
+            entity_frames: HashMap::new(),
+            events: EventBus::new(),
+            triggers: Triggers::new(),
+            world: World::new(),
+            scripts: ScriptHost::default(),
+            input: InputActions::new(),
+            assets: Assets::new(Path::new(RESOURCES_DIR)),
+            hot_reload: None,
+            tilemap: TileMap::default(),
+            ai: AiSystem::new(),
+            combat: Combat::default(),
+            pickups: Pickups::new(),
+            hud: None,
+            camera: Camera::new(),
+            cutscenes: CutscenePlayer::new(),
+            levels: None,
+            player: None,
+            profiler: None,
+            rng: Rng::default(),
+        })
+    }
+
+    /// Load images of the resources directory ahead of their first use.
+    pub fn load_assets(&mut self, images: &[&str]) -> Result<(), G2dError> {
+        for name in images {
+            let path = self.assets.root().join(name);
+            self.assets.load_image(name).map_err(|e| G2dError::asset(path, e))?;
+        }
+        Ok(())
+    }
+
+    /// Screen transitions and effects applied on top of the drawn frame.
+    pub fn post_process(&mut self) -> &mut PostProcess {
+        &mut self.post_process
+    }
+
+    /// Event bus shared by the engine systems, the game and its gui.
+    pub fn events(&mut self) -> &mut EventBus {
+        &mut self.events
+    }
+
+    /// Trigger zones of the current level.
+    pub fn triggers(&mut self) -> &mut Triggers {
+        &mut self.triggers
+    }
+
+    /// Test entity colliders against the trigger zones, the resulting events are delivered on the
+    /// next update.
+    pub fn update_triggers(&mut self, colliders: impl IntoIterator<Item = (EntityId, Rect)>) {
+        self.triggers.update(colliders, &mut self.events);
+    }
+
+    /// Entities of the current level.
+    pub fn world(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Behaviour scripts of the entities.
+    pub fn scripts(&mut self) -> &mut ScriptHost {
+        &mut self.scripts
+    }
+
+    /// Input actions, refreshed on every window event.
+    pub fn input(&mut self) -> &mut InputActions {
+        &mut self.input
+    }
+
+    /// Collision map of the current level.
+    pub fn tilemap(&mut self) -> &mut TileMap {
+        &mut self.tilemap
+    }
+
+    /// Enemy behaviours.
+    pub fn ai(&mut self) -> &mut AiSystem {
+        &mut self.ai
+    }
+
+    /// Health, hitboxes and hurtboxes of the fighting entities.
+    pub fn combat(&mut self) -> &mut Combat {
+        &mut self.combat
+    }
+
+    /// Collectibles of the level and the players inventories.
+    pub fn pickups(&mut self) -> &mut Pickups {
+        &mut self.pickups
+    }
+
+    /// HUD drawn over the world for the player, `None` to draw it through the gui instead.
+    pub fn set_hud(&mut self, hud: Option<BitmapHud>) {
+        self.hud = hud;
+    }
+
+    /// View position in the world.
+    pub fn camera(&mut self) -> &mut Camera {
+        &mut self.camera
+    }
+
+    /// Dialogues and cutscenes, blocking the gameplay input while they play.
+    pub fn cutscenes(&mut self) -> &mut CutscenePlayer {
+        &mut self.cutscenes
+    }
+
+    /// Level manager of the game, loading the levels from its source.
+    pub fn set_levels(&mut self, levels: LevelManager) {
+        self.levels = Some(levels);
+    }
+
+    pub fn levels(&mut self) -> Option<&mut LevelManager> {
+        self.levels.as_mut()
+    }
+
+    /// Enter a level right away, at the `spawn` point.
+    pub fn load_level(&mut self, level: LevelId, spawn: &str) -> Result<(), String> {
+        let levels = self.levels.as_mut().ok_or("no level manager")?;
+        let mut ctx = LevelContext {
+            world: &mut self.world,
+            tilemap: &mut self.tilemap,
+            triggers: &mut self.triggers,
+            post_process: &mut self.post_process,
+            events: &mut self.events,
+            player: self.player,
+        };
+        levels.load(level, spawn, &mut ctx)?;
+        self.level_entered(level);
+        Ok(())
+    }
+
+    /// Prepare the systems depending on the level just entered.
+    fn level_entered(&mut self, level: LevelId) {
+        self.ai.rebuild_nav_graph(&self.tilemap);
+        self.rng.start_level(level.0);
+    }
+
+    /// Random numbers of the game, reseeded at each level start.
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Random streams to store in a [`SaveGame`].
+    pub fn snapshot_rng(&self) -> Rng {
+        self.rng.clone()
+    }
+
+    /// Go on with saved random streams. Entering a level reseeds them, so restore them after.
+    pub fn restore_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
+    /// Enter the level of a save at `spawn`, then restore its random streams.
+    pub fn resume(&mut self, level: LevelId, spawn: &str, save: &SaveGame) -> Result<(), String> {
+        self.load_level(level, spawn)?;
+        if let Some(rng) = &save.rng {
+            self.restore_rng(rng.clone());
+        }
+        Ok(())
+    }
+
+    /// Seed of the whole game, the level seeds are derived from it.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Entity controlled by the player, targeted by the enemies.
+    pub fn player(&self) -> Option<EntityId> {
+        self.player
+    }
+
+    pub fn set_player(&mut self, player: Option<EntityId>) {
+        self.player = player;
+    }
+
+    /// Time the engine systems with a [`FrameProfiler`], installed as the global tracing
+    /// subscriber. Use [`G2dEngine::set_profiler`] instead to combine it with other tracing layers.
+    pub fn enable_profiler(&mut self) -> Result<SharedProfiler, G2dError> {
+        let profiler = FrameProfiler::shared();
+        let subscriber = tracing_subscriber::registry().with(ProfilerLayer::new(profiler.clone()));
+        tracing::subscriber::set_global_default(subscriber)
+            .map_err(|e| G2dError::Config(format!("profiler not installed : {}", e)))?;
+        self.profiler = Some(profiler.clone());
+        Ok(profiler)
+    }
+
+    /// Profiler fed by a [`ProfilerLayer`] installed by the game, its frames are closed by the
+    /// engine after each render.
+    pub fn set_profiler(&mut self, profiler: Option<SharedProfiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn profiler(&self) -> Option<&SharedProfiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Images of the game.
+    pub fn assets(&mut self) -> &mut Assets {
+        &mut self.assets
+    }
+
+    /// Watch `dirs` and reload changed images and scripts in place. A changed project database
+    /// reloads the stories and scripts it holds, then re-enters the current level. Each reload is
+    /// announced with an [`AssetReloaded`] event.
+    pub fn enable_hot_reload(&mut self, dirs: &[PathBuf]) -> notify::Result<()> {
+        self.hot_reload = Some(HotReloader::watch(dirs)?);
+        Ok(())
+    }
+
+    fn reload_changed_assets(&mut self) {
+        let Some(reloader) = self.hot_reload.as_mut() else {
+            return;
+        };
+        for (kind, path) in reloader.poll() {
+            let result = match kind {
+                AssetKind::Image => match self.assets.image_name(&path) {
+                    Some(name) => self.assets.reload_image(&name).map_err(|e| e.to_string()),
+                    None => Ok(()),
+                },
+                AssetKind::Script => self.scripts.load_file(&path),
+                AssetKind::Project => self.reload_project(&path),
+            };
+            match result {
+                Ok(()) => {
+                    info!("HOT RELOAD:{} reloaded", path.display());
+                    self.events.publish(AssetReloaded { kind, path });
+                }
+                Err(e) => error!("HOT RELOAD:{} reload failed : {}", path.display(), e),
+            }
         }
     }
 
-    pub fn load_assets(&mut self) {}
+    /// Read the stories and scripts of the project again and re-enter the current level. The story
+    /// library is only replaced once it is fully read.
+    fn reload_project(&mut self, path: &Path) -> Result<(), String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let scripts = &mut self.scripts;
+        let library = runtime.block_on(async {
+            let pool = engine_db::connect_read_only(path).await.map_err(|e| e.to_string())?;
+            let mut library = StoryLibrary::new();
+            let result = match library.load_db(&pool).await {
+                Ok(()) => scripts.load_db(&pool).await.map(|_| library),
+                Err(e) => Err(e),
+            };
+            pool.close().await;
+            result
+        })?;
+        self.cutscenes.library = library;
+        let Some(levels) = self.levels.as_mut() else {
+            return Ok(());
+        };
+        let mut ctx = LevelContext {
+            world: &mut self.world,
+            tilemap: &mut self.tilemap,
+            triggers: &mut self.triggers,
+            post_process: &mut self.post_process,
+            events: &mut self.events,
+            player: self.player,
+        };
+        levels.reload(&mut ctx)?;
+        if let Some(level) = levels.current() {
+            self.level_entered(level);
+        }
+        Ok(())
+    }
+
+    /// Images of the animation frames of an entity state, drawn at the entity position. Entities
+    /// without frames are not drawn.
+    pub fn set_entity_frames(&mut self, kind: &str, state: &str, frames: Vec<Arc<RgbaImage>>) {
+        self.entity_frames.insert((String::from(kind), String::from(state)), frames);
+    }
+
+    /// Sprites of the visible entities, relative to the camera.
+    fn sprites(&self) -> SpriteLayer {
+        let sprites = self
+            .world
+            .iter()
+            .filter(|entity| self.combat.is_visible(entity.id))
+            .filter_map(|entity| {
+                let frames = self.entity_frames.get(&(entity.kind.clone(), entity.state.clone()))?;
+                let image = frames.get(entity.frame).or(frames.last())?;
+                let position = entity.position - self.camera.position;
+                Some(Sprite {
+                    image: image.clone(),
+                    x: position.x.round() as i32,
+                    y: position.y.round() as i32,
+                })
+            })
+            .collect();
+        SpriteLayer { sprites }
+    }
+
+    /// Software renderer drawing the frame, rebuilt when the frame size changes.
+    pub fn pipeline(&mut self) -> Option<&mut RenderPipeline> {
+        self.pipeline.as_mut()
+    }
 
-    pub fn draw(&self, frame: &mut [u8], default_background: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
+    #[instrument(skip_all)]
+    pub fn draw(&mut self, frame: &mut [u8], default_background: &ImageBuffer<Rgba<u8>, Vec<u8>>) {
+        let (width, height) = default_background.dimensions();
+        if self.pipeline.as_ref().is_none_or(|pipeline| pipeline.size() != (width, height)) {
+            let mut pipeline = RenderPipeline::new(width, height);
+            pipeline.add_static_layer(ImageLayer::new(default_background.clone()));
+            self.pipeline = Some(pipeline);
+        }
+        let sprites = self.sprites();
+        let pipeline = self.pipeline.as_mut().unwrap();
+        // Pixels drawn over the pipeline output last time must be restored.
+        if let Some(area) = self.overdrawn.take() {
+            pipeline.mark_dirty(area);
+        }
+        let bounds: Vec<PixelRect> = sprites.sprites.iter().map(Sprite::bounds).collect();
+        for area in self.drawn_sprites.iter().chain(bounds.iter()) {
+            pipeline.mark_dirty(area.intersection(&PixelRect::new(0, 0, width, height)));
+        }
+        self.drawn_sprites = bounds;
         if self.playing {} else {
-            frame.copy_from_slice(&default_background);
+            pipeline.render(frame, &[&sprites]);
+        }
+        let full = PixelRect::new(0, 0, width, height);
+        if let (Some(hud), Some(player)) = (&self.hud, self.player) {
+            let state = match self.pickups.inventory(player) {
+                Some(inventory) => HudState::from_player(inventory, self.combat.get(player)),
+                None => HudState::default(),
+            };
+            hud.draw(&state, &mut Canvas::new(frame, width, height));
+            self.overdrawn = Some(hud.area(&state).intersection(&full));
+        }
+        if !self.post_process.is_empty() {
+            self.post_process.apply(frame, width, height);
+            self.overdrawn = Some(full);
         }
     }
 
-    pub fn update(&self) {}
+    #[instrument(skip_all)]
+    pub fn update(&mut self, dt: Duration) {
+        self.reload_changed_assets();
+        if let Some(levels) = self.levels.as_mut() {
+            let _span = info_span!("levels").entered();
+            let mut ctx = LevelContext {
+                world: &mut self.world,
+                tilemap: &mut self.tilemap,
+                triggers: &mut self.triggers,
+                post_process: &mut self.post_process,
+                events: &mut self.events,
+                player: self.player,
+            };
+            let entered = match levels.update(&mut ctx, dt.as_secs_f32()) {
+                true => levels.current(),
+                false => None,
+            };
+            if levels.is_transitioning() {
+                self.input.release_all();
+            }
+            if let Some(level) = entered {
+                self.level_entered(level);
+            }
+        }
+        let story_active = info_span!("cutscenes").in_scope(|| {
+            self.cutscenes.update(
+                &mut self.world,
+                &mut self.camera,
+                &mut self.post_process,
+                &self.input,
+                &mut self.events,
+                dt.as_secs_f32(),
+            )
+        });
+        if story_active {
+            self.input.release_all();
+        }
+        self.camera.update(dt.as_secs_f32());
+        info_span!("scripts").in_scope(|| {
+            self.scripts
+                .update(&mut self.world, &self.input, &mut self.events, dt)
+        });
+        info_span!("ai").in_scope(|| {
+            self.ai.update(
+                &mut self.world,
+                &self.tilemap,
+                self.player,
+                &mut self.rng,
+                &mut self.events,
+                dt.as_secs_f32(),
+            )
+        });
+        info_span!("animation").in_scope(|| self.world.animate(dt.as_secs_f32()));
+        let dead = info_span!("combat")
+            .in_scope(|| self.combat.update(&mut self.world, &mut self.events, dt.as_secs_f32()));
+        for dead in dead {
+            self.ai.remove(dead);
+        }
+        info_span!("pickups")
+            .in_scope(|| self.pickups.update(&mut self.world, &mut self.events, dt.as_secs_f32()));
+        info_span!("triggers")
+            .in_scope(|| self.triggers.update(self.world.colliders(), &mut self.events));
+        self.post_process.update(dt);
+        info_span!("events").in_scope(|| self.events.dispatch());
+    }
 
-    pub fn run(&mut self, gui: Box<dyn Gui>) -> Result<(), Error> {
+    /// Open the game window and run the game loop until the window is closed.
+    pub fn run(&mut self, gui: Box<dyn Gui>) -> Result<(), G2dError> {
         let mut is_fullscreen = false;
-        let event_loop = EventLoop::new().unwrap();
+        let event_loop = EventLoop::new()?;
         let mut input = WinitInputHelper::new();
+        let mut last_update = Instant::now();
         let window = {
             let size = LogicalSize::new(self.screen_width as f64, self.screen_height as f64);
             WindowBuilder::new()
@@ -57,8 +548,7 @@ impl G2dEngine {
                 .with_min_inner_size(size)
                 .with_resizable(true)
                 .with_fullscreen(None)
-                .build(&event_loop)
-                .unwrap()
+                .build(&event_loop)?
         };
         let mut size = window.inner_size();
         let mut resized_image = resize(
@@ -83,7 +573,9 @@ impl G2dEngine {
             );
             (pixels, framework)
         };
-        let res = event_loop.run(|event, elwt| {
+        // Error stopping the event loop, returned once it exits.
+        let mut failure: Option<G2dError> = None;
+        event_loop.run(|event, elwt| {
             if input.update(&event) {
                 if input.key_pressed(KeyCode::Escape) || input.close_requested() {
                     elwt.exit();
@@ -95,13 +587,18 @@ impl G2dEngine {
                 // Resize the window
                 if let Some(size) = input.window_resized() {
                     if let Err(err) = pixels.resize_surface(size.width, size.height) {
-                        log_error("pixels.resize_surface", err);
+                        error!("pixels.resize_surface() failed: {err}");
+                        failure = Some(err.into());
                         elwt.exit();
                         return;
                     }
                     framework.resize(size.width, size.height);
                 }
-                self.update();
+                self.input.update(&input);
+                let now = Instant::now();
+                self.update(now - last_update);
+                last_update = now;
+                framework.on_events(&self.events);
                 window.request_redraw();
             }
             match event {
@@ -112,7 +609,7 @@ impl G2dEngine {
                         ..
                     },
                     ..
-                } => if physical_key == PhysicalKey::Code(KeyCode::F11) && state == ElementState::Pressed {
+                } if physical_key == PhysicalKey::Code(KeyCode::F11) && state == ElementState::Pressed => {
                     info!("F11");
                     is_fullscreen = !is_fullscreen;
                     if is_fullscreen {
@@ -120,7 +617,7 @@ impl G2dEngine {
                     } else {
                         window.set_fullscreen(None);
                     }
-                },
+                }
 
                 // Draw the current frame
                 Event::WindowEvent {
@@ -130,8 +627,9 @@ impl G2dEngine {
                     // Draw the world
                     self.draw(pixels.frame_mut(), &resized_image);
                     // Prepare egui
-                    framework.prepare(&window);
+                    info_span!("egui_prepare").in_scope(|| framework.prepare(&window));
                     // Render everything together
+                    let render_span = info_span!("render").entered();
                     let render_result = pixels.render_with(|encoder, render_target, context| {
                         // Render the world texture
                         context.scaling_renderer.render(encoder, render_target);
@@ -139,8 +637,15 @@ impl G2dEngine {
                         framework.render(encoder, render_target, context);
                         Ok(())
                     });
+                    render_span.exit();
+                    if let Some(profiler) = &self.profiler
+                        && let Ok(mut profiler) = profiler.lock()
+                    {
+                        profiler.end_frame();
+                    }
                     if let Err(err) = render_result {
-                        log_error("pixels.render", err);
+                        error!("pixels.render() failed: {err}");
+                        failure = Some(err.into());
                         elwt.exit();
                     }
                 }
@@ -150,8 +655,15 @@ impl G2dEngine {
                 } => {
                     size = new_size;
                     framework.resize(size.width, size.height);
-                    pixels.resize_surface(size.width, size.height);
-                    pixels.resize_buffer(size.width, size.height);
+                    let resized = pixels
+                        .resize_surface(size.width, size.height)
+                        .and_then(|_| pixels.resize_buffer(size.width, size.height));
+                    if let Err(err) = resized {
+                        error!("pixels resize failed: {err}");
+                        failure = Some(err.into());
+                        elwt.exit();
+                        return;
+                    }
                     resized_image = resize(
                         &self.background,
                         size.width,
@@ -164,14 +676,62 @@ impl G2dEngine {
                 }
                 _ => (),
             }
-        });
-        res.map_err(|e| Error::UserDefined(Box::new(e)))
+        })?;
+        failure.map_or(Ok(()), Err)
     }
 }
 
-fn log_error<E: std::error::Error + 'static>(method_name: &str, err: E) {
-    error!("{method_name}() failed: {err}");
-    for source in err.sources().skip(1) {
-        error!("  Caused by: {source}");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec2;
+    use crate::level::DirLevelSource;
+    use crate::rng::GAMEPLAY_STREAM;
+
+    #[test]
+    fn resume_restores_the_streams_after_the_level_start() {
+        let dir = std::env::temp_dir().join(format!("g2d_resume_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let level = r##"{"name": "one", "tile_size": 16, "tiles": ["#."], "placements": [],
+            "spawn_points": {"start": [0, 0]}, "doors": []}"##;
+        std::fs::write(dir.join("1.json"), level).unwrap();
+        let mut engine = G2dEngine::new(8, 8, RgbaImage::new(8, 8)).unwrap();
+        engine.set_levels(LevelManager::new(DirLevelSource::new(&dir)));
+        engine.set_seed(5);
+        engine.load_level(LevelId(1), "start").unwrap();
+        engine.rng().stream(GAMEPLAY_STREAM).next_u32();
+        let mut save = SaveGame::new("1");
+        save.rng = Some(engine.snapshot_rng());
+        let expected = engine.rng().stream(GAMEPLAY_STREAM).next_u32();
+
+        engine.set_seed(5);
+        engine.resume(LevelId(1), "start", &save).unwrap();
+        assert_eq!(engine.rng().stream(GAMEPLAY_STREAM).next_u32(), expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn moving_sprites_are_drawn_like_a_full_redraw() {
+        let background = RgbaImage::from_fn(64, 48, |x, y| Rgba([x as u8 * 4, y as u8 * 5, 90, 255]));
+        let frames = vec![Arc::new(RgbaImage::from_pixel(8, 8, Rgba([255, 255, 0, 255])))];
+        let engine = |position| {
+            let mut engine = G2dEngine::new(64, 48, background.clone()).unwrap();
+            engine.set_entity_frames("coin", "default", frames.clone());
+            let coin = engine.world().spawn("coin", position);
+            (engine, coin)
+        };
+
+        let (mut moving, coin) = engine(Vec2::new(2., 3.));
+        let mut frame = vec![0; 64 * 48 * 4];
+        moving.draw(&mut frame, &background);
+        for position in [Vec2::new(10., 30.), Vec2::new(60., 44.), Vec2::new(-4., 20.5)] {
+            moving.world().get_mut(coin).unwrap().position = position;
+            moving.draw(&mut frame, &background);
+
+            let (mut redrawn, _) = engine(position);
+            let mut expected = vec![0; 64 * 48 * 4];
+            redrawn.draw(&mut expected, &background);
+            assert!(frame == expected, "frame differs from a full redraw with the coin at {:?}", position);
+        }
     }
 }
@@ -0,0 +1,398 @@
+use crate::controller::{CharacterController, ControllerParams};
+use crate::geometry::Vec2;
+use crate::tilemap::TileMap;
+use crate::world::EntityInstance;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Cost added to every jump, so walking is preferred when it is as short.
+const JUMP_PENALTY: f32 = 1.;
+/// Samples used to check that a jump arc does not go through solid tiles.
+const ARC_SAMPLES: usize = 16;
+
+/// How an agent goes from a cell to the next one.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum LinkKind {
+    Walk,
+    /// Step off a ledge and fall down.
+    Fall,
+    /// Jump with the given horizontal speed, in pixels per second.
+    Jump { speed: f32 },
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct NavLink {
+    pub to: (i32, i32),
+    pub kind: LinkKind,
+    pub cost: f32,
+}
+
+/// Navigation graph of a tile map, for agents one tile wide.
+///
+/// Nodes are the empty cells standing on a solid one. Links are derived from the controller
+/// parameters: walk to a neighbour node, fall off a ledge, or jump along an arc that fits the jump
+/// velocity, gravity and run speed without going through solid tiles.
+pub struct NavGraph {
+    params: ControllerParams,
+    tile_size: f32,
+    /// Agent height in tiles, cells above a node must be empty.
+    agent_height: i32,
+    links: HashMap<(i32, i32), Vec<NavLink>>,
+}
+
+impl NavGraph {
+    pub fn build(map: &TileMap, params: ControllerParams, agent_height: i32) -> Self {
+        let mut graph = Self {
+            params,
+            tile_size: map.tile_size(),
+            agent_height: agent_height.max(1),
+            links: HashMap::new(),
+        };
+        for y in 0..map.height() {
+            for x in 0..map.width() {
+                if graph.standable(map, x, y) {
+                    let links = graph.node_links(map, x, y);
+                    graph.links.insert((x, y), links);
+                }
+            }
+        }
+        graph
+    }
+
+    pub fn is_node(&self, cell: (i32, i32)) -> bool {
+        self.links.contains_key(&cell)
+    }
+
+    pub fn links(&self, cell: (i32, i32)) -> &[NavLink] {
+        self.links.get(&cell).map(|links| links.as_slice()).unwrap_or(&[])
+    }
+
+    fn clear(&self, map: &TileMap, x: i32, y: i32) -> bool {
+        (0..self.agent_height).all(|dy| !map.is_solid(x, y - dy))
+    }
+
+    fn standable(&self, map: &TileMap, x: i32, y: i32) -> bool {
+        self.clear(map, x, y) && map.is_solid(x, y + 1)
+    }
+
+    fn node_links(&self, map: &TileMap, x: i32, y: i32) -> Vec<NavLink> {
+        let mut links = Vec::new();
+        for dir in [-1, 1] {
+            let nx = x + dir;
+            if self.standable(map, nx, y) {
+                links.push(NavLink {
+                    to: (nx, y),
+                    kind: LinkKind::Walk,
+                    cost: 1.,
+                });
+            } else if self.clear(map, nx, y) {
+                // Fall straight down the column next to the ledge.
+                if let Some(ny) = (y + 1..map.height()).find(|ny| self.standable(map, nx, *ny))
+                    && (y + 1..=ny).all(|fy| self.clear(map, nx, fy))
+                {
+                    links.push(NavLink {
+                        to: (nx, ny),
+                        kind: LinkKind::Fall,
+                        cost: 1. + 0.5 * (ny - y) as f32,
+                    });
+                }
+            }
+        }
+        let reach = (self.params.jump_distance() / self.tile_size).ceil() as i32 + 1;
+        let rise = (self.params.jump_height() / self.tile_size).floor() as i32;
+        for dy in -rise..=rise {
+            for dx in (-reach..=reach).filter(|dx| *dx != 0) {
+                let to = (x + dx, y + dy);
+                if (dy == 0 && dx.abs() == 1) || !self.standable(map, to.0, to.1) {
+                    continue;
+                }
+                if let Some(speed) = self.jump_speed(map, (x, y), to) {
+                    links.push(NavLink {
+                        to,
+                        kind: LinkKind::Jump { speed },
+                        cost: dx.abs() as f32 + dy.abs() as f32 + JUMP_PENALTY,
+                    });
+                }
+            }
+        }
+        links
+    }
+
+    /// Horizontal speed of a jump from `from` landing on `to`, if the arc is possible and clear.
+    fn jump_speed(&self, map: &TileMap, from: (i32, i32), to: (i32, i32)) -> Option<f32> {
+        let (v0, g) = (self.params.jump_velocity, self.params.gravity);
+        // Height to go down, negative when landing higher.
+        let drop = (to.1 - from.1) as f32 * self.tile_size;
+        let discriminant = v0 * v0 + 2. * g * drop;
+        if discriminant < 0. {
+            return None;
+        }
+        let flight = (v0 + discriminant.sqrt()) / g;
+        let distance = (to.0 - from.0) as f32 * self.tile_size;
+        let speed = distance / flight;
+        if speed.abs() > self.params.run_speed {
+            return None;
+        }
+        let start = Vec2::new(
+            (from.0 as f32 + 0.5) * self.tile_size,
+            (from.1 + 1) as f32 * self.tile_size - 1.,
+        );
+        let arc_clear = (1..ARC_SAMPLES).all(|i| {
+            let t = flight * i as f32 / ARC_SAMPLES as f32;
+            let feet = start + Vec2::new(speed * t, -v0 * t + 0.5 * g * t * t);
+            let (cx, cy) = map.cell_at(feet);
+            self.clear(map, cx, cy)
+        });
+        arc_clear.then_some(speed)
+    }
+
+    /// Cheapest path between two nodes with A*, `from` excluded. `None` if unreachable.
+    pub fn find_path(&self, from: (i32, i32), to: (i32, i32)) -> Option<Vec<NavStep>> {
+        if !self.is_node(from) || !self.is_node(to) {
+            return None;
+        }
+        let heuristic = |cell: (i32, i32)| ((cell.0 - to.0).abs() + (cell.1 - to.1).abs()) as f32 * 0.5;
+        let mut open = BinaryHeap::new();
+        let mut best: HashMap<(i32, i32), f32> = HashMap::from([(from, 0.)]);
+        let mut came_from: HashMap<(i32, i32), ((i32, i32), LinkKind)> = HashMap::new();
+        open.push(OpenNode {
+            estimate: heuristic(from),
+            cost: 0.,
+            cell: from,
+        });
+        while let Some(OpenNode { cost, cell, .. }) = open.pop() {
+            if cell == to {
+                let mut steps = Vec::new();
+                let mut current = to;
+                while let Some((previous, kind)) = came_from.get(&current) {
+                    steps.push(NavStep { cell: current, kind: *kind });
+                    current = *previous;
+                }
+                steps.reverse();
+                return Some(steps);
+            }
+            if cost > best.get(&cell).copied().unwrap_or(f32::INFINITY) {
+                continue;
+            }
+            for link in self.links(cell) {
+                let next_cost = cost + link.cost;
+                if next_cost < best.get(&link.to).copied().unwrap_or(f32::INFINITY) {
+                    best.insert(link.to, next_cost);
+                    came_from.insert(link.to, (cell, link.kind));
+                    open.push(OpenNode {
+                        estimate: next_cost + heuristic(link.to),
+                        cost: next_cost,
+                        cell: link.to,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// Node the entity stands on, or the nearest node below it.
+    pub fn node_of(&self, entity: &EntityInstance, map: &TileMap) -> Option<(i32, i32)> {
+        let bounds = entity.bounds();
+        let (x, y) = map.cell_at(Vec2::new(bounds.center().x, bounds.bottom() - 1.));
+        (y..map.height()).map(|y| (x, y)).find(|cell| self.is_node(*cell))
+    }
+}
+
+/// Step of a path: the cell to reach and how.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct NavStep {
+    pub cell: (i32, i32),
+    pub kind: LinkKind,
+}
+
+struct OpenNode {
+    estimate: f32,
+    cost: f32,
+    cell: (i32, i32),
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    // Reversed for the max heap, ties broken on the cell so the search is deterministic.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .estimate
+            .total_cmp(&self.estimate)
+            .then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+
+/// Progress of a [`PathFollower`].
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum FollowStatus {
+    Moving,
+    Arrived,
+    /// The agent landed off the path, a new path must be found.
+    Lost,
+}
+
+/// Drives a character controller along a path.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct PathFollower {
+    start: (i32, i32),
+    steps: Vec<NavStep>,
+    index: usize,
+}
+
+impl PathFollower {
+    /// Follow `steps`, found by [`NavGraph::find_path`] from the `start` node.
+    pub fn new(start: (i32, i32), steps: Vec<NavStep>) -> Self {
+        Self {
+            start,
+            steps,
+            index: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.index >= self.steps.len()
+    }
+
+    pub fn next_step(&self) -> Option<&NavStep> {
+        self.steps.get(self.index)
+    }
+
+    /// Cell the current step starts from.
+    fn origin(&self) -> (i32, i32) {
+        match self.index {
+            0 => self.start,
+            i => self.steps[i - 1].cell,
+        }
+    }
+
+    /// Steer toward the next step and move the entity.
+    pub fn update(
+        &mut self,
+        entity: &mut EntityInstance,
+        controller: &mut CharacterController,
+        map: &TileMap,
+        dt: f32,
+    ) -> FollowStatus {
+        let tile = map.tile_size();
+        let center_x = |cell: (i32, i32)| (cell.0 as f32 + 0.5) * tile;
+        let run_speed = controller.params.run_speed;
+        // Horizontal speed reaching `x` without overshooting it.
+        let approach = |entity: &EntityInstance, x: f32| {
+            let dx = x - entity.bounds().center().x;
+            dx.signum() * run_speed.min(dx.abs() / dt.max(0.001))
+        };
+        let feet = |entity: &EntityInstance| {
+            let bounds = entity.bounds();
+            map.cell_at(Vec2::new(bounds.center().x, bounds.bottom() - 1.))
+        };
+
+        while let Some(step) = self.steps.get(self.index).copied() {
+            let centered = (entity.bounds().center().x - center_x(step.cell)).abs() <= tile / 4.;
+            if feet(entity) == step.cell && controller.on_ground && centered {
+                self.index += 1;
+                continue;
+            }
+            let origin = self.origin();
+            let cell = feet(entity);
+            if controller.on_ground && cell != origin && cell != step.cell {
+                return FollowStatus::Lost;
+            }
+            match step.kind {
+                LinkKind::Jump { speed } if controller.on_ground && cell == origin => {
+                    if (entity.bounds().center().x - center_x(origin)).abs() <= 2. {
+                        entity.velocity.x = speed;
+                        controller.jump(entity);
+                    } else {
+                        entity.velocity.x = approach(entity, center_x(origin));
+                    }
+                }
+                LinkKind::Jump { speed } if !controller.on_ground => entity.velocity.x = speed,
+                _ => entity.velocity.x = approach(entity, center_x(step.cell)),
+            }
+            controller.step(entity, map, dt);
+            return FollowStatus::Moving;
+        }
+        entity.velocity.x = 0.;
+        controller.step(entity, map, dt);
+        FollowStatus::Arrived
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::World;
+
+    const TILE: f32 = 32.;
+
+    fn graph(rows: &[&str]) -> (TileMap, NavGraph) {
+        let map = TileMap::from_rows(rows, TILE);
+        let graph = NavGraph::build(&map, ControllerParams::default(), 1);
+        (map, graph)
+    }
+
+    #[test]
+    fn walks_along_a_platform() {
+        let (_, graph) = graph(&["      ", "######"]);
+        let path = graph.find_path((0, 0), (5, 0)).unwrap();
+        assert_eq!(path.len(), 5);
+        assert!(path.iter().all(|step| step.kind == LinkKind::Walk));
+    }
+
+    #[test]
+    fn falls_off_a_ledge() {
+        let (_, graph) = graph(&["     ", "##   ", "     ", "     ", "#####"]);
+        let path = graph.find_path((0, 0), (4, 3)).unwrap();
+        assert!(path.iter().any(|step| step.kind == LinkKind::Fall));
+        // Climbing back needs a jump higher than the controller can do.
+        assert!(graph.find_path((4, 3), (0, 0)).is_none());
+    }
+
+    #[test]
+    fn jumps_over_a_gap() {
+        let (_, graph) = graph(&["        ", "###  ###", "########"]);
+        let path = graph.find_path((0, 0), (7, 0)).unwrap();
+        assert!(path.iter().any(|step| matches!(step.kind, LinkKind::Jump { .. })));
+    }
+
+    #[test]
+    fn unreachable_platform() {
+        let (_, graph) = graph(&["           ", "##        #"]);
+        assert!(graph.find_path((0, 0), (10, 0)).is_none());
+        assert!(graph.find_path((0, 0), (5, 0)).is_none());
+    }
+
+    #[test]
+    fn follower_reaches_the_goal() {
+        let (map, graph) = graph(&["        ", "        ", "###  ###", "########"]);
+        let mut world = World::new();
+        let id = world.spawn("enemy", Vec2::new(0., TILE));
+        let entity = world.get_mut(id).unwrap();
+        let mut controller = CharacterController::new(ControllerParams::default());
+        let start = graph.node_of(entity, &map).unwrap();
+        let steps = graph.find_path(start, (7, 1)).unwrap();
+        let mut follower = PathFollower::new(start, steps);
+        let mut status = FollowStatus::Moving;
+        for _ in 0..600 {
+            status = follower.update(entity, &mut controller, &map, 1. / 60.);
+            if status != FollowStatus::Moving {
+                break;
+            }
+        }
+        assert_eq!(status, FollowStatus::Arrived);
+        assert_eq!(graph.node_of(entity, &map), Some((7, 1)));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::world::World;
+use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Item given by a pickup.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub enum Item {
+    Coin,
+    /// Key opening the doors with the same name, held at most once.
+    Key(String),
+    PowerUp(String),
+}
+
+/// Items collected by a player.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Inventory {
+    counts: BTreeMap<Item, u32>,
+    keys: BTreeSet<String>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `amount` items, keys are only added once whatever the amount.
+    pub fn add(&mut self, item: &Item, amount: u32) {
+        match item {
+            Item::Key(name) => {
+                self.keys.insert(name.clone());
+            }
+            _ => *self.counts.entry(item.clone()).or_default() += amount,
+        }
+    }
+
+    /// Remove `amount` items, `false` if there are not enough of them.
+    pub fn remove(&mut self, item: &Item, amount: u32) -> bool {
+        match item {
+            Item::Key(name) => self.use_key(name),
+            _ => match self.counts.get_mut(item) {
+                Some(count) if *count >= amount => {
+                    *count -= amount;
+                    true
+                }
+                _ => false,
+            },
+        }
+    }
+
+    pub fn count(&self, item: &Item) -> u32 {
+        match item {
+            Item::Key(name) => self.keys.contains(name) as u32,
+            _ => self.counts.get(item).copied().unwrap_or(0),
+        }
+    }
+
+    pub fn coins(&self) -> u32 {
+        self.count(&Item::Coin)
+    }
+
+    pub fn has_key(&self, name: &str) -> bool {
+        self.keys.contains(name)
+    }
+
+    /// Consume a key, `false` if it is not held.
+    pub fn use_key(&mut self, name: &str) -> bool {
+        self.keys.remove(name)
+    }
+
+    /// Held keys, sorted by name.
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(String::as_str)
+    }
+}
+
+/// How a pickup reaches the player.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum PickupBehaviour {
+    /// Collected when the player touches it.
+    Collect,
+    /// Flies toward a player closer than `radius`, at `speed` pixels per second.
+    Magnet { radius: f32, speed: f32 },
+}
+
+/// Item lying in the level.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Pickup {
+    pub item: Item,
+    pub amount: u32,
+    pub behaviour: PickupBehaviour,
+    /// Id stored in the save once collected, so the pickup does not respawn.
+    pub unique_id: Option<String>,
+}
+
+impl Pickup {
+    pub fn new(item: Item, amount: u32) -> Self {
+        Self {
+            item,
+            amount,
+            behaviour: PickupBehaviour::Collect,
+            unique_id: None,
+        }
+    }
+
+    pub fn with_magnet(mut self, radius: f32, speed: f32) -> Self {
+        self.behaviour = PickupBehaviour::Magnet { radius, speed };
+        self
+    }
+
+    pub fn with_unique_id(mut self, unique_id: &str) -> Self {
+        self.unique_id = Some(String::from(unique_id));
+        self
+    }
+}
+
+/// Published when a player collects a pickup.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Collected {
+    pub collector: EntityId,
+    pub item: Item,
+    pub amount: u32,
+    pub unique_id: Option<String>,
+}
+
+/// Pickups of the level and the inventories of the players collecting them.
+#[derive(Default)]
+pub struct Pickups {
+    pickups: BTreeMap<EntityId, Pickup>,
+    inventories: BTreeMap<EntityId, Inventory>,
+}
+
+impl Pickups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, entity: EntityId, pickup: Pickup) {
+        self.pickups.insert(entity, pickup);
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> Option<Pickup> {
+        self.pickups.remove(&entity)
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&Pickup> {
+        self.pickups.get(&entity)
+    }
+
+    /// Let an entity collect pickups, with an empty inventory if it had none.
+    pub fn add_collector(&mut self, entity: EntityId) -> &mut Inventory {
+        self.inventories.entry(entity).or_default()
+    }
+
+    pub fn inventory(&self, collector: EntityId) -> Option<&Inventory> {
+        self.inventories.get(&collector)
+    }
+
+    pub fn inventory_mut(&mut self, collector: EntityId) -> Option<&mut Inventory> {
+        self.inventories.get_mut(&collector)
+    }
+
+    /// Remove the pickups of the level, the inventories are kept.
+    pub fn clear(&mut self) {
+        self.pickups.clear();
+    }
+
+    /// Move the magnet pickups, then collect the ones touching a collector. A pickup touching
+    /// several collectors goes to the one with the lowest id.
+    pub fn update(&mut self, world: &mut World, bus: &mut EventBus, dt: f32) {
+        self.pickups.retain(|id, _| world.get(*id).is_some());
+        let collectors: Vec<_> = self
+            .inventories
+            .keys()
+            .filter_map(|id| world.get(*id))
+            .map(|entity| (entity.id, entity.bounds()))
+            .collect();
+        let mut collected = Vec::new();
+        for (id, pickup) in self.pickups.iter() {
+            let entity = world.get_mut(*id).unwrap();
+            if let PickupBehaviour::Magnet { radius, speed } = pickup.behaviour {
+                let center = entity.bounds().center();
+                let nearest = collectors
+                    .iter()
+                    .map(|(_, bounds)| bounds.center())
+                    .filter(|target| target.distance(center) <= radius)
+                    .min_by(|a, b| a.distance(center).total_cmp(&b.distance(center)));
+                if let Some(target) = nearest {
+                    let distance = target.distance(center).max(0.001);
+                    entity.position += (target - center) * ((speed * dt).min(distance) / distance);
+                }
+            }
+            let bounds = entity.bounds();
+            if let Some((collector, _)) = collectors.iter().find(|(_, other)| other.intersects(&bounds)) {
+                collected.push((*id, *collector));
+            }
+        }
+        for (id, collector) in collected {
+            let pickup = self.pickups.remove(&id).unwrap();
+            world.despawn(id);
+            self.inventories.get_mut(&collector).unwrap().add(&pickup.item, pickup.amount);
+            info!("PICKUP:{:?} collected {} {:?}", collector, pickup.amount, pickup.item);
+            bus.publish(Collected {
+                collector,
+                item: pickup.item,
+                amount: pickup.amount,
+                unique_id: pickup.unique_id,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Vec2;
+
+    #[test]
+    fn inventory_counts_items_and_holds_keys_once() {
+        let mut inventory = Inventory::new();
+        inventory.add(&Item::Coin, 3);
+        inventory.add(&Item::Coin, 2);
+        inventory.add(&Item::Key(String::from("tour")), 2);
+        inventory.add(&Item::Key(String::from("cave")), 1);
+        assert_eq!(inventory.coins(), 5);
+        assert_eq!(inventory.count(&Item::Key(String::from("tour"))), 1);
+        assert_eq!(inventory.keys().collect::<Vec<_>>(), ["cave", "tour"]);
+
+        assert!(!inventory.remove(&Item::Coin, 6));
+        assert!(inventory.remove(&Item::Coin, 4));
+        assert_eq!(inventory.coins(), 1);
+        assert!(!inventory.remove(&Item::PowerUp(String::from("saut")), 1));
+        assert!(inventory.use_key("cave"));
+        assert!(!inventory.use_key("cave"));
+        assert!(!inventory.has_key("cave"));
+        assert!(inventory.has_key("tour"));
+    }
+
+    #[test]
+    fn magnet_pulls_toward_the_nearest_collector() {
+        let mut world = World::new();
+        let mut bus = EventBus::new();
+        let mut pickups = Pickups::new();
+        let far = world.spawn("joueur", Vec2::new(-150., 0.));
+        let near = world.spawn("joueur", Vec2::new(100., 0.));
+        let coin = world.spawn("piece", Vec2::new(0., 0.));
+        pickups.add_collector(far);
+        pickups.add_collector(near);
+        pickups.add(coin, Pickup::new(Item::Coin, 1).with_magnet(200., 50.));
+
+        pickups.update(&mut world, &mut bus, 0.1);
+        assert_eq!(world.get(coin).unwrap().position, Vec2::new(5., 0.));
+        // Out of reach, a magnet pickup stays where it is.
+        world.get_mut(near).unwrap().position = Vec2::new(400., 0.);
+        world.get_mut(far).unwrap().position = Vec2::new(-400., 0.);
+        pickups.update(&mut world, &mut bus, 0.1);
+        assert_eq!(world.get(coin).unwrap().position, Vec2::new(5., 0.));
+    }
+
+    #[test]
+    fn touching_pickup_goes_to_the_lowest_collector_id() {
+        let mut world = World::new();
+        let mut bus = EventBus::new();
+        let mut pickups = Pickups::new();
+        let first = world.spawn("joueur", Vec2::new(10., 0.));
+        let second = world.spawn("joueur", Vec2::new(-10., 0.));
+        let key = world.spawn("cle", Vec2::new(0., 0.));
+        pickups.add_collector(second);
+        pickups.add_collector(first);
+        pickups.add(key, Pickup::new(Item::Key(String::from("cave")), 1).with_unique_id("cle_cave"));
+
+        pickups.update(&mut world, &mut bus, 0.1);
+        bus.dispatch();
+        assert!(world.get(key).is_none());
+        assert!(pickups.get(key).is_none());
+        assert!(pickups.inventory(first).unwrap().has_key("cave"));
+        assert!(!pickups.inventory(second).unwrap().has_key("cave"));
+        assert_eq!(
+            bus.read::<Collected>(),
+            [Collected {
+                collector: first,
+                item: Item::Key(String::from("cave")),
+                amount: 1,
+                unique_id: Some(String::from("cle_cave")),
+            }]
+        );
+    }
+
+    #[test]
+    fn despawned_pickups_are_forgotten() {
+        let mut world = World::new();
+        let mut bus = EventBus::new();
+        let mut pickups = Pickups::new();
+        let player = world.spawn("joueur", Vec2::new(0., 0.));
+        let coin = world.spawn("piece", Vec2::new(0., 0.));
+        pickups.add_collector(player);
+        pickups.add(coin, Pickup::new(Item::Coin, 1));
+        world.despawn(coin);
+        pickups.update(&mut world, &mut bus, 0.1);
+        bus.dispatch();
+        assert!(pickups.get(coin).is_none());
+        assert_eq!(pickups.inventory(player).unwrap().coins(), 0);
+        assert!(bus.read::<Collected>().is_empty());
+    }
+}
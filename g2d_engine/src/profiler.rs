@@ -0,0 +1,268 @@
+use serde_json::json;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::span::Id;
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Frames kept in the timing history of each system.
+pub const HISTORY_LEN: usize = 240;
+/// Spans kept for the Chrome trace, the oldest are dropped past it.
+pub const MAX_TRACE_EVENTS: usize = 200_000;
+
+/// Profiler shared between the engine and the tracing layer feeding it.
+pub type SharedProfiler = Arc<Mutex<FrameProfiler>>;
+
+/// One run of a span, in time since the profiler creation.
+#[derive(PartialEq, Debug, Clone)]
+pub struct SpanRecord {
+    pub name: &'static str,
+    pub start: Duration,
+    pub duration: Duration,
+    pub thread: u64,
+}
+
+/// Time spent in each traced system, frame after frame.
+///
+/// Systems are the names of the tracing spans, like `update` or `ai`. The time of a span includes
+/// the time of the spans entered inside it.
+pub struct FrameProfiler {
+    epoch: Instant,
+    current: HashMap<&'static str, Duration>,
+    histories: BTreeMap<&'static str, VecDeque<Duration>>,
+    trace: VecDeque<SpanRecord>,
+    frames: u64,
+}
+
+impl Default for FrameProfiler {
+    fn default() -> Self {
+        Self {
+            epoch: Instant::now(),
+            current: HashMap::new(),
+            histories: BTreeMap::new(),
+            trace: VecDeque::new(),
+            frames: 0,
+        }
+    }
+}
+
+impl FrameProfiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shared() -> SharedProfiler {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    /// Add a run of a span to the current frame.
+    pub fn record(&mut self, name: &'static str, start: Instant, duration: Duration, thread: u64) {
+        *self.current.entry(name).or_default() += duration;
+        if self.trace.len() == MAX_TRACE_EVENTS {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(SpanRecord {
+            name,
+            start: start.saturating_duration_since(self.epoch),
+            duration,
+            thread,
+        });
+    }
+
+    /// Close the current frame, appending its times to the histories. Systems which did not run
+    /// during the frame get a zero time.
+    pub fn end_frame(&mut self) {
+        for name in self.current.keys() {
+            self.histories.entry(name).or_default();
+        }
+        for (name, history) in self.histories.iter_mut() {
+            if history.len() == HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(self.current.remove(name).unwrap_or_default());
+        }
+        self.frames += 1;
+    }
+
+    /// Frames closed since the profiler creation.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn systems(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.histories.keys().copied()
+    }
+
+    /// Times of the last frames of a system, oldest first.
+    pub fn history(&self, system: &str) -> Option<&VecDeque<Duration>> {
+        self.histories.get(system)
+    }
+
+    pub fn average(&self, system: &str) -> Option<Duration> {
+        let history = self.history(system)?;
+        let total: Duration = history.iter().sum();
+        Some(total / history.len().max(1) as u32)
+    }
+
+    pub fn max(&self, system: &str) -> Option<Duration> {
+        self.history(system)?.iter().max().copied()
+    }
+
+    /// Span runs kept for the trace, oldest first.
+    pub fn trace(&self) -> &VecDeque<SpanRecord> {
+        &self.trace
+    }
+
+    /// Trace in the Chrome trace event format, readable by `chrome://tracing` and Perfetto.
+    pub fn chrome_trace(&self) -> serde_json::Value {
+        let events: Vec<serde_json::Value> = self
+            .trace
+            .iter()
+            .map(|record| {
+                json!({
+                    "name": record.name,
+                    "cat": "g2d",
+                    "ph": "X",
+                    "ts": record.start.as_secs_f64() * 1e6,
+                    "dur": record.duration.as_secs_f64() * 1e6,
+                    "pid": 1,
+                    "tid": record.thread,
+                })
+            })
+            .collect();
+        json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+    }
+
+    pub fn export_chrome_trace(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.chrome_trace().to_string())
+    }
+}
+
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static THREAD: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Small number identifying the current thread in the trace.
+fn thread_number() -> u64 {
+    THREAD.with(|thread| {
+        if thread.get() == 0 {
+            thread.set(NEXT_THREAD.fetch_add(1, Ordering::Relaxed));
+        }
+        thread.get()
+    })
+}
+
+/// Start of the current run of a span, kept in the span extensions.
+struct Entered(Instant);
+
+/// Tracing layer timing the spans into a [`FrameProfiler`].
+pub struct ProfilerLayer {
+    profiler: SharedProfiler,
+}
+
+impl ProfilerLayer {
+    pub fn new(profiler: SharedProfiler) -> Self {
+        Self { profiler }
+    }
+}
+
+impl<S> Layer<S> for ProfilerLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().replace(Entered(Instant::now()));
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let Some(Entered(start)) = span.extensions_mut().remove::<Entered>() else {
+            return;
+        };
+        if let Ok(mut profiler) = self.profiler.lock() {
+            profiler.record(span.name(), start, start.elapsed(), thread_number());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn systems_missing_from_a_frame_get_a_zero_time() {
+        let mut profiler = FrameProfiler::new();
+        let now = Instant::now();
+        profiler.record("update", now, ms(2), 1);
+        profiler.record("update", now, ms(3), 1);
+        profiler.end_frame();
+        profiler.record("ai", now, ms(4), 1);
+        profiler.end_frame();
+        assert_eq!(profiler.frames(), 2);
+        assert_eq!(profiler.systems().collect::<Vec<_>>(), ["ai", "update"]);
+        assert_eq!(profiler.history("update"), Some(&VecDeque::from([ms(5), ms(0)])));
+        // A system seen late starts its history at the frame it first ran.
+        assert_eq!(profiler.history("ai"), Some(&VecDeque::from([ms(4)])));
+        assert_eq!(profiler.history("render"), None);
+    }
+
+    #[test]
+    fn history_keeps_the_last_frames() {
+        let mut profiler = FrameProfiler::new();
+        for i in 0..HISTORY_LEN as u64 + 10 {
+            profiler.record("update", Instant::now(), ms(i), 1);
+            profiler.end_frame();
+        }
+        let history = profiler.history("update").unwrap();
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history.front(), Some(&ms(10)));
+        assert_eq!(history.back(), Some(&ms(HISTORY_LEN as u64 + 9)));
+    }
+
+    #[test]
+    fn average_and_max_cover_the_window() {
+        let mut profiler = FrameProfiler::new();
+        for millis in [2, 6, 1, 3] {
+            profiler.record("render", Instant::now(), ms(millis), 1);
+            profiler.end_frame();
+        }
+        assert_eq!(profiler.average("render"), Some(ms(3)));
+        assert_eq!(profiler.max("render"), Some(ms(6)));
+        assert_eq!(profiler.average("ai"), None);
+        assert_eq!(profiler.max("ai"), None);
+    }
+
+    #[test]
+    fn chrome_trace_has_complete_events_in_microseconds() {
+        let mut profiler = FrameProfiler::new();
+        let start = profiler.epoch + Duration::from_micros(1500);
+        profiler.record("update", start, Duration::from_micros(250), 3);
+        let trace = profiler.chrome_trace();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event["name"], "update");
+        assert_eq!(event["ph"], "X");
+        assert_eq!(event["ts"].as_f64(), Some(1500.));
+        assert_eq!(event["dur"].as_f64(), Some(250.));
+        assert_eq!(event["tid"], 3);
+        assert_eq!(event["pid"], 1);
+        assert_eq!(trace["displayTimeUnit"], "ms");
+    }
+}
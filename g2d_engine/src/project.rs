@@ -0,0 +1,25 @@
+use crate::error::G2dError;
+use gwen2d_project::db::{engine_db, project_repository};
+use log::info;
+use std::path::Path;
+
+pub use gwen2d_project::model::project::Project;
+
+/// Read the entities of a project made in the editor, read-only. The project is named after its
+/// database file, like in the editor.
+pub fn load_project(db_path: &Path) -> Result<Project, G2dError> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| G2dError::Config(e.to_string()))?;
+    let data = runtime.block_on(async {
+        let pool = engine_db::connect_read_only(db_path).await?;
+        let data = project_repository::load(&pool).await;
+        pool.close().await;
+        data
+    })?;
+    let name = db_path.file_stem().unwrap_or_default().to_string_lossy();
+    let project = data.into_project(&name)?;
+    info!("project {} loaded from {}", name, db_path.display());
+    Ok(project)
+}
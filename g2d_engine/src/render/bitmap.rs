@@ -0,0 +1,186 @@
+use image::RgbaImage;
+
+/// Digits 0 to 9 of a 3x5 pixel font, one row per byte, highest of the 3 low bits on the left.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Rectangle of frame pixels.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub struct PixelRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl PixelRect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width as i32
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height as i32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.right() && y >= self.y && y < self.bottom()
+    }
+
+    /// Overlapping part of both rectangles, empty if they do not overlap.
+    pub fn intersection(&self, other: &PixelRect) -> PixelRect {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (right, bottom) = (self.right().min(other.right()), self.bottom().min(other.bottom()));
+        PixelRect::new(x, y, (right - x).max(0) as u32, (bottom - y).max(0) as u32)
+    }
+
+    /// Smallest rectangle containing both.
+    pub fn union(&self, other: &PixelRect) -> PixelRect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let (right, bottom) = (self.right().max(other.right()), self.bottom().max(other.bottom()));
+        PixelRect::new(x, y, (right - x) as u32, (bottom - y) as u32)
+    }
+}
+
+/// RGBA pixels drawn into by the bitmap renderer: a whole frame, or a band of its rows.
+/// Coordinates are frame coordinates and drawing outside of the clip rectangle is ignored.
+pub struct Canvas<'a> {
+    pixels: &'a mut [u8],
+    width: u32,
+    /// Frame row of the first row of `pixels`.
+    origin_y: i32,
+    clip: PixelRect,
+}
+
+impl<'a> Canvas<'a> {
+    pub fn new(frame: &'a mut [u8], width: u32, height: u32) -> Self {
+        Self::band(frame, width, 0, height)
+    }
+
+    /// Canvas over `height` rows of a frame `width` pixels wide, starting at frame row `y`.
+    pub fn band(rows: &'a mut [u8], width: u32, y: i32, height: u32) -> Self {
+        Self {
+            pixels: rows,
+            width,
+            origin_y: y,
+            clip: PixelRect::new(0, y, width, height),
+        }
+    }
+
+    /// Restrict the drawing to a part of the canvas.
+    pub fn with_clip(mut self, clip: PixelRect) -> Self {
+        self.clip = self.clip.intersection(&clip);
+        self
+    }
+
+    /// Area that can be drawn, in frame coordinates.
+    pub fn clip(&self) -> PixelRect {
+        self.clip
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        (((y - self.origin_y) as u32 * self.width + x as u32) * 4) as usize
+    }
+
+    /// Blend a pixel over the canvas.
+    pub fn blend(&mut self, x: i32, y: i32, color: [u8; 4]) {
+        if !self.clip.contains(x, y) || color[3] == 0 {
+            return;
+        }
+        let i = self.index(x, y);
+        let alpha = color[3] as u32;
+        for (under, over) in self.pixels[i..i + 3].iter_mut().zip(color) {
+            *under = ((over as u32 * alpha + *under as u32 * (255 - alpha)) / 255) as u8;
+        }
+        self.pixels[i + 3] = 255;
+    }
+
+    /// Draw an image with its top left corner at `x`, `y`, blending its alpha.
+    pub fn blit(&mut self, image: &RgbaImage, x: i32, y: i32) {
+        let area = PixelRect::new(x, y, image.width(), image.height()).intersection(&self.clip);
+        for py in area.y..area.bottom() {
+            for px in area.x..area.right() {
+                let pixel = image.get_pixel((px - x) as u32, (py - y) as u32).0;
+                if pixel[3] == 255 {
+                    let i = self.index(px, py);
+                    self.pixels[i..i + 4].copy_from_slice(&pixel);
+                } else {
+                    self.blend(px, py, pixel);
+                }
+            }
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: [u8; 4]) {
+        let area = PixelRect::new(x, y, width, height).intersection(&self.clip);
+        for py in area.y..area.bottom() {
+            for px in area.x..area.right() {
+                self.blend(px, py, color);
+            }
+        }
+    }
+
+    /// Draw a number with the built-in digit font, each font pixel being `scale` pixels wide.
+    /// Returns the width drawn.
+    pub fn draw_number(&mut self, value: u32, x: i32, y: i32, scale: u32, color: [u8; 4]) -> u32 {
+        let mut cursor = x;
+        for digit in value.to_string().bytes().map(|b| (b - b'0') as usize) {
+            for (row, bits) in DIGITS[digit].iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) != 0 {
+                        let px = cursor + (col * scale) as i32;
+                        let py = y + (row as u32 * scale) as i32;
+                        self.fill_rect(px, py, scale, scale, color);
+                    }
+                }
+            }
+            cursor += (4 * scale) as i32;
+        }
+        (cursor - x) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digit_glyphs_are_distinct() {
+        for (i, glyph) in DIGITS.iter().enumerate() {
+            for (j, other) in DIGITS.iter().enumerate().skip(i + 1) {
+                assert_ne!(glyph, other, "digits {} and {} look the same", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn draw_number_writes_the_glyph_pixels() {
+        let mut frame = vec![0; 8 * 5 * 4];
+        let width = Canvas::new(&mut frame, 8, 5).draw_number(8, 0, 0, 1, [255, 255, 255, 255]);
+        assert_eq!(width, 4);
+        let lit = |x: usize, y: usize| frame[(y * 8 + x) * 4 + 3] == 255;
+        assert!(lit(0, 3), "the lower left bar of an 8 is missing");
+        assert!(!lit(1, 1) && !lit(1, 3));
+    }
+}
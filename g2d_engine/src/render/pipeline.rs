@@ -0,0 +1,326 @@
+use crate::render::bitmap::{Canvas, PixelRect};
+use crate::tilemap::TileMap;
+use image::RgbaImage;
+use rayon::prelude::*;
+use std::sync::Arc;
+
+/// Rows rasterized together by one task.
+pub const DEFAULT_BAND_HEIGHT: u32 = 32;
+
+/// Part of the scene drawn by the pipeline. Layers are drawn band by band, possibly from several
+/// threads at once, so `draw` must only touch the canvas it is given.
+pub trait Layer: Send + Sync {
+    fn draw(&self, canvas: &mut Canvas);
+}
+
+/// Image drawn at a fixed position, like a background.
+pub struct ImageLayer {
+    pub image: RgbaImage,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl ImageLayer {
+    pub fn new(image: RgbaImage) -> Self {
+        Self { image, x: 0, y: 0 }
+    }
+}
+
+impl Layer for ImageLayer {
+    fn draw(&self, canvas: &mut Canvas) {
+        canvas.blit(&self.image, self.x, self.y);
+    }
+}
+
+/// Solid tiles of a tile map, filled with a color or drawn with a tile image.
+pub struct TileLayer {
+    pub map: TileMap,
+    pub color: [u8; 4],
+    pub tile: Option<RgbaImage>,
+}
+
+impl Layer for TileLayer {
+    fn draw(&self, canvas: &mut Canvas) {
+        let size = self.map.tile_size();
+        let clip = canvas.clip();
+        let (y0, y1) = ((clip.y as f32 / size).floor() as i32, (clip.bottom() as f32 / size).ceil() as i32);
+        let (x0, x1) = ((clip.x as f32 / size).floor() as i32, (clip.right() as f32 / size).ceil() as i32);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                if !self.map.is_solid(x, y) {
+                    continue;
+                }
+                let (px, py) = ((x as f32 * size) as i32, (y as f32 * size) as i32);
+                match &self.tile {
+                    Some(tile) => canvas.blit(tile, px, py),
+                    None => canvas.fill_rect(px, py, size as u32, size as u32, self.color),
+                }
+            }
+        }
+    }
+}
+
+/// Image drawn at a position of the frame.
+#[derive(Clone)]
+pub struct Sprite {
+    pub image: Arc<RgbaImage>,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Sprite {
+    pub fn bounds(&self) -> PixelRect {
+        PixelRect::new(self.x, self.y, self.image.width(), self.image.height())
+    }
+}
+
+/// Sprites drawn in order, rebuilt every frame.
+#[derive(Default)]
+pub struct SpriteLayer {
+    pub sprites: Vec<Sprite>,
+}
+
+impl Layer for SpriteLayer {
+    fn draw(&self, canvas: &mut Canvas) {
+        let clip = canvas.clip();
+        for sprite in self.sprites.iter() {
+            if !sprite.bounds().intersection(&clip).is_empty() {
+                canvas.blit(&sprite.image, sprite.x, sprite.y);
+            }
+        }
+    }
+}
+
+/// Areas of the frame to redraw on the next render.
+#[derive(Default)]
+pub struct DirtyRegions {
+    rects: Vec<PixelRect>,
+    all: bool,
+}
+
+impl DirtyRegions {
+    pub fn mark(&mut self, rect: PixelRect) {
+        if !self.all && !rect.is_empty() {
+            self.rects.push(rect);
+        }
+    }
+
+    pub fn mark_all(&mut self) {
+        self.all = true;
+        self.rects.clear();
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.all
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.all && self.rects.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.all = false;
+        self.rects.clear();
+    }
+
+    /// Part of a band to redraw: the union of the dirty rectangles overlapping it.
+    pub fn in_band(&self, band: &PixelRect) -> Option<PixelRect> {
+        if self.all {
+            return Some(*band);
+        }
+        self.rects
+            .iter()
+            .map(|rect| rect.intersection(band))
+            .filter(|rect| !rect.is_empty())
+            .reduce(|a, b| a.union(&b))
+    }
+}
+
+/// Software renderer of the frame.
+///
+/// Static layers are drawn once into a cached buffer, redrawn only when invalidated. Each render
+/// copies the cached pixels of the dirty regions back into the frame and draws the dynamic layers
+/// over them, band of rows by band of rows on the rayon thread pool. Rows outside of the dirty
+/// regions are left untouched, so the frame must keep the previous render between two calls.
+pub struct RenderPipeline {
+    width: u32,
+    height: u32,
+    band_height: u32,
+    parallel: bool,
+    static_layers: Vec<Box<dyn Layer>>,
+    cache: Vec<u8>,
+    cache_valid: bool,
+    dirty: DirtyRegions,
+}
+
+impl RenderPipeline {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            band_height: DEFAULT_BAND_HEIGHT,
+            parallel: true,
+            static_layers: Vec::new(),
+            cache: vec![0; (width * height * 4) as usize],
+            cache_valid: false,
+            dirty: DirtyRegions::default(),
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn set_band_height(&mut self, band_height: u32) {
+        self.band_height = band_height.max(1);
+    }
+
+    /// Rasterize the bands on the rayon thread pool, or one after the other.
+    pub fn set_parallel(&mut self, parallel: bool) {
+        self.parallel = parallel;
+    }
+
+    /// Add a layer drawn over the previous static layers.
+    pub fn add_static_layer(&mut self, layer: impl Layer + 'static) {
+        self.static_layers.push(Box::new(layer));
+        self.invalidate_static();
+    }
+
+    pub fn clear_static_layers(&mut self) {
+        self.static_layers.clear();
+        self.invalidate_static();
+    }
+
+    /// Redraw the static layers and the whole frame on the next render, after a static layer
+    /// changed.
+    pub fn invalidate_static(&mut self) {
+        self.cache_valid = false;
+        self.dirty.mark_all();
+    }
+
+    pub fn mark_dirty(&mut self, rect: PixelRect) {
+        self.dirty.mark(rect);
+    }
+
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty.mark_all();
+    }
+
+    fn band_rect(&self, index: usize) -> PixelRect {
+        let y = index as u32 * self.band_height;
+        PixelRect::new(0, y as i32, self.width, self.band_height.min(self.height - y))
+    }
+
+    /// Draw the dirty regions of the frame, `dynamic` layers over the static ones.
+    pub fn render(&mut self, frame: &mut [u8], dynamic: &[&dyn Layer]) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let row_bytes = (self.width * 4) as usize;
+        let band_bytes = row_bytes * self.band_height as usize;
+        if !self.cache_valid {
+            let layers = &self.static_layers;
+            let (width, band_height) = (self.width, self.band_height);
+            let draw_static = |(index, rows): (usize, &mut [u8])| {
+                rows.fill(0);
+                let y = (index as u32 * band_height) as i32;
+                let mut canvas = Canvas::band(rows, width, y, (rows.len() / row_bytes) as u32);
+                for layer in layers.iter() {
+                    layer.draw(&mut canvas);
+                }
+            };
+            if self.parallel {
+                self.cache.par_chunks_mut(band_bytes).enumerate().for_each(draw_static);
+            } else {
+                self.cache.chunks_mut(band_bytes).enumerate().for_each(draw_static);
+            }
+            self.cache_valid = true;
+        }
+        if self.dirty.is_empty() {
+            return;
+        }
+
+        let bands: Vec<(usize, PixelRect)> = (0..self.height.div_ceil(self.band_height) as usize)
+            .filter_map(|index| self.dirty.in_band(&self.band_rect(index)).map(|area| (index, area)))
+            .collect();
+        let (width, cache) = (self.width, &self.cache);
+        let draw = |(rows, area): (&mut [u8], PixelRect)| {
+            let band_y = area.y - area.y % self.band_height as i32;
+            let (x0, x1) = (area.x as usize * 4, area.right() as usize * 4);
+            for y in area.y..area.bottom() {
+                let start = y as usize * row_bytes;
+                let local = (y - band_y) as usize * row_bytes;
+                rows[local + x0..local + x1].copy_from_slice(&cache[start + x0..start + x1]);
+            }
+            let mut canvas = Canvas::band(rows, width, band_y, (rows.len() / row_bytes) as u32).with_clip(area);
+            for layer in dynamic {
+                layer.draw(&mut canvas);
+            }
+        };
+        let mut chunks: Vec<Option<&mut [u8]>> = frame.chunks_mut(band_bytes).map(Some).collect();
+        let work: Vec<(&mut [u8], PixelRect)> =
+            bands.iter().filter_map(|(index, area)| chunks[*index].take().map(|rows| (rows, *area))).collect();
+        if self.parallel {
+            work.into_par_iter().for_each(draw);
+        } else {
+            work.into_iter().for_each(draw);
+        }
+        self.dirty.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const WIDTH: u32 = 40;
+    const HEIGHT: u32 = 30;
+
+    fn pipeline() -> RenderPipeline {
+        let mut pipeline = RenderPipeline::new(WIDTH, HEIGHT);
+        pipeline.set_band_height(8);
+        pipeline.add_static_layer(ImageLayer::new(RgbaImage::from_fn(WIDTH, HEIGHT, |x, y| {
+            Rgba([x as u8 * 6, y as u8 * 8, 40, 255])
+        })));
+        pipeline
+    }
+
+    fn sprite(x: i32, y: i32) -> Sprite {
+        Sprite {
+            image: Arc::new(RgbaImage::from_pixel(6, 5, Rgba([255, 0, 255, 255]))),
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn dirty_renders_match_a_full_redraw() {
+        let mut incremental = pipeline();
+        let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        let mut previous = sprite(0, 0);
+        incremental.render(&mut frame, &[&SpriteLayer { sprites: vec![previous.clone()] }]);
+        for (x, y) in [(3, 7), (20, 6), (37, 27), (-3, -2), (15, 15)] {
+            let moved = sprite(x, y);
+            incremental.mark_dirty(previous.bounds());
+            incremental.mark_dirty(moved.bounds().intersection(&PixelRect::new(0, 0, WIDTH, HEIGHT)));
+            let layer = SpriteLayer { sprites: vec![moved.clone()] };
+            incremental.render(&mut frame, &[&layer]);
+
+            let mut full = pipeline();
+            full.set_parallel(false);
+            let mut expected = vec![0; (WIDTH * HEIGHT * 4) as usize];
+            full.render(&mut expected, &[&layer]);
+            assert!(frame == expected, "sprite at {}, {}", x, y);
+            previous = moved;
+        }
+    }
+
+    #[test]
+    fn empty_pipeline_renders_nothing() {
+        let mut pipeline = RenderPipeline::new(0, 4);
+        pipeline.mark_all_dirty();
+        pipeline.render(&mut [], &[]);
+        RenderPipeline::new(4, 0).render(&mut [], &[]);
+    }
+}
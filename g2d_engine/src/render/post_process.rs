@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// RGB color used by the post-process effects.
+pub type Color = [u8; 3];
+
+/// A full screen effect applied on the logical RGBA frame once the world is drawn.
+pub trait PostEffect {
+    /// Advance the effect clock.
+    fn update(&mut self, _dt: Duration) {}
+
+    /// Apply the effect on a `width` x `height` RGBA frame.
+    fn apply(&mut self, frame: &mut [u8], width: u32, height: u32);
+
+    /// An effect returning `true` is dropped from the stack on the next update.
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+/// Handle of an effect pushed on a [`PostProcess`] stack.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct EffectId(u32);
+
+/// Ordered stack of post-process effects, applied one after the other.
+#[derive(Default)]
+pub struct PostProcess {
+    next_id: u32,
+    effects: Vec<(EffectId, Box<dyn PostEffect>)>,
+}
+
+impl PostProcess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push an effect on top of the stack. The returned handle removes it.
+    pub fn push<E: PostEffect + 'static>(&mut self, effect: E) -> EffectId {
+        let id = EffectId(self.next_id);
+        self.next_id += 1;
+        self.effects.push((id, Box::new(effect)));
+        id
+    }
+
+    /// Remove one effect, returns `false` if it already finished or was removed.
+    pub fn remove(&mut self, id: EffectId) -> bool {
+        let len = self.effects.len();
+        self.effects.retain(|(effect_id, _)| *effect_id != id);
+        self.effects.len() != len
+    }
+
+    /// Remove every effect.
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    /// Advance all effects and drop the finished ones.
+    pub fn update(&mut self, dt: Duration) {
+        for (_, effect) in self.effects.iter_mut() {
+            effect.update(dt);
+        }
+        self.effects.retain(|(_, effect)| !effect.finished());
+    }
+
+    /// Apply all effects in push order.
+    pub fn apply(&mut self, frame: &mut [u8], width: u32, height: u32) {
+        for (_, effect) in self.effects.iter_mut() {
+            effect.apply(frame, width, height);
+        }
+    }
+}
+
+/// Linear progress of a timed effect, between 0 and 1.
+fn progress(elapsed: Duration, duration: Duration) -> f32 {
+    if duration.is_zero() {
+        1.
+    } else {
+        (elapsed.as_secs_f32() / duration.as_secs_f32()).min(1.)
+    }
+}
+
+fn blend(dst: &mut [u8], color: Color, alpha: f32) {
+    for (channel, value) in dst.iter_mut().take(3).zip(color) {
+        *channel = (*channel as f32 + (value as f32 - *channel as f32) * alpha).round() as u8;
+    }
+}
+
+/// Fade the frame to or from a solid color.
+pub struct Fade {
+    pub color: Color,
+    pub from: f32,
+    pub to: f32,
+    pub duration: Duration,
+    elapsed: Duration,
+}
+
+impl Fade {
+    pub fn new(color: Color, from: f32, to: f32, duration: Duration) -> Self {
+        Self {
+            color,
+            from,
+            to,
+            duration,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// Fade from the frame to `color`. The frame stays covered until the effect is removed.
+    pub fn to_color(color: Color, duration: Duration) -> Self {
+        Self::new(color, 0., 1., duration)
+    }
+
+    /// Fade from `color` to the frame. The effect is dropped once done.
+    pub fn from_color(color: Color, duration: Duration) -> Self {
+        Self::new(color, 1., 0., duration)
+    }
+
+    pub fn opacity(&self) -> f32 {
+        let t = progress(self.elapsed, self.duration);
+        self.from + (self.to - self.from) * t
+    }
+}
+
+impl PostEffect for Fade {
+    fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    fn apply(&mut self, frame: &mut [u8], _width: u32, _height: u32) {
+        let alpha = self.opacity();
+        if alpha <= 0. {
+            return;
+        }
+        for pixel in frame.chunks_exact_mut(4) {
+            blend(pixel, self.color, alpha);
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration && self.to <= 0.
+    }
+}
+
+/// Circle shaped wipe, everything outside the circle is filled with `color`.
+pub struct IrisWipe {
+    pub color: Color,
+    /// Circle center, in frame relative coordinates (0..1).
+    pub center: (f32, f32),
+    /// `true` shrinks the circle down to nothing, `false` opens it.
+    pub closing: bool,
+    pub duration: Duration,
+    elapsed: Duration,
+}
+
+impl IrisWipe {
+    pub fn close(color: Color, center: (f32, f32), duration: Duration) -> Self {
+        Self {
+            color,
+            center,
+            closing: true,
+            duration,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn open(color: Color, center: (f32, f32), duration: Duration) -> Self {
+        Self {
+            closing: false,
+            ..Self::close(color, center, duration)
+        }
+    }
+}
+
+impl PostEffect for IrisWipe {
+    fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    fn apply(&mut self, frame: &mut [u8], width: u32, height: u32) {
+        let t = progress(self.elapsed, self.duration);
+        let openness = if self.closing { 1. - t } else { t };
+        let cx = self.center.0 * width as f32;
+        let cy = self.center.1 * height as f32;
+        // Farthest corner distance, so a fully opened iris covers the whole frame.
+        let max_radius = [(0., 0.), (width as f32, 0.), (0., height as f32), (width as f32, height as f32)]
+            .iter()
+            .map(|(x, y)| ((x - cx).powi(2) + (y - cy).powi(2)).sqrt())
+            .fold(0., f32::max);
+        let radius = max_radius * openness;
+        let radius_sq = radius * radius;
+        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+            let x = (i as u32 % width) as f32 + 0.5 - cx;
+            let y = (i as u32 / width) as f32 + 0.5 - cy;
+            if x * x + y * y >= radius_sq {
+                pixel[..3].copy_from_slice(&self.color);
+            }
+        }
+    }
+
+    fn finished(&self) -> bool {
+        !self.closing && self.elapsed >= self.duration
+    }
+}
+
+/// Shake the whole frame, the amplitude decays linearly over the duration.
+pub struct ScreenShake {
+    /// Maximum offset in pixels.
+    pub amplitude: f32,
+    /// Oscillations per second.
+    pub frequency: f32,
+    pub duration: Duration,
+    elapsed: Duration,
+    scratch: Vec<u8>,
+}
+
+impl ScreenShake {
+    pub fn new(amplitude: f32, frequency: f32, duration: Duration) -> Self {
+        Self {
+            amplitude,
+            frequency,
+            duration,
+            elapsed: Duration::ZERO,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Current offset in pixels.
+    pub fn offset(&self) -> (i32, i32) {
+        let t = self.elapsed.as_secs_f32();
+        let strength = self.amplitude * (1. - progress(self.elapsed, self.duration));
+        let phase = t * self.frequency * std::f32::consts::TAU;
+        // Incommensurate frequencies on each axis so the motion does not look like a line.
+        let dx = (phase.sin() * strength).round() as i32;
+        let dy = ((phase * 1.37).cos() * strength).round() as i32;
+        (dx, dy)
+    }
+}
+
+impl PostEffect for ScreenShake {
+    fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    fn apply(&mut self, frame: &mut [u8], width: u32, height: u32) {
+        let (dx, dy) = self.offset();
+        if dx == 0 && dy == 0 {
+            return;
+        }
+        self.scratch.clear();
+        self.scratch.extend_from_slice(frame);
+        let (w, h) = (width as i32, height as i32);
+        for y in 0..h {
+            let sy = (y - dy).clamp(0, h - 1);
+            for x in 0..w {
+                let sx = (x - dx).clamp(0, w - 1);
+                let dst = ((y * w + x) * 4) as usize;
+                let src = ((sy * w + sx) * 4) as usize;
+                frame[dst..dst + 4].copy_from_slice(&self.scratch[src..src + 4]);
+            }
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Short full screen flash, fading out from `intensity` to nothing.
+pub struct Flash {
+    pub color: Color,
+    pub intensity: f32,
+    pub duration: Duration,
+    elapsed: Duration,
+}
+
+impl Flash {
+    pub fn new(color: Color, intensity: f32, duration: Duration) -> Self {
+        Self {
+            color,
+            intensity,
+            duration,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl PostEffect for Flash {
+    fn update(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    fn apply(&mut self, frame: &mut [u8], _width: u32, _height: u32) {
+        let alpha = self.intensity * (1. - progress(self.elapsed, self.duration));
+        if alpha <= 0. {
+            return;
+        }
+        for pixel in frame.chunks_exact_mut(4) {
+            blend(pixel, self.color, alpha);
+        }
+    }
+
+    fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
+
+/// Replace exact colors by other ones, e.g. to recolor sprites of a level.
+#[derive(Default)]
+pub struct PaletteSwap {
+    pub palette: HashMap<Color, Color>,
+}
+
+impl PaletteSwap {
+    pub fn new(pairs: impl IntoIterator<Item = (Color, Color)>) -> Self {
+        Self {
+            palette: pairs.into_iter().collect(),
+        }
+    }
+}
+
+impl PostEffect for PaletteSwap {
+    fn apply(&mut self, frame: &mut [u8], _width: u32, _height: u32) {
+        if self.palette.is_empty() {
+            return;
+        }
+        for pixel in frame.chunks_exact_mut(4) {
+            if let Some(color) = self.palette.get(&[pixel[0], pixel[1], pixel[2]]) {
+                pixel[..3].copy_from_slice(color);
+            }
+        }
+    }
+}
+
+/// Color grading through a `size` x `size` x `size` 3D lookup table.
+pub struct ColorLut {
+    size: usize,
+    table: Vec<Color>,
+    /// Blend factor between the original and the graded color.
+    pub strength: f32,
+}
+
+impl ColorLut {
+    /// Build a table from raw entries, ordered red first then green then blue.
+    /// Returns `None` if `table` does not hold `size³` entries.
+    pub fn new(size: usize, table: Vec<Color>) -> Option<Self> {
+        (size >= 2 && table.len() == size * size * size).then_some(Self {
+            size,
+            table,
+            strength: 1.,
+        })
+    }
+
+    /// Build a table by sampling a grading function.
+    pub fn from_fn(size: usize, grade: impl Fn(Color) -> Color) -> Self {
+        let size = size.max(2);
+        let step = |i: usize| (i * 255 / (size - 1)) as u8;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push(grade([step(r), step(g), step(b)]));
+                }
+            }
+        }
+        Self {
+            size,
+            table,
+            strength: 1.,
+        }
+    }
+
+    pub fn with_strength(mut self, strength: f32) -> Self {
+        self.strength = strength.clamp(0., 1.);
+        self
+    }
+
+    /// Nearest entry lookup.
+    pub fn lookup(&self, color: Color) -> Color {
+        let index = |c: u8| (c as usize * (self.size - 1) + 127) / 255;
+        let (r, g, b) = (index(color[0]), index(color[1]), index(color[2]));
+        self.table[(b * self.size + g) * self.size + r]
+    }
+}
+
+impl PostEffect for ColorLut {
+    fn apply(&mut self, frame: &mut [u8], _width: u32, _height: u32) {
+        for pixel in frame.chunks_exact_mut(4) {
+            let graded = self.lookup([pixel[0], pixel[1], pixel[2]]);
+            blend(pixel, graded, self.strength);
+        }
+    }
+}
+
+/// CRT like scanlines: darken every `spacing` rows.
+pub struct Scanlines {
+    /// Darkening of the scanline rows, 0 does nothing and 1 makes them black.
+    pub intensity: f32,
+    /// 1 darkens every row, 0 is taken as 1.
+    pub spacing: u32,
+}
+
+impl Scanlines {
+    pub fn new(intensity: f32) -> Self {
+        Self {
+            intensity,
+            spacing: 2,
+        }
+    }
+}
+
+impl PostEffect for Scanlines {
+    fn apply(&mut self, frame: &mut [u8], width: u32, _height: u32) {
+        if width == 0 || frame.is_empty() {
+            return;
+        }
+        let spacing = self.spacing.max(1) as usize;
+        let factor = 1. - self.intensity.clamp(0., 1.);
+        let row_len = width as usize * 4;
+        for row in frame.chunks_exact_mut(row_len).skip(spacing - 1).step_by(spacing) {
+            for pixel in row.chunks_exact_mut(4) {
+                for channel in pixel.iter_mut().take(3) {
+                    *channel = (*channel as f32 * factor) as u8;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: [u8; 4] = [100, 100, 100, 255];
+
+    /// `width` x `height` frame filled with one color.
+    fn frame(width: u32, height: u32, pixel: [u8; 4]) -> Vec<u8> {
+        pixel.repeat((width * height) as usize)
+    }
+
+    fn pixel(frame: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        frame[i..i + 4].try_into().unwrap()
+    }
+
+    #[test]
+    fn fade_covers_the_frame_then_uncovers_it() {
+        let mut fade = Fade::to_color([0, 0, 0], Duration::from_secs(1));
+        let mut pixels = frame(2, 2, GREY);
+        fade.apply(&mut pixels, 2, 2);
+        assert_eq!(pixel(&pixels, 2, 0, 0), GREY);
+        fade.update(Duration::from_secs(1));
+        fade.apply(&mut pixels, 2, 2);
+        assert_eq!(pixel(&pixels, 2, 1, 1), [0, 0, 0, 255]);
+        assert!(!fade.finished(), "a fade out stays until removed");
+
+        let mut fade = Fade::from_color([0, 0, 0], Duration::from_secs(1));
+        fade.update(Duration::from_millis(500));
+        let mut pixels = frame(2, 2, GREY);
+        fade.apply(&mut pixels, 2, 2);
+        assert_eq!(pixel(&pixels, 2, 0, 0), [50, 50, 50, 255]);
+        fade.update(Duration::from_millis(500));
+        assert!(fade.finished());
+    }
+
+    #[test]
+    fn iris_wipe_fills_outside_the_circle() {
+        let mut iris = IrisWipe::close([255, 0, 0], (0.5, 0.5), Duration::from_secs(1));
+        iris.update(Duration::from_millis(600));
+        let mut pixels = frame(9, 9, GREY);
+        iris.apply(&mut pixels, 9, 9);
+        assert_eq!(pixel(&pixels, 9, 4, 4), GREY);
+        assert_eq!(pixel(&pixels, 9, 0, 0), [255, 0, 0, 255]);
+        iris.update(Duration::from_millis(400));
+        iris.apply(&mut pixels, 9, 9);
+        assert_eq!(pixel(&pixels, 9, 4, 4), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn screen_shake_moves_the_frame_and_stops() {
+        let mut shake = ScreenShake::new(2., 1., Duration::from_secs(1));
+        shake.update(Duration::from_millis(250));
+        assert_ne!(shake.offset(), (0, 0));
+        let mut pixels = frame(4, 4, GREY);
+        pixels[..4].copy_from_slice(&[255, 255, 255, 255]);
+        let before = pixels.clone();
+        shake.apply(&mut pixels, 4, 4);
+        assert_ne!(pixels, before);
+        shake.update(Duration::from_millis(750));
+        assert!(shake.finished());
+    }
+
+    #[test]
+    fn flash_fades_out() {
+        let mut flash = Flash::new([255, 255, 255], 1., Duration::from_secs(1));
+        let mut pixels = frame(1, 1, GREY);
+        flash.apply(&mut pixels, 1, 1);
+        assert_eq!(pixels, [255, 255, 255, 255]);
+        flash.update(Duration::from_secs(1));
+        let mut pixels = frame(1, 1, GREY);
+        flash.apply(&mut pixels, 1, 1);
+        assert_eq!(pixels, GREY);
+        assert!(flash.finished());
+    }
+
+    #[test]
+    fn palette_swap_replaces_exact_colors_only() {
+        let mut swap = PaletteSwap::new([([100, 100, 100], [1, 2, 3])]);
+        let mut pixels = [GREY, [100, 100, 101, 255]].concat();
+        swap.apply(&mut pixels, 2, 1);
+        assert_eq!(pixels, [[1, 2, 3, 255], [100, 100, 101, 255]].concat());
+    }
+
+    #[test]
+    fn color_lut_grades_colors() {
+        assert!(ColorLut::new(2, vec![[0, 0, 0]; 7]).is_none());
+        let mut invert = ColorLut::from_fn(2, |[r, g, b]| [255 - r, 255 - g, 255 - b]);
+        let mut pixels = [[0, 0, 0, 255], [255, 255, 255, 255]].concat();
+        invert.apply(&mut pixels, 2, 1);
+        assert_eq!(pixels, [[255, 255, 255, 255], [0, 0, 0, 255]].concat());
+        let mut half = invert.with_strength(0.5);
+        let mut pixels = frame(1, 1, [0, 0, 0, 255]);
+        half.apply(&mut pixels, 1, 1);
+        assert_eq!(pixels, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn scanlines_darken_every_spacing_rows() {
+        let mut scanlines = Scanlines::new(1.);
+        let mut pixels = frame(1, 4, GREY);
+        scanlines.apply(&mut pixels, 1, 4);
+        let rows: Vec<u8> = (0..4).map(|y| pixel(&pixels, 1, 0, y)[0]).collect();
+        assert_eq!(rows, [100, 0, 100, 0]);
+
+        scanlines.spacing = 1;
+        let mut pixels = frame(1, 3, GREY);
+        scanlines.apply(&mut pixels, 1, 3);
+        assert_eq!(pixels, frame(1, 3, [0, 0, 0, 255]));
+
+        scanlines.apply(&mut [], 0, 0);
+    }
+
+    #[test]
+    fn stack_drops_finished_effects() {
+        let mut post = PostProcess::new();
+        post.push(Flash::new([255, 255, 255], 1., Duration::from_millis(100)));
+        post.push(Scanlines::new(0.5));
+        post.update(Duration::from_millis(100));
+        let mut pixels = frame(1, 2, GREY);
+        post.apply(&mut pixels, 1, 2);
+        assert_eq!(pixels, [GREY, [50, 50, 50, 255]].concat());
+    }
+
+    #[test]
+    fn remove_drops_only_the_given_effect() {
+        let mut post = PostProcess::new();
+        let fade = post.push(Fade::to_color([0, 0, 0], Duration::ZERO));
+        post.push(Scanlines::new(0.5));
+        assert!(post.remove(fade));
+        assert!(!post.remove(fade));
+        let mut pixels = frame(1, 2, GREY);
+        post.apply(&mut pixels, 1, 2);
+        assert_eq!(pixels, [GREY, [50, 50, 50, 255]].concat());
+    }
+}
@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Stream of the gameplay randomness shared by the game systems.
+pub const GAMEPLAY_STREAM: &str = "gameplay";
+pub const AI_STREAM: &str = "ai";
+pub const LOOT_STREAM: &str = "loot";
+/// Stream of the randomness without effect on the gameplay, like particles, drawn as often as the
+/// frame rate allows.
+pub const VISUAL_STREAM: &str = "visual";
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+/// PCG32 generator (XSH RR variant): 64 bits of state, 32 bits outputs, and a stream selector
+/// giving independent sequences for the same seed.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct Pcg32 {
+    state: u64,
+    inc: u64,
+}
+
+impl Pcg32 {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            inc: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        ((self.next_u32() as u64) << 32) | self.next_u32() as u64
+    }
+
+    /// Uniform in `0..bound`, without modulo bias. Returns 0 if `bound` is 0.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        if bound == 0 {
+            return 0;
+        }
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let value = self.next_u32();
+            if value >= threshold {
+                return value % bound;
+            }
+        }
+    }
+
+    /// Uniform in `min..max`, `min` if the range is empty.
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+        min.wrapping_add(self.below(max.abs_diff(min)) as i32)
+    }
+
+    /// Uniform in `0.0..1.0`.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    /// `true` with the probability `p`.
+    pub fn chance(&mut self, p: f32) -> bool {
+        self.next_f32() < p
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        match items.len() {
+            0 => None,
+            len => items.get(self.below(len as u32) as usize),
+        }
+    }
+}
+
+/// Random numbers of the game, reproducible from a seed.
+///
+/// Each system draws from its own named stream, so that drawing more numbers in one stream, like
+/// the visual one on a faster computer, does not change the others. The streams are reseeded at
+/// each level start from the game seed and the level, and saved with the game.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Rng {
+    game_seed: u64,
+    seed: u64,
+    streams: BTreeMap<String, Pcg32>,
+}
+
+impl Rng {
+    pub fn new(game_seed: u64) -> Self {
+        Self {
+            game_seed,
+            seed: game_seed,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    pub fn game_seed(&self) -> u64 {
+        self.game_seed
+    }
+
+    /// Seed of the streams since the last reseed.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restart every stream from `seed`.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.streams.clear();
+    }
+
+    /// Reseed the streams for a level, from the game seed and the level number.
+    pub fn start_level(&mut self, level: u32) {
+        self.reseed(split_mix(self.game_seed ^ ((level as u64) << 32 | level as u64)));
+    }
+
+    /// Generator of a stream, created on first use.
+    pub fn stream(&mut self, name: &str) -> &mut Pcg32 {
+        let seed = self.seed;
+        self.streams
+            .entry(String::from(name))
+            .or_insert_with(|| Pcg32::new(seed, fnv1a(name)))
+    }
+}
+
+/// SplitMix64 finalizer, spreading close seeds apart.
+fn split_mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// FNV-1a hash of a stream name, stable across builds unlike the std hasher.
+fn fnv1a(name: &str) -> u64 {
+    name.bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::save::SaveGame;
+
+    fn draws(rng: &mut Rng, stream: &str, count: usize) -> Vec<u32> {
+        (0..count).map(|_| rng.stream(stream).next_u32()).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_sequence() {
+        let (mut a, mut b) = (Rng::new(42), Rng::new(42));
+        a.start_level(3);
+        b.start_level(3);
+        assert_eq!(draws(&mut a, GAMEPLAY_STREAM, 8), draws(&mut b, GAMEPLAY_STREAM, 8));
+        let mut other = Rng::new(42);
+        other.start_level(4);
+        assert_ne!(draws(&mut a, GAMEPLAY_STREAM, 8), draws(&mut other, GAMEPLAY_STREAM, 8));
+    }
+
+    #[test]
+    fn visual_draws_leave_the_gameplay_stream_unchanged() {
+        let (mut slow, mut fast) = (Rng::new(7), Rng::new(7));
+        slow.stream(VISUAL_STREAM).next_u32();
+        draws(&mut fast, VISUAL_STREAM, 100);
+        assert_eq!(draws(&mut slow, GAMEPLAY_STREAM, 8), draws(&mut fast, GAMEPLAY_STREAM, 8));
+        assert_ne!(draws(&mut slow, AI_STREAM, 8), draws(&mut slow, LOOT_STREAM, 8));
+    }
+
+    #[test]
+    fn saved_streams_go_on_with_the_sequence() {
+        let mut rng = Rng::new(99);
+        rng.start_level(1);
+        draws(&mut rng, LOOT_STREAM, 5);
+        let mut save = SaveGame::new("1");
+        save.rng = Some(rng.clone());
+        let json = serde_json::to_string(&save).unwrap();
+        let expected = draws(&mut rng, LOOT_STREAM, 8);
+
+        let mut restored = serde_json::from_str::<SaveGame>(&json).unwrap().rng.unwrap();
+        assert_eq!(draws(&mut restored, LOOT_STREAM, 8), expected);
+    }
+}
@@ -0,0 +1,347 @@
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::event::trigger::TriggerEvent;
+use crate::pickup::Collected;
+use crate::rng::Rng;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Version of the save files written by this build.
+pub const SAVE_VERSION: u32 = 2;
+
+/// Trigger zones tagged `checkpoint:<name>` record a checkpoint when the player enters them.
+pub const CHECKPOINT_TAG_PREFIX: &str = "checkpoint:";
+
+const MAGIC: &[u8; 4] = b"G2DS";
+const HEADER_LEN: usize = 12;
+const SAVE_EXTENSION: &str = "sav";
+
+/// Migrations of the save payload, `MIGRATIONS[i]` upgrades version `i + 1` to `i + 2`.
+const MIGRATIONS: &[fn(&mut Value)] = &[add_rng];
+const _: () = assert!(MIGRATIONS.len() == SAVE_VERSION as usize - 1);
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(default)]
+pub struct PlayerStats {
+    pub health: u32,
+    pub max_health: u32,
+    pub lives: u32,
+    pub score: u64,
+}
+
+impl Default for PlayerStats {
+    fn default() -> Self {
+        Self {
+            health: 3,
+            max_health: 3,
+            lives: 3,
+            score: 0,
+        }
+    }
+}
+
+/// Progress of a player, stored in a save slot.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(default)]
+pub struct SaveGame {
+    pub level: String,
+    pub checkpoint: Option<String>,
+    pub player: PlayerStats,
+    /// Unique ids of the collectibles already picked up, so they do not respawn.
+    pub collected: BTreeSet<String>,
+    pub flags: BTreeMap<String, bool>,
+    /// Random streams when saved, restored so that the game goes on as it would have.
+    pub rng: Option<Rng>,
+}
+
+impl SaveGame {
+    pub fn new(level: &str) -> Self {
+        Self {
+            level: String::from(level),
+            ..Default::default()
+        }
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(false)
+    }
+
+    pub fn set_flag(&mut self, name: &str, value: bool) {
+        self.flags.insert(String::from(name), value);
+    }
+
+    /// Record the checkpoint the player entered during the last tick, if any.
+    pub fn update_checkpoint(&mut self, events: &EventBus, player: EntityId) -> bool {
+        let reached = events.read::<TriggerEvent>().iter().rev().find_map(|event| match event {
+            TriggerEvent::Enter(contact) if contact.entity == player => {
+                contact.tag.strip_prefix(CHECKPOINT_TAG_PREFIX)
+            }
+            _ => None,
+        });
+        match reached {
+            Some(name) if self.checkpoint.as_deref() != Some(name) => {
+                info!("SAVE:checkpoint {} reached", name);
+                self.checkpoint = Some(String::from(name));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record the unique pickups the player collected during the last tick.
+    pub fn update_collected(&mut self, events: &EventBus, player: EntityId) {
+        for collected in events.read::<Collected>().iter().filter(|c| c.collector == player) {
+            if let Some(unique_id) = &collected.unique_id {
+                self.collected.insert(unique_id.clone());
+            }
+        }
+    }
+}
+
+/// Version 2 saves the random streams.
+fn add_rng(payload: &mut Value) {
+    if let Some(object) = payload.as_object_mut() {
+        object.entry("rng").or_insert(Value::Null);
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    /// No platform data directory to store the saves.
+    NoDataDir,
+    Io(std::io::Error),
+    /// The file is not a save, is truncated or its checksum does not match.
+    Corrupted(String),
+    /// The file was written by a newer version of the game.
+    UnsupportedVersion(u32),
+    /// The header has a version no release ever wrote, the file is corrupted or too old.
+    UnknownVersion(u32),
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::NoDataDir => write!(f, "no data directory available"),
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::Corrupted(reason) => write!(f, "corrupted save : {}", reason),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "save version {} is newer than supported version {}", version, SAVE_VERSION)
+            }
+            SaveError::UnknownVersion(version) => {
+                write!(f, "save version {} is unknown, the file is corrupted or too old", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+/// Numbered save slots stored as files in a directory.
+///
+/// A save file is the `G2DS` magic, the format version and the CRC32 of the payload (both little
+/// endian `u32`), followed by the JSON payload. Files are written to a temporary file then renamed,
+/// so a crash while saving never leaves a half written slot.
+pub struct SaveSlots {
+    dir: PathBuf,
+}
+
+impl SaveSlots {
+    /// Slots in the platform data directory, under `<data dir>/<game>/saves`.
+    pub fn new(game: &str) -> Result<Self, SaveError> {
+        let dir = dirs_next::data_dir().ok_or(SaveError::NoDataDir)?.join(game).join("saves");
+        Ok(Self::with_dir(&dir))
+    }
+
+    pub fn with_dir(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn slot_path(&self, slot: u32) -> PathBuf {
+        self.dir.join(format!("slot_{}.{}", slot, SAVE_EXTENSION))
+    }
+
+    /// Used slots, sorted.
+    pub fn slots(&self) -> Vec<u32> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut slots: Vec<u32> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != SAVE_EXTENSION {
+                    return None;
+                }
+                path.file_stem()?.to_str()?.strip_prefix("slot_")?.parse().ok()
+            })
+            .collect();
+        slots.sort();
+        slots
+    }
+
+    pub fn save(&self, slot: u32, game: &SaveGame) -> Result<(), SaveError> {
+        let payload = serde_json::to_vec_pretty(game).map_err(|e| SaveError::Corrupted(e.to_string()))?;
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.slot_path(slot);
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&bytes)?;
+            file.sync_all()?;
+        }
+        std::fs::rename(&tmp_path, &path)?;
+        info!("SAVE:slot {} written to {}", slot, path.display());
+        Ok(())
+    }
+
+    /// Load a slot, `None` if it is empty. Older save versions are migrated.
+    pub fn load(&self, slot: u32) -> Result<Option<SaveGame>, SaveError> {
+        let path = self.slot_path(slot);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        decode(&bytes).map(Some).inspect_err(|e| warn!("SAVE:slot {} load failed : {}", slot, e))
+    }
+
+    pub fn delete(&self, slot: u32) -> Result<(), SaveError> {
+        match std::fs::remove_file(self.slot_path(slot)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn decode(bytes: &[u8]) -> Result<SaveGame, SaveError> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err(SaveError::Corrupted(String::from("missing header")));
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    let checksum = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    let payload = &bytes[HEADER_LEN..];
+    if crc32fast::hash(payload) != checksum {
+        return Err(SaveError::Corrupted(String::from("checksum mismatch")));
+    }
+    if version == 0 {
+        return Err(SaveError::UnknownVersion(version));
+    }
+    if version > SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+    let mut value: Value = serde_json::from_slice(payload).map_err(|e| SaveError::Corrupted(e.to_string()))?;
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        migration(&mut value);
+    }
+    serde_json::from_value(value).map_err(|e| SaveError::Corrupted(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_slots(name: &str) -> SaveSlots {
+        let dir = std::env::temp_dir().join(format!("g2d_saves_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        SaveSlots::with_dir(&dir)
+    }
+
+    /// Save file bytes with a valid checksum.
+    fn encode(version: u32, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&crc32fast::hash(payload).to_le_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn saved_game_loads_back() {
+        let slots = temp_slots("round_trip");
+        let mut game = SaveGame::new("forest");
+        game.set_flag("boss", true);
+        game.rng = Some(Rng::new(3));
+        slots.save(2, &game).unwrap();
+        assert_eq!(slots.slots(), vec![2]);
+        assert_eq!(slots.load(2).unwrap(), Some(game));
+        assert_eq!(slots.load(1).unwrap(), None);
+        slots.delete(2).unwrap();
+        assert!(slots.slots().is_empty());
+    }
+
+    #[test]
+    fn checksum_mismatch_is_corrupted() {
+        let mut bytes = encode(SAVE_VERSION, br#"{"level": "forest"}"#);
+        let last = bytes.len() - 3;
+        bytes[last] ^= 1;
+        assert!(matches!(decode(&bytes), Err(SaveError::Corrupted(reason)) if reason == "checksum mismatch"));
+    }
+
+    #[test]
+    fn truncated_file_is_corrupted() {
+        let bytes = encode(SAVE_VERSION, br#"{"level": "forest"}"#);
+        assert!(matches!(decode(&bytes[..HEADER_LEN - 1]), Err(SaveError::Corrupted(_))));
+        assert!(matches!(decode(&bytes[..bytes.len() - 4]), Err(SaveError::Corrupted(_))));
+    }
+
+    #[test]
+    fn bad_magic_is_corrupted() {
+        let mut bytes = encode(SAVE_VERSION, b"{}");
+        bytes[0] = b'X';
+        assert!(matches!(decode(&bytes), Err(SaveError::Corrupted(reason)) if reason == "missing header"));
+    }
+
+    #[test]
+    fn unknown_and_newer_versions_are_refused() {
+        let error = decode(&encode(0, b"{}")).unwrap_err();
+        assert!(matches!(error, SaveError::UnknownVersion(0)));
+        assert!(error.to_string().contains("corrupted or too old"));
+        let error = decode(&encode(SAVE_VERSION + 1, b"{}")).unwrap_err();
+        assert!(matches!(error, SaveError::UnsupportedVersion(version) if version == SAVE_VERSION + 1));
+        assert!(error.to_string().contains("newer"));
+    }
+
+    #[test]
+    fn version_1_save_is_migrated() {
+        let payload = br#"{"level": "forest", "flags": {"boss": true}}"#;
+        let mut value: Value = serde_json::from_slice(payload).unwrap();
+        add_rng(&mut value);
+        assert_eq!(value["rng"], Value::Null);
+
+        let game = decode(&encode(1, payload)).unwrap();
+        assert_eq!(game.level, "forest");
+        assert!(game.flag("boss"));
+        assert_eq!(game.rng, None);
+    }
+}
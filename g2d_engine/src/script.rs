@@ -0,0 +1,343 @@
+use crate::audio::PlaySound;
+use crate::entity::EntityId;
+use crate::event::bus::EventBus;
+use crate::geometry::Vec2;
+use crate::input::InputActions;
+use crate::world::{EntityInstance, World};
+use gwen2d_project::db::project_repository;
+use log::{error, info, warn};
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use sqlx::{Pool, Sqlite};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// Extension of the behaviour script files, edited outside of the editor while developing.
+pub const SCRIPT_EXTENSION: &str = "rhai";
+
+/// Event emitted by a script through `emit(name)`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ScriptEvent {
+    pub source: EntityId,
+    pub name: String,
+}
+
+/// Sandbox limits applied to every script call.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ScriptLimits {
+    /// Maximum number of operations of a single call, stops infinite loops.
+    pub max_operations: u64,
+    pub max_call_levels: usize,
+    pub max_string_size: usize,
+    pub max_array_size: usize,
+    pub max_map_size: usize,
+}
+
+impl Default for ScriptLimits {
+    fn default() -> Self {
+        Self {
+            max_operations: 50_000,
+            max_call_levels: 32,
+            max_string_size: 4_096,
+            max_array_size: 1_024,
+            max_map_size: 256,
+        }
+    }
+}
+
+enum ScriptCommand {
+    Spawn { kind: String, position: Vec2 },
+    Despawn,
+    PlaySound(String),
+    Emit(String),
+}
+
+/// State shared between the host and the functions registered in the script engine.
+#[derive(Default)]
+struct ScriptContext {
+    entity: Option<EntityInstance>,
+    held: BTreeSet<String>,
+    pressed: BTreeSet<String>,
+    commands: Vec<ScriptCommand>,
+}
+
+/// Per entity state, exposed as `this` in the script functions.
+struct ScriptInstance {
+    this: Dynamic,
+    started: bool,
+}
+
+/// Runs the Rhai behaviour scripts of the entities.
+///
+/// A script is attached to an entity kind (the project entity name) and may define the
+/// `on_spawn()` and `on_update(dt)` functions. Each instance has its own `this` object map to
+/// keep state between calls.
+///
+/// Scripts can use `entity_id()`, `x()`, `y()`, `velocity_x()`, `velocity_y()`, `state()`,
+/// `set_position(x, y)`, `move_by(dx, dy)`, `set_velocity(vx, vy)`, `set_state(name)`,
+/// `action_held(name)`, `action_pressed(name)`, `spawn_entity(kind, x, y)`, `despawn()`,
+/// `play_sound(name)` and `emit(name)`. Modules and `eval` are disabled and every call is bounded
+/// by the [`ScriptLimits`].
+pub struct ScriptHost {
+    engine: Engine,
+    context: Rc<RefCell<ScriptContext>>,
+    scripts: HashMap<String, AST>,
+    instances: BTreeMap<EntityId, ScriptInstance>,
+}
+
+impl ScriptHost {
+    pub fn new(limits: ScriptLimits) -> Self {
+        let context = Rc::new(RefCell::new(ScriptContext::default()));
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(limits.max_operations)
+            .set_max_call_levels(limits.max_call_levels)
+            .set_max_string_size(limits.max_string_size)
+            .set_max_array_size(limits.max_array_size)
+            .set_max_map_size(limits.max_map_size)
+            .set_module_resolver(DummyModuleResolver::new());
+        engine.disable_symbol("eval");
+        engine.on_print(|text| info!("SCRIPT:{}", text));
+        engine.on_debug(|text, source, pos| info!("SCRIPT:{:?} {:?} {}", source, pos, text));
+        register_api(&mut engine, &context);
+        Self {
+            engine,
+            context,
+            scripts: HashMap::new(),
+            instances: BTreeMap::new(),
+        }
+    }
+
+    /// Compile and register the script of an entity kind, replacing the previous one.
+    pub fn load_script(&mut self, kind: &str, source: &str) -> Result<(), String> {
+        let ast = self.engine.compile(source).map_err(|e| e.to_string())?;
+        self.scripts.insert(String::from(kind), ast);
+        Ok(())
+    }
+
+    /// Load every script of the project database, returns the number of scripts loaded. A script
+    /// which does not compile is logged and skipped.
+    pub async fn load_db(&mut self, pool: &Pool<Sqlite>) -> Result<usize, String> {
+        let scripts = project_repository::scripts(pool).await.map_err(|e| e.to_string())?;
+        let mut count = 0;
+        for (kind, source) in scripts.iter() {
+            match self.load_script(kind, source) {
+                Ok(()) => count += 1,
+                Err(e) => error!("SCRIPT:{} load failed : {}", kind, e),
+            }
+        }
+        info!("SCRIPT:{} scripts loaded", count);
+        Ok(count)
+    }
+
+    /// Load a script file over the one of the project, the entity kind is the file stem. Used by
+    /// the hot reload while a script is edited.
+    pub fn load_file(&mut self, path: &Path) -> Result<(), String> {
+        let kind = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or_else(|| format!("invalid script path {}", path.display()))?;
+        let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        self.load_script(&kind, &source)
+    }
+
+    pub fn has_script(&self, kind: &str) -> bool {
+        self.scripts.contains_key(kind)
+    }
+
+    /// Run the scripts of every entity of the world, then apply what they requested.
+    pub fn update(&mut self, world: &mut World, actions: &InputActions, bus: &mut EventBus, dt: Duration) {
+        self.instances.retain(|id, _| world.get(*id).is_some());
+        {
+            let mut context = self.context.borrow_mut();
+            context.held = actions.held().clone();
+            context.pressed = actions.pressed().clone();
+        }
+        for id in world.ids() {
+            let Some(entity) = world.get(id) else {
+                continue;
+            };
+            let Some(ast) = self.scripts.get(&entity.kind) else {
+                continue;
+            };
+            let instance = self.instances.entry(id).or_insert_with(|| ScriptInstance {
+                this: Dynamic::from_map(Map::new()),
+                started: false,
+            });
+            self.context.borrow_mut().entity = Some(entity.clone());
+            if !instance.started {
+                instance.started = true;
+                call(&self.engine, ast, &mut instance.this, id, "on_spawn", ());
+            }
+            call(&self.engine, ast, &mut instance.this, id, "on_update", (dt.as_secs_f64(),));
+            let (updated, commands) = {
+                let mut context = self.context.borrow_mut();
+                (context.entity.take(), std::mem::take(&mut context.commands))
+            };
+            if let (Some(updated), Some(entity)) = (updated, world.get_mut(id)) {
+                *entity = updated;
+            }
+            for command in commands {
+                match command {
+                    ScriptCommand::Spawn { kind, position } => {
+                        world.spawn(&kind, position);
+                    }
+                    ScriptCommand::Despawn => {
+                        world.despawn(id);
+                    }
+                    ScriptCommand::PlaySound(name) => bus.publish(PlaySound {
+                        name,
+                        source: Some(id),
+                    }),
+                    ScriptCommand::Emit(name) => bus.publish(ScriptEvent { source: id, name }),
+                }
+            }
+        }
+    }
+}
+
+impl Default for ScriptHost {
+    fn default() -> Self {
+        Self::new(ScriptLimits::default())
+    }
+}
+
+fn call(engine: &Engine, ast: &AST, this: &mut Dynamic, id: EntityId, name: &str, args: impl rhai::FuncArgs) {
+    if !ast.iter_functions().any(|f| f.name == name) {
+        return;
+    }
+    let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(this);
+    if let Err(e) = engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, name, args) {
+        warn!("SCRIPT:entity {} {}() failed : {}", id.0, name, e);
+    }
+}
+
+fn register_api(engine: &mut Engine, context: &Rc<RefCell<ScriptContext>>) {
+    // Current entity.
+    let ctx = context.clone();
+    engine.register_fn("entity_id", move || ctx.borrow().entity.as_ref().map_or(-1, |e| e.id.0 as i64));
+    let ctx = context.clone();
+    engine.register_fn("x", move || ctx.borrow().entity.as_ref().map_or(0., |e| e.position.x as f64));
+    let ctx = context.clone();
+    engine.register_fn("y", move || ctx.borrow().entity.as_ref().map_or(0., |e| e.position.y as f64));
+    let ctx = context.clone();
+    engine.register_fn("velocity_x", move || ctx.borrow().entity.as_ref().map_or(0., |e| e.velocity.x as f64));
+    let ctx = context.clone();
+    engine.register_fn("velocity_y", move || ctx.borrow().entity.as_ref().map_or(0., |e| e.velocity.y as f64));
+    let ctx = context.clone();
+    engine.register_fn("state", move || ctx.borrow().entity.as_ref().map(|e| e.state.clone()).unwrap_or_default());
+    let ctx = context.clone();
+    engine.register_fn("set_position", move |x: f64, y: f64| {
+        if let Some(e) = ctx.borrow_mut().entity.as_mut() {
+            e.position = Vec2::new(x as f32, y as f32);
+        }
+    });
+    let ctx = context.clone();
+    engine.register_fn("move_by", move |dx: f64, dy: f64| {
+        if let Some(e) = ctx.borrow_mut().entity.as_mut() {
+            e.position += Vec2::new(dx as f32, dy as f32);
+        }
+    });
+    let ctx = context.clone();
+    engine.register_fn("set_velocity", move |vx: f64, vy: f64| {
+        if let Some(e) = ctx.borrow_mut().entity.as_mut() {
+            e.velocity = Vec2::new(vx as f32, vy as f32);
+        }
+    });
+    let ctx = context.clone();
+    engine.register_fn("set_state", move |state: &str| {
+        if let Some(e) = ctx.borrow_mut().entity.as_mut() {
+            e.set_state(state);
+        }
+    });
+    // Input actions.
+    let ctx = context.clone();
+    engine.register_fn("action_held", move |action: &str| ctx.borrow().held.contains(action));
+    let ctx = context.clone();
+    engine.register_fn("action_pressed", move |action: &str| ctx.borrow().pressed.contains(action));
+    // Deferred commands, applied once the call is done.
+    let ctx = context.clone();
+    engine.register_fn("spawn_entity", move |kind: &str, x: f64, y: f64| {
+        ctx.borrow_mut().commands.push(ScriptCommand::Spawn {
+            kind: String::from(kind),
+            position: Vec2::new(x as f32, y as f32),
+        });
+    });
+    let ctx = context.clone();
+    engine.register_fn("despawn", move || ctx.borrow_mut().commands.push(ScriptCommand::Despawn));
+    let ctx = context.clone();
+    engine.register_fn("play_sound", move |name: &str| {
+        ctx.borrow_mut().commands.push(ScriptCommand::PlaySound(String::from(name)));
+    });
+    let ctx = context.clone();
+    engine.register_fn("emit", move |name: &str| {
+        ctx.borrow_mut().commands.push(ScriptCommand::Emit(String::from(name)));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(host: &mut ScriptHost, world: &mut World, bus: &mut EventBus) {
+        host.update(world, &InputActions::new(), bus, Duration::from_millis(100));
+        bus.dispatch();
+    }
+
+    #[test]
+    fn scripts_move_their_entity_and_keep_state() {
+        let mut host = ScriptHost::default();
+        host.load_script(
+            "joueur",
+            "fn on_spawn() { this.ticks = 0; } fn on_update(dt) { this.ticks += 1; set_position(this.ticks * 10.0, 0.0); }",
+        )
+        .unwrap();
+        let mut world = World::new();
+        let mut bus = EventBus::new();
+        let player = world.spawn("joueur", Vec2::ZERO);
+        let other = world.spawn("ennemi", Vec2::ZERO);
+        run(&mut host, &mut world, &mut bus);
+        run(&mut host, &mut world, &mut bus);
+        assert_eq!(world.get(player).unwrap().position, Vec2::new(20., 0.));
+        assert_eq!(world.get(other).unwrap().position, Vec2::ZERO);
+    }
+
+    #[test]
+    fn operation_limit_stops_infinite_loops() {
+        let mut host = ScriptHost::new(ScriptLimits {
+            max_operations: 1_000,
+            ..ScriptLimits::default()
+        });
+        host.load_script("boucle", "fn on_update(dt) { loop { } } ").unwrap();
+        host.load_script("signal", "fn on_update(dt) { emit(\"vivant\"); }").unwrap();
+        let mut world = World::new();
+        let mut bus = EventBus::new();
+        world.spawn("boucle", Vec2::ZERO);
+        let alive = world.spawn("signal", Vec2::ZERO);
+        // The endless script is stopped and the next entity still runs.
+        run(&mut host, &mut world, &mut bus);
+        assert_eq!(
+            bus.read::<ScriptEvent>(),
+            [ScriptEvent {
+                source: alive,
+                name: String::from("vivant"),
+            }]
+        );
+    }
+
+    #[test]
+    fn eval_and_modules_are_disabled() {
+        let mut host = ScriptHost::default();
+        assert!(host.load_script("eval", "fn on_update(dt) { eval(\"despawn()\"); }").is_err());
+        assert!(host.load_script("import", "import \"fichier\" as f;").is_ok());
+        let mut world = World::new();
+        let mut bus = EventBus::new();
+        let id = world.spawn("import", Vec2::ZERO);
+        host.load_script("import", "fn on_update(dt) { import \"fichier\" as f; despawn(); }").unwrap();
+        run(&mut host, &mut world, &mut bus);
+        assert!(world.get(id).is_some(), "the import must fail before the despawn");
+    }
+}
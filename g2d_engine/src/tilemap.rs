@@ -0,0 +1,127 @@
+use crate::geometry::{Rect, Vec2};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum Tile {
+    #[default]
+    Empty,
+    Solid,
+}
+
+/// Collision grid of a level. Cells outside the map are empty.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TileMap {
+    width: i32,
+    height: i32,
+    tile_size: f32,
+    tiles: Vec<Tile>,
+}
+
+impl Default for TileMap {
+    fn default() -> Self {
+        Self::new(0, 0, 32.)
+    }
+}
+
+impl TileMap {
+    pub fn new(width: i32, height: i32, tile_size: f32) -> Self {
+        Self {
+            width,
+            height,
+            tile_size,
+            tiles: vec![Tile::Empty; (width.max(0) * height.max(0)) as usize],
+        }
+    }
+
+    /// Build a map from rows of characters, `#` being solid and anything else empty.
+    pub fn from_rows(rows: &[&str], tile_size: f32) -> Self {
+        let height = rows.len() as i32;
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0) as i32;
+        let mut map = Self::new(width, height, tile_size);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    map.set(x as i32, y as i32, Tile::Solid);
+                }
+            }
+        }
+        map
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn tile_size(&self) -> f32 {
+        self.tile_size
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Tile {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return Tile::Empty;
+        }
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) {
+        if x >= 0 && y >= 0 && x < self.width && y < self.height {
+            self.tiles[(y * self.width + x) as usize] = tile;
+        }
+    }
+
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.get(x, y) == Tile::Solid
+    }
+
+    /// Cell containing a world position.
+    pub fn cell_at(&self, point: Vec2) -> (i32, i32) {
+        (
+            (point.x / self.tile_size).floor() as i32,
+            (point.y / self.tile_size).floor() as i32,
+        )
+    }
+
+    /// World bounds of a cell.
+    pub fn cell_rect(&self, x: i32, y: i32) -> Rect {
+        Rect::new(
+            x as f32 * self.tile_size,
+            y as f32 * self.tile_size,
+            self.tile_size,
+            self.tile_size,
+        )
+    }
+
+    pub fn is_solid_at(&self, point: Vec2) -> bool {
+        let (x, y) = self.cell_at(point);
+        self.is_solid(x, y)
+    }
+
+    /// Solid cells overlapping a rectangle.
+    pub fn solid_cells(&self, rect: &Rect) -> Vec<(i32, i32)> {
+        let (x0, y0) = self.cell_at(Vec2::new(rect.x, rect.y));
+        // Edges touching a cell border do not overlap the next cell.
+        let (x1, y1) = self.cell_at(Vec2::new(rect.right() - 0.001, rect.bottom() - 0.001));
+        let mut cells = Vec::new();
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                if self.is_solid(x, y) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    /// No solid cell between two world positions.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        let distance = from.distance(to);
+        let steps = (distance / (self.tile_size / 4.)).ceil().max(1.) as i32;
+        (0..=steps).all(|i| {
+            let t = i as f32 / steps as f32;
+            !self.is_solid_at(from + (to - from) * t)
+        })
+    }
+}
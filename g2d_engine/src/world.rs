@@ -0,0 +1,156 @@
+use crate::entity::EntityId;
+use crate::geometry::{Rect, Vec2};
+use std::collections::{BTreeMap, HashMap};
+
+/// Collider size used for kinds without a registered size.
+pub const DEFAULT_ENTITY_SIZE: Vec2 = Vec2 { x: 32., y: 32. };
+
+/// Runtime instance of a project entity.
+#[derive(PartialEq, Debug, Clone)]
+pub struct EntityInstance {
+    pub id: EntityId,
+    /// Name of the project entity this instance was spawned from.
+    pub kind: String,
+    /// Top left corner, in world pixels.
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub size: Vec2,
+    /// Current animation state, matching an `EntityState` name of the project.
+    pub state: String,
+    /// Current frame in the animation state.
+    pub frame: usize,
+    /// Time the current frame has been shown, in seconds.
+    pub frame_time: f32,
+}
+
+impl EntityInstance {
+    pub fn bounds(&self) -> Rect {
+        Rect::new(self.position.x, self.position.y, self.size.x, self.size.y)
+    }
+
+    /// Switch animation state, restarting the animation if the state changes.
+    pub fn set_state(&mut self, state: &str) {
+        if self.state != state {
+            self.state = String::from(state);
+            self.frame = 0;
+            self.frame_time = 0.;
+        }
+    }
+}
+
+/// Looping animation of an entity state.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Animation {
+    pub frames: usize,
+    /// Time each frame is shown, in seconds.
+    pub frame_duration: f32,
+}
+
+/// All the entities alive in the current level.
+#[derive(Default)]
+pub struct World {
+    next_id: u32,
+    kind_sizes: HashMap<String, Vec2>,
+    /// Animations by kind and state, states without one stay on their first frame.
+    animations: HashMap<(String, String), Animation>,
+    entities: BTreeMap<EntityId, EntityInstance>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collider size of the instances spawned for `kind`.
+    pub fn set_kind_size(&mut self, kind: &str, size: Vec2) {
+        self.kind_sizes.insert(String::from(kind), size);
+    }
+
+    pub fn set_animation(&mut self, kind: &str, state: &str, animation: Animation) {
+        self.animations.insert((String::from(kind), String::from(state)), animation);
+    }
+
+    /// Advance the animation frame of every entity.
+    pub fn animate(&mut self, dt: f32) {
+        for entity in self.entities.values_mut() {
+            let Some(animation) = self.animations.get(&(entity.kind.clone(), entity.state.clone())) else {
+                continue;
+            };
+            if animation.frames == 0 || animation.frame_duration <= 0. {
+                continue;
+            }
+            entity.frame_time += dt;
+            while entity.frame_time >= animation.frame_duration {
+                entity.frame_time -= animation.frame_duration;
+                entity.frame = (entity.frame + 1) % animation.frames;
+            }
+        }
+    }
+
+    /// Id given to the next spawned entity, ids only grow.
+    pub fn next_id(&self) -> EntityId {
+        EntityId(self.next_id)
+    }
+
+    pub fn spawn(&mut self, kind: &str, position: Vec2) -> EntityId {
+        let id = EntityId(self.next_id);
+        self.next_id += 1;
+        let size = self.kind_sizes.get(kind).copied().unwrap_or(DEFAULT_ENTITY_SIZE);
+        self.entities.insert(
+            id,
+            EntityInstance {
+                id,
+                kind: String::from(kind),
+                position,
+                velocity: Vec2::ZERO,
+                size,
+                state: String::from("default"),
+                frame: 0,
+                frame_time: 0.,
+            },
+        );
+        id
+    }
+
+    pub fn despawn(&mut self, id: EntityId) -> Option<EntityInstance> {
+        self.entities.remove(&id)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&EntityInstance> {
+        self.entities.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut EntityInstance> {
+        self.entities.get_mut(&id)
+    }
+
+    /// Entities sorted by id.
+    pub fn iter(&self) -> impl Iterator<Item = &EntityInstance> {
+        self.entities.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut EntityInstance> {
+        self.entities.values_mut()
+    }
+
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.entities.keys().copied().collect()
+    }
+
+    /// Bounds of every entity, used for trigger tests.
+    pub fn colliders(&self) -> Vec<(EntityId, Rect)> {
+        self.entities.values().map(|entity| (entity.id, entity.bounds())).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entities.clear();
+    }
+}
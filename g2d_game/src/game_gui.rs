@@ -12,7 +12,7 @@ impl GameGui {
 }
 
 impl Gui for GameGui {
-    fn ui(&mut self, ctx: &Context) {
+    fn ui(&mut self, _ctx: &Context) {
 
     }
 }
\ No newline at end of file
@@ -1,28 +1,77 @@
 #![deny(clippy::all)]
 #![forbid(unsafe_code)]
 
-use g2d_engine::G2dEngine;
-use pixels::Error;
+use error_iter::ErrorIter;
+use g2d_engine::error::G2dError;
+use g2d_engine::{G2dEngine, RESOURCES_DIR};
+use log::{error, info};
+use rfd::{MessageButtons, MessageDialog, MessageLevel};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 mod game_gui;
 
 const WIDTH: u32 = 1024;
 const HEIGHT: u32 = 768;
-const BOX_SIZE: i16 = 64;
+const BACKGROUND: &str = "background.png";
+/// Environment variable naming the Chrome trace file written on exit.
+const PROFILE_VAR: &str = "G2D_PROFILE";
 
-fn main() -> Result<(), Error> {
+fn main() -> ExitCode {
     // init logger
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
 
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report(&e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), G2dError> {
     // load background
-    let background = image::open("./resources/background.png")
-        .unwrap()
-        .to_rgba8();
+    let path = Path::new(RESOURCES_DIR).join(BACKGROUND);
+    let background = image::open(&path).map_err(|e| G2dError::asset(path, e))?.to_rgba8();
 
     // init GUI
     let gui = Box::new(game_gui::GameGui::new());
 
     // init engine
-    let mut engine = G2dEngine::new(WIDTH, HEIGHT, background);
-    engine.run(gui)
+    let mut engine = G2dEngine::new(WIDTH, HEIGHT, background)?;
+
+    // reload changed resources while developing
+    #[cfg(debug_assertions)]
+    if let Err(e) = engine.enable_hot_reload(&[PathBuf::from(RESOURCES_DIR)]) {
+        error!("hot reload disabled : {}", e);
+    }
+    // time the engine systems, exported as a Chrome trace when the game exits
+    let profile = std::env::var_os(PROFILE_VAR).map(PathBuf::from);
+    let profiler = match profile {
+        Some(_) => Some(engine.enable_profiler()?),
+        None => None,
+    };
+    let result = engine.run(gui);
+    if let (Some(path), Some(profiler)) = (profile, profiler) {
+        match profiler.lock().unwrap().export_chrome_trace(&path) {
+            Ok(()) => info!("profile written to {}", path.display()),
+            Err(e) => error!("profile not written to {} : {}", path.display(), e),
+        }
+    }
+    result
+}
+
+/// Log the error with its causes and show it to the player.
+fn report(e: &G2dError) {
+    error!("{}", e);
+    for source in e.sources().skip(1) {
+        error!("  Caused by: {}", source);
+    }
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title("Erreur")
+        .set_description(format!("Le jeu s'est arrêté sur une erreur.\n\n{}", e))
+        .set_buttons(MessageButtons::Ok)
+        .show();
 }
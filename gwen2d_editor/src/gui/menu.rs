@@ -1,3 +1,9 @@
+/// Action chosen in the menu bar, run by the editor.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum MenuAction {
+    OpenProject,
+}
+
 pub struct Menu {}
 
 impl Menu {
@@ -5,13 +11,15 @@ impl Menu {
         Self {}
     }
 
-    pub fn show(&mut self, ui: &mut eframe::egui::Ui, ctx: &egui::Context) {
+    pub fn show(&mut self, ui: &mut eframe::egui::Ui, ctx: &egui::Context) -> Option<MenuAction> {
+        let mut action = None;
         egui::menu::bar(ui, |ui| {
             ui.menu_button("Project", |ui| {
                 if ui.button("New project").clicked() {
                     ui.close_menu();
                 }
                 if ui.button("Open project").clicked() {
+                    action = Some(MenuAction::OpenProject);
                     ui.close_menu();
                 }
                 if ui.button("Quit").clicked() {
@@ -19,5 +27,6 @@ impl Menu {
                 }
             });
         });
+        action
     }
 }
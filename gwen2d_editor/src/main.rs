@@ -1,8 +1,14 @@
-use crate::gui::menu::Menu;
+use crate::gui::menu::{Menu, MenuAction};
 use eframe::egui::Ui;
 use eframe::egui::ViewportBuilder;
+use gwen2d_project::db::engine_db::{DbError, EngineDb};
+use gwen2d_project::db::project_repository::ProjectData;
+use gwen2d_project::model::project::Project;
+use log::error;
+use std::path::PathBuf;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
 
 mod gui {
     pub mod menu;
@@ -16,13 +22,53 @@ enum EngineEditorTab {
     Level,
 }
 
+/// Name, database and content of a project read in the background.
+type OpenedProject = Result<(String, EngineDb, ProjectData), DbError>;
+
 struct GwenEditor {
     runtime: Arc<Runtime>,
     tab: EngineEditorTab,
     menu: Menu,
+    db: Arc<Mutex<EngineDb>>,
+    project: Option<Project>,
+    opened_project: Arc<std::sync::Mutex<Option<OpenedProject>>>,
+    error: Option<String>,
 }
 
 impl GwenEditor {
+    /// Open the project database and read the project in the background. The project open until
+    /// now is kept if it fails.
+    fn open_project(&mut self, path: PathBuf, ctx: &egui::Context) {
+        let opened = self.opened_project.clone();
+        let ctx = ctx.clone();
+        self.runtime.spawn(async move {
+            let mut new_db = EngineDb::new();
+            let result = match new_db.open_existing(&path).await {
+                Ok(()) => new_db.load_project().await,
+                Err(e) => Err(e),
+            };
+            let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            *opened.lock().unwrap() = Some(result.map(|data| (name, new_db, data)));
+            ctx.request_repaint();
+        });
+    }
+
+    /// Show the project read in the background, or why it could not be opened. The database is
+    /// replaced with the project, never before.
+    fn finish_opening(&mut self, opened: OpenedProject) {
+        match opened.and_then(|(name, db, data)| data.into_project(&name).map(|project| (db, project))) {
+            Ok((db, project)) => {
+                self.db = Arc::new(Mutex::new(db));
+                self.project = Some(project);
+                self.error = None;
+            }
+            Err(e) => {
+                error!("DB:project open failed : {}", e);
+                self.error = Some(format!("Impossible d'ouvrir le projet : {}", e));
+            }
+        }
+    }
+
     fn show_tabs(&mut self, ui: &mut Ui, ctx: &egui::Context) {
         ui.horizontal(|ui| {
             ui.selectable_value(&mut self.tab, EngineEditorTab::Project, "Explorer");
@@ -39,6 +85,10 @@ impl Default for GwenEditor {
             runtime: Arc::new(Runtime::new().unwrap()),
             tab: EngineEditorTab::Project,
             menu: Menu::new(),
+            db: Arc::new(Mutex::new(EngineDb::new())),
+            project: None,
+            opened_project: Arc::new(std::sync::Mutex::new(None)),
+            error: None,
         }
     }
 }
@@ -46,12 +96,37 @@ impl Default for GwenEditor {
 impl eframe::App for GwenEditor {
     // EGUI frame update method
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let opened = self.opened_project.lock().unwrap().take();
+        if let Some(opened) = opened {
+            self.finish_opening(opened);
+        }
+        let mut action = None;
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
-            self.menu.show(ui, ctx);
+            action = self.menu.show(ui, ctx);
             self.show_tabs(ui, ctx);
         });
+        if action == Some(MenuAction::OpenProject)
+            && let Some(path) = rfd::FileDialog::new().add_filter("Projet GWEN 2D", &["db"]).pick_file()
+        {
+            self.open_project(path, ctx);
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.label("Bienvenue dans l'application !");
+            match &self.project {
+                Some(project) => {
+                    ui.label(format!(
+                        "{} : {} catégories, {} entités",
+                        project.name,
+                        project.categories.borrow().len(),
+                        project.entities.borrow().len()
+                    ));
+                }
+                None => {
+                    ui.label("Bienvenue dans l'application !");
+                }
+            }
+            if let Some(error) = &self.error {
+                ui.colored_label(egui::Color32::RED, error);
+            }
         });
     }
 }
@@ -0,0 +1,68 @@
+use gwen2d_project::db::engine_db;
+use std::path::PathBuf;
+use tokio::runtime::Runtime;
+
+const USAGE: &str = "usage : gwen2d_engine check <projet.db> [--repair]";
+
+/// Run the command given on the command line and return the exit code of the process, `None`
+/// without command to start the editor.
+///
+/// `check <projet.db> [--repair]` prints the integrity problems of a project and exits with 1 if
+/// there are some, without writing to the file. `--repair` upgrades the schema and fixes them
+/// instead.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, args) = args.split_first()?;
+    let code = match command.as_str() {
+        "check" => match args {
+            [path] => check(PathBuf::from(path), false),
+            [path, option] if option == "--repair" => check(PathBuf::from(path), true),
+            _ => usage(),
+        },
+        _ => usage(),
+    };
+    Some(code)
+}
+
+fn usage() -> i32 {
+    eprintln!("{}", USAGE);
+    2
+}
+
+fn check(path: PathBuf, repair: bool) -> i32 {
+    let runtime = match Runtime::new() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let result = runtime.block_on(async {
+        if repair {
+            engine_db::repair_file(&path).await
+        } else {
+            engine_db::check_file(&path).await
+        }
+    });
+    match result {
+        Ok(issues) if issues.is_empty() => {
+            println!("{} : aucun problème", path.display());
+            0
+        }
+        Ok(issues) => {
+            for issue in issues.iter() {
+                println!("{}", issue);
+            }
+            if repair {
+                println!("{} : {} problèmes corrigés", path.display(), issues.len());
+                0
+            } else {
+                println!("{} : {} problèmes, corrigez-les avec --repair", path.display(), issues.len());
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!("Impossible de vérifier {} : {}", path.display(), e);
+            2
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use chrono::{DateTime, Local, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Projects kept in the list, the least recently opened are forgotten past it.
+pub const MAX_RECENT_PROJECTS: usize = 10;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct RecentProject {
+    pub name: String,
+    /// Project database.
+    pub path: PathBuf,
+    pub last_opened: DateTime<Utc>,
+}
+
+impl RecentProject {
+    /// `false` if the project database was moved or deleted since it was opened.
+    pub fn exists(&self) -> bool {
+        self.path.is_file()
+    }
+
+    /// Date of the last opening, in local time.
+    pub fn last_opened_label(&self) -> String {
+        self.last_opened.with_timezone(&Local).format("%d/%m/%Y %H:%M").to_string()
+    }
+}
+
+/// Projects opened in the editor, most recent first, saved in the user config directory.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct RecentProjects {
+    projects: Vec<RecentProject>,
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+impl RecentProjects {
+    /// `recent_projects.json` in the `gwen2d` directory of the user config directory.
+    pub fn default_file() -> Option<PathBuf> {
+        dirs_next::config_dir().map(|dir| dir.join("gwen2d").join("recent_projects.json"))
+    }
+
+    /// List saved in the default file, empty if there is none yet or it cannot be read.
+    pub fn load() -> Self {
+        match Self::default_file() {
+            Some(file) => Self::load_from(&file),
+            None => Self::default(),
+        }
+    }
+
+    pub fn load_from(file: &Path) -> Self {
+        let mut recent = match std::fs::read(file) {
+            Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                error!("CONFIG:{} unreadable : {}", file.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        recent.file = Some(file.to_path_buf());
+        recent
+    }
+
+    pub fn projects(&self) -> &[RecentProject] {
+        &self.projects
+    }
+
+    /// Directory of the most recent project, where new projects are proposed.
+    pub fn last_directory(&self) -> Option<PathBuf> {
+        self.projects.first()?.path.parent().map(Path::to_path_buf)
+    }
+
+    /// Put a project first in the list, opened now, and save the list.
+    pub fn touch(&mut self, name: &str, path: &Path) {
+        self.projects.retain(|project| project.path != path);
+        self.projects.insert(
+            0,
+            RecentProject {
+                name: String::from(name),
+                path: path.to_path_buf(),
+                last_opened: Utc::now(),
+            },
+        );
+        self.projects.truncate(MAX_RECENT_PROJECTS);
+        self.save();
+    }
+
+    /// Forget a project and save the list.
+    pub fn remove(&mut self, path: &Path) {
+        self.projects.retain(|project| project.path != path);
+        self.save();
+    }
+
+    /// Write the list, a failure is only logged as the list is a convenience.
+    fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let written = file
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(file, serde_json::to_string_pretty(self).unwrap_or_default()));
+        match written {
+            Ok(()) => info!("CONFIG:{} saved", file.display()),
+            Err(e) => error!("CONFIG:{} save failed : {}", file.display(), e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gwen2d_recent_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn names(recent: &RecentProjects) -> Vec<String> {
+        recent.projects().iter().map(|project| project.name.clone()).collect()
+    }
+
+    #[test]
+    fn list_is_saved_and_reloaded() {
+        let dir = temp_dir("round_trip");
+        let file = dir.join("config").join("recent_projects.json");
+        let project_path = |i: usize| dir.join(format!("projet{}.db", i));
+        let mut recent = RecentProjects::load_from(&file);
+        assert!(recent.projects().is_empty());
+        for i in 0..=MAX_RECENT_PROJECTS {
+            std::fs::write(project_path(i), "").unwrap();
+            recent.touch(&format!("projet{}", i), &project_path(i));
+        }
+        // Opening a project again moves it first instead of adding it twice.
+        recent.touch("projet5", &project_path(5));
+        recent.remove(&project_path(9));
+        std::fs::remove_file(project_path(8)).unwrap();
+
+        let reloaded = RecentProjects::load_from(&file);
+        assert_eq!(reloaded.projects(), recent.projects());
+        // The oldest project went past the limit, then one was removed.
+        assert_eq!(
+            names(&reloaded),
+            ["projet5", "projet10", "projet8", "projet7", "projet6", "projet4", "projet3", "projet2", "projet1"]
+        );
+        assert_eq!(reloaded.last_directory(), Some(dir.clone()));
+        let missing: Vec<bool> = reloaded.projects().iter().map(|project| !project.exists()).collect();
+        assert_eq!(missing, [false, false, true, false, false, false, false, false, false]);
+    }
+
+    #[test]
+    fn unreadable_file_gives_an_empty_list() {
+        let dir = temp_dir("unreadable");
+        let file = dir.join("recent_projects.json");
+        std::fs::write(&file, "pas du JSON").unwrap();
+        assert!(RecentProjects::load_from(&file).projects().is_empty());
+    }
+}
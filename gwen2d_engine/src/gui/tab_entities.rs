@@ -1,24 +1,37 @@
 use std::sync::Arc;
-use crate::model::{
-    entity::Entity, entity_category::EntityCategory, entity_state::EntityState, project::Project,
+use gwen2d_project::model::{
+    entity::EntityId, entity_category::CategoryId, entity_state::StateId, project::Project,
 };
 use eframe::egui;
 use eframe::egui::{Align2, ColorImage, Pos2, TextureHandle, Ui, Vec2};
-use image::{io::Reader as ImageReader, DynamicImage};
+use image::{DynamicImage, ImageReader};
 use tokio::sync::Mutex;
-use crate::db::engine_db::EngineDb;
+use gwen2d_project::db::engine_db::EngineDb;
+
+/// Object renamed in the rename window.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum RenameTarget {
+    Category(CategoryId),
+    Entity(EntityId),
+    State(EntityId, StateId),
+}
 
 pub struct TabEntities {
-    selected_category: EntityCategory,
-    selected_entity: Entity,
-    selected_state: EntityState,
+    selected_category: Option<CategoryId>,
+    selected_entity: Option<EntityId>,
+    selected_state: Option<StateId>,
     category_creation_window: bool,
     entity_creation_window: bool,
     state_creation_window: bool,
+    rename_window: bool,
+    rename_target: Option<RenameTarget>,
     name: String,
     new_category: String,
     new_entity: String,
     new_state: String,
+    new_name: String,
+    /// Why the last creation or rename was refused.
+    error: String,
     images: Vec<Option<TextureHandle>>,
     image_texture: Option<TextureHandle>,
 }
@@ -26,16 +39,20 @@ pub struct TabEntities {
 impl TabEntities {
     pub fn new() -> Self {
         TabEntities {
-            selected_category: EntityCategory::default(),
-            selected_entity: Entity::default(EntityCategory::default()),
-            selected_state: EntityState::default(),
+            selected_category: None,
+            selected_entity: None,
+            selected_state: None,
             category_creation_window: false,
             entity_creation_window: false,
             state_creation_window: false,
+            rename_window: false,
+            rename_target: None,
             name: String::new(),
             new_category: String::new(),
             new_entity: String::new(),
             new_state: String::new(),
+            new_name: String::new(),
+            error: String::new(),
             images: vec![None; 5],
             image_texture: None,
         }
@@ -65,21 +82,25 @@ impl TabEntities {
             ui.set_min_width(ctx.screen_rect().width() - 26.);
             ui.horizontal(|ui| {
                 ui.label("   Catégorie");
+                let selected = self.selected_category.and_then(|id| project.category_name(id));
                 egui::ComboBox::from_id_salt("Classe de l'entité")
-                    .selected_text(format!("{:?}", self.selected_category.name()))
+                    .selected_text(format!("{:?}", selected.unwrap_or_default()))
                     .show_ui(ui, |ui| {
-                        for category_value in project.categories.borrow_mut().iter_mut() {
-                            let label = category_value.name().clone();
+                        for category_value in project.categories.borrow().iter() {
                             ui.selectable_value(
                                 &mut self.selected_category,
-                                category_value.to_owned(),
-                                format!("{:?}", label),
+                                Some(category_value.id),
+                                format!("{:?}", category_value.name()),
                             );
                         }
                     });
                 if ui.button("+").clicked() {
+                    self.error.clear();
                     self.category_creation_window = true;
                 }
+                if let Some(id) = self.selected_category {
+                    self.rename_button(ui, project, RenameTarget::Category(id));
+                }
                 if self.create_category(ui, ctx, project) {
                     self.category_creation_window = false;
                 }
@@ -87,21 +108,26 @@ impl TabEntities {
             ui.separator();
             ui.horizontal(|ui| {
                 ui.label("Entité");
+                let selected = self.selected_entity.and_then(|id| project.entity_name(id));
                 egui::ComboBox::from_id_salt("Nom de l'entité")
-                    .selected_text(format!("{:?}", self.selected_entity.name()))
+                    .selected_text(format!("{:?}", selected.unwrap_or_default()))
                     .show_ui(ui, |ui| {
-                        for (k, v) in project.entities.borrow_mut().iter_mut() {
-                            let label = v.name().clone();
-                            ui.selectable_value(
-                                &mut self.selected_entity,
-                                v.to_owned(),
-                                format!("{:?}", label),
-                            );
+                        for v in project.entities.borrow().values() {
+                            if ui
+                                .selectable_value(&mut self.selected_entity, Some(v.id), format!("{:?}", v.name()))
+                                .clicked()
+                            {
+                                self.selected_state = None;
+                            }
                         }
                     });
                 if ui.button("+").clicked() {
+                    self.error.clear();
                     self.entity_creation_window = true;
                 }
+                if let Some(id) = self.selected_entity {
+                    self.rename_button(ui, project, RenameTarget::Entity(id));
+                }
                 if self.create_entity(ui, ctx, project) {
                     self.entity_creation_window = false;
                 }
@@ -109,28 +135,96 @@ impl TabEntities {
             ui.separator();
             ui.horizontal(|ui| {
                 ui.label("Etat");
+                let selected = match (self.selected_entity, self.selected_state) {
+                    (Some(entity_id), Some(state_id)) => project.state_name(entity_id, state_id),
+                    _ => None,
+                };
                 egui::ComboBox::from_id_salt("Nom de l'état")
-                    .selected_text(format!("{:?}", self.selected_state.name()))
+                    .selected_text(format!("{:?}", selected.unwrap_or_default()))
                     .show_ui(ui, |ui| {
-                        for v in project.get_states(&self.selected_entity.name) {
-                            let label = String::from(v.name.as_str());
-                            ui.selectable_value(
-                                &mut self.selected_state,
-                                v,
-                                format!("{:?}", label),
-                            );
+                        if let Some(entity_id) = self.selected_entity {
+                            for v in project.get_states(entity_id) {
+                                ui.selectable_value(&mut self.selected_state, Some(v.id), format!("{:?}", v.name));
+                            }
                         }
                     });
-                if ui.button("+").clicked() {
+                if ui.button("+").clicked() && self.selected_entity.is_some() {
+                    self.error.clear();
                     self.state_creation_window = true;
                 }
-                if self.create_entity_state(ui, ctx, project, &self.selected_entity.name.clone()) {
-                    self.state_creation_window = false;
+                if let (Some(entity_id), Some(state_id)) = (self.selected_entity, self.selected_state) {
+                    self.rename_button(ui, project, RenameTarget::State(entity_id, state_id));
+                }
+                if let Some(entity_id) = self.selected_entity {
+                    if self.create_entity_state(ui, ctx, project, entity_id) {
+                        self.state_creation_window = false;
+                    }
                 }
             });
+            if self.rename(ctx, project) {
+                self.rename_window = false;
+            }
         });
     }
 
+    fn rename_button(&mut self, ui: &mut Ui, project: &Project, target: RenameTarget) {
+        if ui.button("Renommer").clicked() {
+            let name = match target {
+                RenameTarget::Category(id) => project.category_name(id),
+                RenameTarget::Entity(id) => project.entity_name(id),
+                RenameTarget::State(entity_id, state_id) => project.state_name(entity_id, state_id),
+            };
+            self.new_name = name.unwrap_or_default();
+            self.error.clear();
+            self.rename_target = Some(target);
+            self.rename_window = true;
+        }
+    }
+
+    /// Window renaming a category, an entity or a state. The objects reference each other by id,
+    /// so only the renamed one changes.
+    fn rename(&mut self, ctx: &egui::Context, project: &mut Project) -> bool {
+        let Some(target) = self.rename_target else {
+            return false;
+        };
+        let current_pos = Pos2::new(100., 100.);
+        let mut cancel_window = false;
+        egui::Window::new("Renommer")
+            .collapsible(false)
+            .current_pos(current_pos)
+            .anchor(Align2::CENTER_CENTER, Vec2::new(0., 0.))
+            .open(&mut self.rename_window)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Nouveau nom");
+                    ui.text_edit_singleline(&mut self.new_name);
+                });
+                if !self.error.is_empty() {
+                    ui.colored_label(egui::Color32::RED, &self.error);
+                }
+
+                ui.horizontal(|ui| {
+                    if ui.button("Sauvegarder").clicked() {
+                        let renamed = match target {
+                            RenameTarget::Category(id) => project.rename_category(id, &self.new_name),
+                            RenameTarget::Entity(id) => project.rename_entity(id, &self.new_name),
+                            RenameTarget::State(entity_id, state_id) => {
+                                project.rename_state(entity_id, state_id, &self.new_name)
+                            }
+                        };
+                        match renamed {
+                            Ok(()) => cancel_window = true,
+                            Err(e) => self.error = e,
+                        }
+                    }
+                    if ui.button("Annuler").clicked() {
+                        cancel_window = true;
+                    }
+                });
+            });
+        cancel_window
+    }
+
     fn create_category(&mut self, ui: &mut Ui, ctx: &egui::Context, project: &mut Project) -> bool {
         let current_pos = Pos2::new(100., 100.);
         let mut cancel_window = false;
@@ -145,13 +239,18 @@ impl TabEntities {
                     ui.text_edit_singleline(&mut self.new_category);
                 });
 
+                if !self.error.is_empty() {
+                    ui.colored_label(egui::Color32::RED, &self.error);
+                }
                 ui.horizontal(|ui| {
                     if ui.button("Sauvegarder").clicked() {
-                        project
-                            .categories
-                            .borrow_mut()
-                            .push(EntityCategory::new(&self.new_category));
-                        cancel_window = true;
+                        match project.add_category(&self.new_category) {
+                            Ok(id) => {
+                                self.selected_category = Some(id);
+                                cancel_window = true;
+                            }
+                            Err(e) => self.error = e,
+                        }
                     }
                     if ui.button("Annuler").clicked() {
                         cancel_window = true;
@@ -175,10 +274,24 @@ impl TabEntities {
                     ui.text_edit_singleline(&mut self.name);
                 });
 
+                if !self.error.is_empty() {
+                    ui.colored_label(egui::Color32::RED, &self.error);
+                }
                 ui.horizontal(|ui| {
                     if ui.button("Sauvegarder").clicked() {
-                        project.add_entity(&self.selected_category, &self.name);
-                        cancel_window = true;
+                        let category = self.selected_category.or_else(|| project.default_category());
+                        let created = match category {
+                            Some(category) => project.add_entity(category, &self.name),
+                            None => Err(String::from("aucune catégorie")),
+                        };
+                        match created {
+                            Ok(id) => {
+                                self.selected_entity = Some(id);
+                                self.selected_state = None;
+                                cancel_window = true;
+                            }
+                            Err(e) => self.error = e,
+                        }
                     }
                     if ui.button("Annuler").clicked() {
                         cancel_window = true;
@@ -193,7 +306,7 @@ impl TabEntities {
         ui: &mut Ui,
         ctx: &egui::Context,
         project: &mut Project,
-        entity_name: &String,
+        entity_id: EntityId,
     ) -> bool {
         let current_pos = Pos2::new(100., 100.);
         let mut cancel_window = false;
@@ -208,10 +321,18 @@ impl TabEntities {
                     ui.text_edit_singleline(&mut self.new_state);
                 });
 
+                if !self.error.is_empty() {
+                    ui.colored_label(egui::Color32::RED, &self.error);
+                }
                 ui.horizontal(|ui| {
                     if ui.button("Sauvegarder").clicked() {
-                        project.add_entity_state(entity_name, &self.new_state);
-                        cancel_window = true;
+                        match project.add_entity_state(entity_id, &self.new_state) {
+                            Ok(id) => {
+                                self.selected_state = Some(id);
+                                cancel_window = true;
+                            }
+                            Err(e) => self.error = e,
+                        }
                     }
                     if ui.button("Annuler").clicked() {
                         cancel_window = true;
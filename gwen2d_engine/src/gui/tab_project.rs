@@ -1,30 +1,90 @@
-use crate::db::engine_db::EngineDb;
+use crate::config::recent_projects::RecentProjects;
 use dirs_next::home_dir;
-use eframe::egui::{Align2, Pos2, Ui, Vec2};
+use eframe::egui::{Align2, Pos2, Ui, Vec2, ViewportCommand};
 use eframe::epaint::FontId;
 use egui::{Color32, RichText};
-use std::path::PathBuf;
+use gwen2d_project::db::autosave::{self, Recovery};
+use gwen2d_project::db::engine_db::{DbError, EngineDb};
+use gwen2d_project::db::integrity::Issue;
+use gwen2d_project::db::project_repository::{ProjectData, ProjectSnapshot};
+use gwen2d_project::model::project::Project;
+use gwen2d_project::model::template::ProjectTemplate;
+use log::error;
+use rfd::{MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use tokio::runtime::Runtime;
 use tokio::sync::Mutex;
 
+/// Title of the editor window, followed by the project name once one is loaded.
+pub const EDITOR_TITLE: &str = "GWEN 2D ENGINE";
+
+/// Project read in the background, shown by the next frame.
+struct LoadedProject {
+    name: String,
+    path: PathBuf,
+    /// Database of the project, replacing the one open until now. `None` keeps it.
+    db: Option<EngineDb>,
+    data: ProjectData,
+    /// First id never used in the database.
+    next_id: i64,
+    /// Changes autosaved and never saved in the database.
+    recovery: Option<Recovery>,
+}
+
+type OpenedProject = Result<LoadedProject, DbError>;
+
+/// Where the database of an opened project comes from.
+enum ProjectSource {
+    Existing,
+    /// New database filled from the export in the directory.
+    Import(PathBuf),
+    /// New database filled from a template.
+    Template(ProjectData),
+}
+
+/// Integrity check of the open project run in the background.
+enum IntegrityState {
+    Running,
+    Checked(Vec<Issue>),
+    /// The issues were fixed and the project is being reloaded.
+    Repaired(Vec<Issue>),
+    Failed(String),
+}
+
 pub struct TabProject {
     path: Option<String>,
+    project_name: String,
     new_project_name: String,
     new_project_path: String,
+    new_project_template: ProjectTemplate,
     project_creation_window: bool,
     loaded_project: bool,
+    opened_project: Arc<std::sync::Mutex<Option<OpenedProject>>>,
+    export_status: Arc<std::sync::Mutex<String>>,
+    integrity_window: bool,
+    integrity: Arc<std::sync::Mutex<IntegrityState>>,
+    recent_projects: RecentProjects,
 }
 
 impl TabProject {
     pub fn new() -> Self {
+        let recent_projects = RecentProjects::load();
+        let new_project_path = recent_projects.last_directory().or_else(home_dir).unwrap_or_default();
         TabProject {
             path: None,
+            project_name: String::new(),
             new_project_name: String::new(),
-            new_project_path: home_dir().unwrap().to_string_lossy().to_string(),
+            new_project_path: new_project_path.to_string_lossy().to_string(),
+            new_project_template: ProjectTemplate::Empty,
             project_creation_window: false,
             loaded_project: false,
+            opened_project: Arc::new(std::sync::Mutex::new(None)),
+            export_status: Arc::new(std::sync::Mutex::new(String::new())),
+            integrity_window: false,
+            integrity: Arc::new(std::sync::Mutex::new(IntegrityState::Running)),
+            recent_projects,
         }
     }
 
@@ -38,7 +98,12 @@ impl TabProject {
         ctx: &egui::Context,
         runtime: &mut Arc<Runtime>,
         db: &mut Arc<Mutex<EngineDb>>,
+        project: &mut Project,
     ) {
+        let opened = self.opened_project.lock().unwrap().take();
+        if let Some(opened) = opened {
+            self.finish_opening(ctx, db, project, opened);
+        }
         ui.horizontal(|ui| {
             ui.vertical(|ui| {
                 if ui
@@ -52,34 +117,247 @@ impl TabProject {
                     .button(RichText::new("Ouvrir un projet").font(FontId::proportional(20.0)))
                     .clicked()
                 {
-                    if let Some(path) = rfd::FileDialog::new().pick_file() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Projet GWEN 2D", &["db"])
+                        .pick_file()
+                    {
                         self.path = Some(path.display().to_string());
+                        let current = self.current_project(project);
+                        let opened = self.opened_project.clone();
+                        open_project(runtime, db, &path, ProjectSource::Existing, current, opened, ctx.clone());
                     }
                 }
-                if self.manage_project_creation(ui, ctx, runtime, db) {
+                ui.add_space(10.);
+                if ui
+                    .button(RichText::new("Importer un projet").font(FontId::proportional(20.0)))
+                    .clicked()
+                {
+                    if let Some(dir) = rfd::FileDialog::new().set_title("Export à importer").pick_folder() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .set_title("Nouveau projet")
+                            .add_filter("Projet GWEN 2D", &["db"])
+                            .save_file()
+                        {
+                            self.path = Some(dir.display().to_string());
+                            let source = ProjectSource::Import(dir);
+                            let current = self.current_project(project);
+                            open_project(runtime, db, &path, source, current, self.opened_project.clone(), ctx.clone());
+                        }
+                    }
+                }
+                if self.loaded_project {
+                    ui.add_space(10.);
+                    if ui
+                        .button(RichText::new("Exporter le projet").font(FontId::proportional(20.0)))
+                        .clicked()
+                    {
+                        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.export_project(runtime, db, project, dir);
+                        }
+                    }
+                    ui.label(self.export_status.lock().unwrap().as_str());
+                    ui.add_space(10.);
+                    if ui
+                        .button(RichText::new("Vérifier le projet").font(FontId::proportional(20.0)))
+                        .clicked()
+                    {
+                        self.check_integrity(runtime, db, ctx);
+                    }
+                }
+                if self.manage_project_creation(ui, ctx, runtime, db, project) {
                     self.project_creation_window = false;
                 }
             });
             ui.add_space(100.);
             let text = if self.loaded_project {
-                RichText::new(self.new_project_name.clone())
+                RichText::new(self.project_name.clone())
             } else {
                 RichText::new("Aucun projet chargé")
             };
             ui.label(text.font(FontId::proportional(40.0)).color(Color32::WHITE))
         });
+        self.display_recent_projects(ui, ctx, runtime, db, project);
+        self.display_integrity(ctx, runtime, db, project);
         self.display_stats(ui, ctx);
     }
 
+    /// Check the project database in the background and show the problems found in a window.
+    fn check_integrity(&mut self, runtime: &mut Arc<Runtime>, db: &mut Arc<Mutex<EngineDb>>, ctx: &egui::Context) {
+        self.integrity_window = true;
+        *self.integrity.lock().unwrap() = IntegrityState::Running;
+        let integrity = self.integrity.clone();
+        let db = db.clone();
+        let ctx = ctx.clone();
+        runtime.spawn(async move {
+            let result = db.lock().await.check_integrity().await;
+            *integrity.lock().unwrap() = match result {
+                Ok(issues) => IntegrityState::Checked(issues),
+                Err(e) => {
+                    error!("DB:integrity check failed : {}", e);
+                    IntegrityState::Failed(e.to_string())
+                }
+            };
+            ctx.request_repaint();
+        });
+    }
+
+    /// Save the project, fix the problems of its database and reload it, as the repair may
+    /// change or remove entities.
+    fn repair_integrity(
+        &mut self,
+        runtime: &mut Arc<Runtime>,
+        db: &mut Arc<Mutex<EngineDb>>,
+        project: &Project,
+        ctx: &egui::Context,
+    ) {
+        let data = match ProjectData::from_project(project) {
+            Ok(data) => data,
+            Err(e) => {
+                *self.integrity.lock().unwrap() = IntegrityState::Failed(e.to_string());
+                return;
+            }
+        };
+        *self.integrity.lock().unwrap() = IntegrityState::Running;
+        let integrity = self.integrity.clone();
+        let opened = self.opened_project.clone();
+        let name = self.project_name.clone();
+        let db = db.clone();
+        let ctx = ctx.clone();
+        runtime.spawn(async move {
+            let db = db.lock().await;
+            let repaired = match db.save_project(&data).await {
+                Ok(()) => db.repair_integrity().await,
+                Err(e) => Err(e),
+            };
+            let reloaded = match repaired {
+                Ok(issues) => match db.load_project().await {
+                    Ok(data) => db.next_id().await.map(|next_id| (issues, data, next_id)),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            *integrity.lock().unwrap() = match reloaded {
+                Ok((issues, data, next_id)) => {
+                    let path = db.path().map(Path::to_path_buf).unwrap_or_default();
+                    *opened.lock().unwrap() = Some(Ok(LoadedProject {
+                        name,
+                        path,
+                        db: None,
+                        data,
+                        next_id,
+                        recovery: None,
+                    }));
+                    IntegrityState::Repaired(issues)
+                }
+                Err(e) => {
+                    error!("DB:integrity repair failed : {}", e);
+                    IntegrityState::Failed(e.to_string())
+                }
+            };
+            ctx.request_repaint();
+        });
+    }
+
+    fn display_integrity(
+        &mut self,
+        ctx: &egui::Context,
+        runtime: &mut Arc<Runtime>,
+        db: &mut Arc<Mutex<EngineDb>>,
+        project: &Project,
+    ) {
+        let mut repair = false;
+        egui::Window::new("Vérification du projet")
+            .collapsible(false)
+            .open(&mut self.integrity_window)
+            .show(ctx, |ui| match &*self.integrity.lock().unwrap() {
+                IntegrityState::Running => {
+                    ui.spinner();
+                }
+                IntegrityState::Checked(issues) if issues.is_empty() => {
+                    ui.label("Aucun problème trouvé");
+                }
+                IntegrityState::Checked(issues) => {
+                    ui.label(format!("{} problèmes trouvés :", issues.len()));
+                    egui::ScrollArea::vertical().max_height(300.).show(ui, |ui| {
+                        for issue in issues {
+                            ui.label(issue.to_string());
+                        }
+                    });
+                    ui.separator();
+                    repair = ui
+                        .button("Réparer")
+                        .on_hover_text(
+                            "Le projet est sauvegardé puis les lignes irréparables sont mises en quarantaine",
+                        )
+                        .clicked();
+                }
+                IntegrityState::Repaired(issues) => {
+                    ui.label(format!("{} problèmes corrigés", issues.len()));
+                }
+                IntegrityState::Failed(e) => {
+                    ui.colored_label(Color32::RED, format!("❌ Erreur : {}", e));
+                }
+            });
+        if repair {
+            self.repair_integrity(runtime, db, project, ctx);
+        }
+    }
+
+    /// Recent projects, most recent first. A project whose file is missing cannot be opened, only
+    /// removed from the list.
+    fn display_recent_projects(
+        &mut self,
+        ui: &mut Ui,
+        ctx: &egui::Context,
+        runtime: &mut Arc<Runtime>,
+        db: &mut Arc<Mutex<EngineDb>>,
+        project: &Project,
+    ) {
+        let mut opened = None;
+        let mut removed = None;
+        ui.group(|ui| {
+            ui.label(RichText::new("Projets récents").font(FontId::proportional(20.0)));
+            if self.recent_projects.projects().is_empty() {
+                ui.label("Aucun projet récent");
+            }
+            for recent in self.recent_projects.projects() {
+                ui.horizontal(|ui| {
+                    let exists = recent.exists();
+                    if ui.add_enabled(exists, egui::Button::new(&recent.name)).clicked() {
+                        opened = Some(recent.path.clone());
+                    }
+                    ui.label(recent.last_opened_label());
+                    ui.label(recent.path.display().to_string());
+                    if !exists {
+                        ui.colored_label(Color32::RED, "fichier introuvable");
+                    }
+                    if ui.button("Retirer").clicked() {
+                        removed = Some(recent.path.clone());
+                    }
+                });
+            }
+        });
+        if let Some(path) = opened {
+            self.path = Some(path.display().to_string());
+            let current = self.current_project(project);
+            open_project(runtime, db, &path, ProjectSource::Existing, current, self.opened_project.clone(), ctx.clone());
+        }
+        if let Some(path) = removed {
+            self.recent_projects.remove(&path);
+        }
+    }
+
     fn manage_project_creation(
         &mut self,
         ui: &mut Ui,
         ctx: &eframe::egui::Context,
         runtime: &mut Arc<Runtime>,
         db: &mut Arc<Mutex<EngineDb>>,
+        project: &Project,
     ) -> bool {
         let current_pos = Pos2::new(100., 100.);
         let mut cancel_window = false;
+        let mut created = None;
         egui::Window::new("Nouveau projet")
             .collapsible(false)
             .resizable(false)
@@ -88,27 +366,36 @@ impl TabProject {
             .open(&mut self.project_creation_window)
             .show(ctx, |ui| {
                 let mut db_path = None;
-                let mut root_path = PathBuf::from_str(&self.new_project_path).unwrap();
                 ui.horizontal(|ui| {
                     ui.label("Nom du projet");
                     ui.text_edit_singleline(&mut self.new_project_name);
                 });
-                if !self.new_project_name.is_empty() {
-                    db_path = Some(root_path.join(self.new_project_name.clone()));
-                }
+                ui.horizontal(|ui| {
+                    ui.label("Modèle");
+                    egui::ComboBox::from_id_salt("Modèle de projet")
+                        .selected_text(self.new_project_template.label())
+                        .show_ui(ui, |ui| {
+                            for template in ProjectTemplate::ALL {
+                                ui.selectable_value(&mut self.new_project_template, template, template.label());
+                            }
+                        });
+                });
                 if ui.button("Répertoire").clicked() {
-                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                        db_path = Some(path.join(self.new_project_name.clone()));
+                    if let Some(path) = rfd::FileDialog::new().set_directory(&self.new_project_path).pick_folder() {
+                        self.new_project_path = path.to_string_lossy().to_string();
                     }
                 }
+                if !self.new_project_name.is_empty() {
+                    let root_path = PathBuf::from_str(&self.new_project_path).unwrap();
+                    db_path = Some(root_path.join(self.new_project_name.clone()));
+                }
                 ui.separator();
                 ui.horizontal_wrapped(|ui| {
                     let save_button = egui::Button::new("Sauvegarder");
                     match db_path {
                         Some(path) => {
                             ui.add_enabled(true, save_button).clicked().then(|| {
-                                create_new_project(runtime, db, &path.clone());
-                                self.loaded_project = true;
+                                created = Some(path);
                                 cancel_window = true
                             });
                         }
@@ -120,9 +407,107 @@ impl TabProject {
                 });
                 ui.label(self.new_project_path.clone());
             });
+        if let Some(path) = created {
+            let new_project = self.new_project_template.build(self.new_project_name.clone());
+            let current = self.current_project(project);
+            let opened = self.opened_project.clone();
+            match ProjectData::from_project(&new_project) {
+                Ok(data) => open_project(runtime, db, &path, ProjectSource::Template(data), current, opened, ctx.clone()),
+                Err(e) => *opened.lock().unwrap() = Some(Err(e)),
+            }
+            self.path = Some(path.display().to_string());
+        }
         cancel_window
     }
 
+    /// Project to save before another one replaces it, if one is open.
+    fn current_project(&self, project: &Project) -> Option<ProjectSnapshot> {
+        self.loaded_project.then(|| ProjectSnapshot::new(project))
+    }
+
+    /// Show the project read in the background, or why it could not be opened. The database and
+    /// the model are replaced together, so that no save writes a project into the database of
+    /// another.
+    fn finish_opening(
+        &mut self,
+        ctx: &egui::Context,
+        db: &mut Arc<Mutex<EngineDb>>,
+        project: &mut Project,
+        opened: OpenedProject,
+    ) {
+        let opened = opened.and_then(|opened| {
+            let data = match opened.recovery {
+                Some(recovery) if ask_recovery(&opened.name, &recovery) => recovery.data,
+                Some(_) => {
+                    if let Err(e) = autosave::discard(&opened.path) {
+                        error!("DB:autosave discard failed : {}", e);
+                    }
+                    opened.data
+                }
+                None => opened.data,
+            };
+            let mut loaded = data.into_project(&opened.name)?;
+            loaded.reserve_ids_below(opened.next_id);
+            Ok((loaded, opened.db, opened.name, opened.path))
+        });
+        match opened {
+            Ok((loaded, new_db, name, path)) => {
+                // The saves already started keep the previous database.
+                if let Some(new_db) = new_db {
+                    *db = Arc::new(Mutex::new(new_db));
+                }
+                *project = loaded;
+                self.recent_projects.touch(&name, &path);
+                self.set_loaded(ctx, &name);
+            }
+            Err(e) => {
+                error!("DB:project open failed : {}", e);
+                MessageDialog::new()
+                    .set_level(MessageLevel::Error)
+                    .set_title("Ouverture du projet")
+                    .set_description(format!(
+                        "Impossible d'ouvrir {} :\n{}",
+                        self.path.clone().unwrap_or_default(),
+                        e
+                    ))
+                    .set_buttons(MessageButtons::Ok)
+                    .show();
+            }
+        }
+    }
+
+    /// Export the project in the background, the result is shown under the export button.
+    fn export_project(&mut self, runtime: &mut Arc<Runtime>, db: &mut Arc<Mutex<EngineDb>>, project: &Project, dir: PathBuf) {
+        let status = self.export_status.clone();
+        let data = match ProjectData::from_project(project) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("DB:project export failed : {}", e);
+                *status.lock().unwrap() = format!("❌ Erreur : {}", e);
+                return;
+            }
+        };
+        let db = db.clone();
+        let name = self.project_name.clone();
+        *status.lock().unwrap() = String::from("Export...");
+        runtime.spawn(async move {
+            let result = db.lock().await.export_project(&name, &data, &dir).await;
+            *status.lock().unwrap() = match result {
+                Ok(()) => format!("Projet exporté dans {}", dir.display()),
+                Err(e) => {
+                    error!("DB:project export failed : {}", e);
+                    format!("❌ Erreur : {}", e)
+                }
+            };
+        });
+    }
+
+    fn set_loaded(&mut self, ctx: &egui::Context, name: &str) {
+        self.project_name = String::from(name);
+        self.loaded_project = true;
+        ctx.send_viewport_cmd(ViewportCommand::Title(format!("{} - {}", EDITOR_TITLE, name)));
+    }
+
     fn display_stats(&mut self, ui: &mut Ui, ctx: &eframe::egui::Context) {
         ui.group(|ui| {
             ui.horizontal(|ui| {
@@ -135,15 +520,98 @@ impl TabProject {
     }
 }
 
-fn create_new_project(
+/// Ask whether the changes autosaved before the editor stopped should be restored.
+fn ask_recovery(name: &str, recovery: &Recovery) -> bool {
+    let saved_at = recovery
+        .saved_at
+        .map(|date| format!(" le {}", date.format("%d/%m/%Y à %H:%M")))
+        .unwrap_or_default();
+    let answer = MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("Récupération du projet")
+        .set_description(format!(
+            "L'éditeur ne s'est pas fermé correctement. Des modifications de {} non sauvegardées ont \
+             été enregistrées automatiquement{}.\n\nRestaurer ces modifications ? Sinon elles seront \
+             supprimées.",
+            name, saved_at
+        ))
+        .set_buttons(MessageButtons::YesNo)
+        .show();
+    answer == MessageDialogResult::Yes
+}
+
+/// Save the project open until now, then open the project database and read the project in the
+/// background, first creating the database for a new source. The project open until now is kept
+/// if it fails.
+fn open_project(
     runtime: &mut Arc<Runtime>,
     db: &mut Arc<Mutex<EngineDb>>,
-    db_path: &PathBuf,
+    db_path: &Path,
+    source: ProjectSource,
+    current: Option<ProjectSnapshot>,
+    opened: Arc<std::sync::Mutex<Option<OpenedProject>>>,
+    ctx: egui::Context,
 ) {
-    let mut db_to_open = db.clone();
-    let path = db_path.clone();
+    let db = db.clone();
+    let path = match source {
+        ProjectSource::Existing => db_path.to_path_buf(),
+        _ => db_path.with_extension("db"),
+    };
     runtime.spawn(async move {
-        let mut db_locked = db_to_open.lock().await;
-        db_locked.open(&path).await;
+        if let Some(current) = current {
+            let saved = match current.encode() {
+                Ok(data) => db.lock().await.save_project(&data).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = saved {
+                error!("DB:project save before opening failed : {}", e);
+                *opened.lock().unwrap() = Some(Err(e));
+                ctx.request_repaint();
+                return;
+            }
+        }
+        let mut new_db = EngineDb::new();
+        let result = match source {
+            ProjectSource::Existing => new_db.open_existing(&path).await,
+            ProjectSource::Import(dir) => new_db.import_project(&dir, &path).await.map(|_| ()),
+            ProjectSource::Template(data) => match new_db.create(&path).await {
+                Ok(()) => new_db.save_project(&data).await,
+                Err(e) => Err(e),
+            },
+        };
+        let result = match result {
+            Ok(()) => match new_db.load_project().await {
+                Ok(data) => new_db.next_id().await.map(|next_id| (data, next_id)),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        let recovery = match &result {
+            Ok((data, _)) => match new_db.recovery().await {
+                // Nothing to recover if the autosave has the saved project.
+                Ok(Some(recovery)) if recovery.data == *data => {
+                    if let Err(e) = new_db.discard_autosave() {
+                        error!("DB:autosave discard failed : {}", e);
+                    }
+                    None
+                }
+                Ok(recovery) => recovery,
+                Err(e) => {
+                    error!("DB:autosave unreadable : {}", e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        *opened.lock().unwrap() = Some(result.map(|(data, next_id)| LoadedProject {
+            name,
+            path,
+            db: Some(new_db),
+            data,
+            next_id,
+            recovery,
+        }));
+        ctx.request_repaint();
     });
 }
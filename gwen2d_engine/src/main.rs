@@ -1,25 +1,23 @@
-use crate::db::engine_db;
 use crate::gui::tab_project::TabProject;
 use eframe::egui;
 use eframe::egui::ViewportBuilder;
 use egui::{TextBuffer, Widget};
 use gui::tab_entities::TabEntities;
-use log::info;
-use model::project::Project;
+use gwen2d_project::db::autosave::AUTOSAVE_INTERVAL;
+use gwen2d_project::db::engine_db;
+use gwen2d_project::db::project_repository::ProjectSnapshot;
+use gwen2d_project::model::project::Project;
+use log::{error, info};
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::runtime::Runtime;
 use tokio::sync::Mutex;
 
-mod db {
-    pub mod engine_db;
-}
+mod cli;
 
-mod model {
-    pub mod entity;
-    pub mod entity_category;
-    pub mod entity_state;
-    pub mod project;
+mod config {
+    pub mod recent_projects;
 }
 
 mod gui {
@@ -37,13 +35,17 @@ enum EngineEditorTab {
 
 fn main() -> eframe::Result {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
     info!("START");
     let options = eframe::NativeOptions {
         viewport: ViewportBuilder::default().with_inner_size([1024., 768.]),
         ..Default::default()
     };
     eframe::run_native(
-        "GWEN 2D ENGINE",
+        gui::tab_project::EDITOR_TITLE,
         options,
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
@@ -63,9 +65,52 @@ struct MyApp {
     name_character: String,
     project: Project,
     db: Arc<Mutex<engine_db::EngineDb>>,
+    save_status: Arc<std::sync::Mutex<String>>,
+    last_autosave: Instant,
 }
 
-impl MyApp {}
+impl MyApp {
+    /// Save the project in the background, the result is shown next to the save button. The
+    /// frames are encoded by the background task.
+    fn save_project(&mut self) {
+        let snapshot = ProjectSnapshot::new(&self.project);
+        let db = self.db.clone();
+        let status = self.save_status.clone();
+        *status.lock().unwrap() = String::from("Sauvegarde...");
+        self.runtime.spawn(async move {
+            let result = match snapshot.encode() {
+                Ok(data) => db.lock().await.save_project(&data).await,
+                Err(e) => Err(e),
+            };
+            *status.lock().unwrap() = match result {
+                Ok(()) => String::from("Projet sauvegardé"),
+                Err(e) => {
+                    error!("DB:project save failed : {}", e);
+                    format!("❌ Erreur : {}", e)
+                }
+            };
+        });
+    }
+
+    /// Autosave the project in the background if it changed, so that a crash loses at most the
+    /// last changes.
+    fn autosave_project(&mut self) {
+        self.last_autosave = Instant::now();
+        let snapshot = ProjectSnapshot::new(&self.project);
+        let db = self.db.clone();
+        let status = self.save_status.clone();
+        self.runtime.spawn(async move {
+            let result = match snapshot.encode() {
+                Ok(data) => db.lock().await.autosave_project(&data).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("DB:project autosave failed : {}", e);
+                *status.lock().unwrap() = format!("❌ Erreur : {}", e);
+            }
+        });
+    }
+}
 
 impl Default for MyApp {
     fn default() -> Self {
@@ -80,12 +125,20 @@ impl Default for MyApp {
             name_character: String::new(),
             project: Project::new("default".to_string()),
             db: Arc::new(Mutex::new(engine_db::EngineDb::new())),
+            save_status: Arc::new(std::sync::Mutex::new(String::new())),
+            last_autosave: Instant::now(),
         }
     }
 }
 
 impl eframe::App for MyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if self.tab_project.is_loaded() {
+            if self.last_autosave.elapsed() >= AUTOSAVE_INTERVAL {
+                self.autosave_project();
+            }
+            ctx.request_repaint_after(AUTOSAVE_INTERVAL);
+        }
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.selectable_value(&mut self.tab, EngineEditorTab::Project, "Projet");
@@ -93,6 +146,11 @@ impl eframe::App for MyApp {
                     ui.selectable_value(&mut self.tab, EngineEditorTab::Character, "Personnages");
                     ui.selectable_value(&mut self.tab, EngineEditorTab::Scene, "Scenes");
                     ui.selectable_value(&mut self.tab, EngineEditorTab::Level, "Niveaux");
+                    ui.separator();
+                    if ui.button("Sauvegarder").clicked() {
+                        self.save_project();
+                    }
+                    ui.label(self.save_status.lock().unwrap().as_str());
                 }
             });
             ui.separator();
@@ -116,7 +174,7 @@ impl eframe::App for MyApp {
                 }
                 _ => {
                     self.tab_project
-                        .show_ui(ui, ctx, &mut self.runtime, &mut self.db);
+                        .show_ui(ui, ctx, &mut self.runtime, &mut self.db, &mut self.project);
                 }
             }
 
@@ -132,4 +190,20 @@ impl eframe::App for MyApp {
             ui.label(format!("Hello '{}', age {}", self.name, self.age));
         });
     }
+
+    /// Save the open project before quitting, so that closing the editor keeps the work.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if !self.tab_project.is_loaded() {
+            return;
+        }
+        let snapshot = ProjectSnapshot::new(&self.project);
+        let db = self.db.clone();
+        let result = self.runtime.block_on(async move {
+            let data = snapshot.encode()?;
+            db.lock().await.save_project(&data).await
+        });
+        if let Err(e) = result {
+            error!("DB:project save on exit failed : {}", e);
+        }
+    }
 }
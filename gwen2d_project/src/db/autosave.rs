@@ -0,0 +1,135 @@
+use crate::db::engine_db::{self, DbError};
+use crate::db::project_repository::{self, ProjectData};
+use chrono::{DateTime, Local};
+use log::info;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Time between two autosaves of the open project.
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Changes found in the autosave of a project at opening, left by an editor which did not quit
+/// normally.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Recovery {
+    pub data: ProjectData,
+    pub saved_at: Option<DateTime<Local>>,
+}
+
+/// `<project>.autosave.db` next to the project database. It has the schema of a project and
+/// exists only while the project has changes not saved in its database.
+pub fn sidecar_path(db_path: &Path) -> PathBuf {
+    let stem = db_path.file_stem().unwrap_or_default().to_string_lossy();
+    db_path.with_file_name(format!("{}.autosave.db", stem))
+}
+
+pub fn exists(db_path: &Path) -> bool {
+    sidecar_path(db_path).is_file()
+}
+
+/// Replace the content of the autosave with the project.
+pub async fn write(db_path: &Path, data: &ProjectData) -> Result<(), DbError> {
+    let sidecar = sidecar_path(db_path);
+    let pool = engine_db::connect(&sidecar.to_string_lossy().replace('\\', "/"), true).await?;
+    let saved = project_repository::save(&pool, data).await;
+    pool.close().await;
+    saved?;
+    info!("DB:project autosaved to {}", sidecar.display());
+    Ok(())
+}
+
+/// Content of the autosave, if there is one.
+pub async fn read(db_path: &Path) -> Result<Option<Recovery>, DbError> {
+    let sidecar = sidecar_path(db_path);
+    if !sidecar.is_file() {
+        return Ok(None);
+    }
+    let saved_at = std::fs::metadata(&sidecar)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(DateTime::<Local>::from);
+    let pool = engine_db::connect(&sidecar.to_string_lossy().replace('\\', "/"), false).await?;
+    let data = project_repository::load(&pool).await;
+    pool.close().await;
+    Ok(Some(Recovery { data: data?, saved_at }))
+}
+
+/// Delete the autosave, with the journal files SQLite may have left.
+pub fn discard(db_path: &Path) -> Result<(), DbError> {
+    let sidecar = sidecar_path(db_path);
+    let existed = sidecar.exists();
+    for suffix in ["", "-wal", "-shm", "-journal"] {
+        let path = PathBuf::from(format!("{}{}", sidecar.display(), suffix));
+        if path.exists() {
+            std::fs::remove_file(&path).map_err(|e| DbError::Autosave(format!("{} : {}", path.display(), e)))?;
+        }
+    }
+    if existed {
+        info!("DB:autosave {} discarded", sidecar.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::engine_db::EngineDb;
+    use crate::db::project_repository::CategoryData;
+    use crate::model::entity_category::CategoryId;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gwen2d_autosave_{}_{}.db", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        let _ = discard(&path);
+        path
+    }
+
+    fn project(categories: &[&str]) -> ProjectData {
+        ProjectData {
+            categories: categories
+                .iter()
+                .enumerate()
+                .map(|(i, name)| CategoryData {
+                    id: CategoryId(i as i64 + 1),
+                    name: String::from(*name),
+                })
+                .collect(),
+            entities: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn unsaved_changes_are_recovered_after_a_crash() {
+        let path = temp_db("crash");
+        let mut db = EngineDb::new();
+        db.create(&path).await.unwrap();
+        db.save_project(&project(&["Aucune"])).await.unwrap();
+        db.autosave_project(&project(&["Aucune"])).await.unwrap();
+        assert!(!exists(&path), "an unchanged project must not be autosaved");
+        db.autosave_project(&project(&["Aucune", "Décors"])).await.unwrap();
+        assert!(exists(&path));
+        // The editor stops without saving.
+        drop(db);
+
+        let mut db = EngineDb::new();
+        db.open_existing(&path).await.unwrap();
+        assert_eq!(db.load_project().await.unwrap(), project(&["Aucune"]));
+        let recovery = db.recovery().await.unwrap().unwrap();
+        assert_eq!(recovery.data, project(&["Aucune", "Décors"]));
+        db.save_project(&recovery.data).await.unwrap();
+        assert!(!exists(&path));
+        assert_eq!(db.recovery().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn reverted_changes_delete_the_autosave() {
+        let path = temp_db("revert");
+        let mut db = EngineDb::new();
+        db.create(&path).await.unwrap();
+        db.save_project(&project(&["Aucune"])).await.unwrap();
+        db.autosave_project(&project(&[])).await.unwrap();
+        assert!(exists(&path));
+        db.autosave_project(&project(&["Aucune"])).await.unwrap();
+        assert!(!exists(&path));
+    }
+}
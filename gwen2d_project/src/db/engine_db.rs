@@ -0,0 +1,319 @@
+use crate::db::autosave::{self, Recovery};
+use crate::db::integrity::{self, Issue};
+use crate::db::migration::{self, SCHEMA_VERSION};
+use crate::db::project_export;
+use crate::db::project_repository::{self, ProjectData};
+use log::{error, info, warn};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::{Pool, Sqlite};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub enum DbError {
+    Sqlx(sqlx::Error),
+    /// The project was written by a newer version of the editor, its schema version is given.
+    NewerSchema(u32),
+    /// The project must be upgraded by the editor before the game can read it, its schema
+    /// version is given.
+    OlderSchema(u32),
+    /// No project database is open.
+    NotOpen,
+    /// The file is a database of another application, the reason is given.
+    NotAProject(String),
+    /// A frame image could not be encoded or decoded.
+    Image(image::ImageError),
+    /// A new project would overwrite this file.
+    AlreadyExists(std::path::PathBuf),
+    /// A project export could not be written or read, the reason is given.
+    Export(String),
+    /// The autosave of the project could not be deleted, the reason is given.
+    Autosave(String),
+}
+
+impl Display for DbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::Sqlx(e) => write!(f, "{}", e),
+            DbError::NewerSchema(version) => write!(
+                f,
+                "projet en version {}, plus récente que la version {} gérée par cet éditeur",
+                version, SCHEMA_VERSION
+            ),
+            DbError::OlderSchema(version) => write!(
+                f,
+                "projet en version {}, ouvrez-le dans l'éditeur pour le mettre à la version {}",
+                version, SCHEMA_VERSION
+            ),
+            DbError::NotOpen => write!(f, "aucun projet ouvert"),
+            DbError::NotAProject(reason) => write!(f, "ce fichier n'est pas un projet GWEN 2D : {}", reason),
+            DbError::Image(e) => write!(f, "image invalide : {}", e),
+            DbError::AlreadyExists(path) => write!(f, "le fichier {} existe déjà", path.display()),
+            DbError::Export(reason) => write!(f, "export du projet impossible : {}", reason),
+            DbError::Autosave(reason) => write!(f, "autosauvegarde impossible : {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Sqlx(e) => Some(e),
+            DbError::Image(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(e: sqlx::Error) -> Self {
+        DbError::Sqlx(e)
+    }
+}
+
+impl From<image::ImageError> for DbError {
+    fn from(e: image::ImageError) -> Self {
+        DbError::Image(e)
+    }
+}
+
+pub struct EngineDb {
+    name: Arc<Mutex<String>>,
+    db : Option<Pool<Sqlite>>,
+    path: Option<PathBuf>,
+    /// Project as last read from or written to the database.
+    saved: Mutex<Option<ProjectData>>,
+    /// Project as last written to the autosave.
+    autosaved: Mutex<Option<ProjectData>>,
+}
+
+impl Default for EngineDb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EngineDb {
+    pub fn new() -> Self {
+        EngineDb {
+            name: Arc::new(Mutex::new(String::from(""))),
+            db : None,
+            path: None,
+            saved: Mutex::new(None),
+            autosaved: Mutex::new(None),
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.db.is_some()
+    }
+
+    pub fn pool(&self) -> Option<&Pool<Sqlite>> {
+        self.db.as_ref()
+    }
+
+    /// Read the whole project.
+    pub async fn load_project(&self) -> Result<ProjectData, DbError> {
+        let data = project_repository::load(self.db.as_ref().ok_or(DbError::NotOpen)?).await?;
+        *self.saved.lock().unwrap() = Some(data.clone());
+        Ok(data)
+    }
+
+    /// First id never given to a category, entity or state of the database, deleted ones included.
+    pub async fn next_id(&self) -> Result<i64, DbError> {
+        project_repository::next_id(self.db.as_ref().ok_or(DbError::NotOpen)?).await
+    }
+
+    /// Write the whole project in one transaction. The autosave is deleted as the database now
+    /// has every change.
+    pub async fn save_project(&self, project: &ProjectData) -> Result<(), DbError> {
+        project_repository::save(self.db.as_ref().ok_or(DbError::NotOpen)?, project).await?;
+        *self.saved.lock().unwrap() = Some(project.clone());
+        self.discard_autosave()
+    }
+
+    /// Write the project to its autosave if it changed since the last save or autosave. The
+    /// autosave is deleted if the project is back to its saved state.
+    pub async fn autosave_project(&self, project: &ProjectData) -> Result<(), DbError> {
+        let path = self.path.as_ref().ok_or(DbError::NotOpen)?;
+        if self.saved.lock().unwrap().as_ref() == Some(project) {
+            return self.discard_autosave();
+        }
+        if self.autosaved.lock().unwrap().as_ref() == Some(project) {
+            return Ok(());
+        }
+        autosave::write(path, project).await?;
+        *self.autosaved.lock().unwrap() = Some(project.clone());
+        Ok(())
+    }
+
+    /// Changes autosaved but never saved in the project, if the editor did not quit normally.
+    pub async fn recovery(&self) -> Result<Option<Recovery>, DbError> {
+        match &self.path {
+            Some(path) => autosave::read(path).await,
+            None => Ok(None),
+        }
+    }
+
+    pub fn discard_autosave(&self) -> Result<(), DbError> {
+        *self.autosaved.lock().unwrap() = None;
+        match &self.path {
+            Some(path) => autosave::discard(path),
+            None => Ok(()),
+        }
+    }
+
+    /// Database of the open project.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Problems of the project database, see [`integrity::check`].
+    pub async fn check_integrity(&self) -> Result<Vec<Issue>, DbError> {
+        integrity::check(self.db.as_ref().ok_or(DbError::NotOpen)?).await
+    }
+
+    /// Fix the problems of the project database, see [`integrity::repair`]. Returns the issues
+    /// fixed.
+    pub async fn repair_integrity(&self) -> Result<Vec<Issue>, DbError> {
+        integrity::repair(self.db.as_ref().ok_or(DbError::NotOpen)?).await
+    }
+
+    /// Write the project, with the levels and stories of the database, to a directory of text
+    /// files.
+    pub async fn export_project(&self, name: &str, project: &ProjectData, dir: &Path) -> Result<(), DbError> {
+        project_export::export(self.db.as_ref().ok_or(DbError::NotOpen)?, name, project, dir).await
+    }
+
+    /// Create a new project database from an export and open it. Returns the name of the exported
+    /// project. Nothing is left on disk if the import fails.
+    pub async fn import_project(&mut self, dir: &Path, db_path: &Path) -> Result<String, DbError> {
+        let db_path = db_path.with_extension("db");
+        self.create(&db_path).await?;
+        let imported = project_export::import(self.db.as_ref().ok_or(DbError::NotOpen)?, dir).await;
+        if imported.is_err() {
+            if let Some(pool) = self.db.take() {
+                pool.close().await;
+            }
+            let _ = std::fs::remove_file(&db_path);
+        }
+        imported
+    }
+
+    /// Create the database of a new project with the latest schema, failing if the file already
+    /// exists.
+    pub async fn create(&mut self, db_path: &Path) -> Result<(), DbError> {
+        let db_path = db_path.with_extension("db");
+        if db_path.exists() {
+            return Err(DbError::AlreadyExists(db_path));
+        }
+        self.open_with(&db_path, true).await
+    }
+
+    /// Open the database of an existing project, failing if the file is missing or is not a
+    /// project.
+    pub async fn open_existing(&mut self, db_path: &Path) -> Result<(), DbError> {
+        self.open_with(db_path, false).await
+    }
+
+    async fn open_with(&mut self, db_path: &Path, create: bool) -> Result<(), DbError> {
+        let conn_str = db_path.to_string_lossy().replace('\\', "/");
+        info!("DB:open {}", conn_str);
+        match connect(&conn_str, create).await {
+            Ok(pool) => {
+                info!("DB:{} open succes", conn_str);
+                if autosave::exists(db_path) {
+                    warn!("DB:{} was not closed properly, its autosave is kept for recovery", conn_str);
+                }
+                *self.name.lock().unwrap() = conn_str;
+                self.db = Some(pool);
+                self.path = Some(db_path.to_path_buf());
+                Ok(())
+            }
+            Err(e) => {
+                error!("DB:{} open failed : {}", conn_str, e);
+                *self.name.lock().unwrap() = format!("❌ Erreur : {}", e);
+                self.db = None;
+                self.path = None;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Open a project database read-only, as the game does. The project is never upgraded, it must
+/// already have the schema of this build.
+pub async fn connect_read_only(db_path: &Path) -> Result<Pool<Sqlite>, DbError> {
+    let options = SqliteConnectOptions::new().filename(db_path).read_only(true);
+    let pool = SqlitePoolOptions::new().connect_with(options).await?;
+    let checked = match migration::stored_schema_version(&pool).await {
+        Ok(Some(version)) if version > SCHEMA_VERSION => Err(DbError::NewerSchema(version)),
+        Ok(Some(version)) if version < SCHEMA_VERSION => Err(DbError::OlderSchema(version)),
+        Ok(Some(_)) => migration::check_schema(&pool).await,
+        Ok(None) => Err(DbError::NotAProject(String::from("aucune version de schéma"))),
+        Err(e) => Err(e),
+    };
+    match checked {
+        Ok(()) => Ok(pool),
+        Err(e) => {
+            pool.close().await;
+            Err(e)
+        }
+    }
+}
+
+/// Problems of a project file, opened read-only. A project with an older schema only reports
+/// [`Issue::OldSchema`], the other checks need the upgrade.
+pub async fn check_file(db_path: &Path) -> Result<Vec<Issue>, DbError> {
+    match connect_read_only(db_path).await {
+        Ok(pool) => {
+            let issues = integrity::check(&pool).await;
+            pool.close().await;
+            issues
+        }
+        Err(DbError::OlderSchema(version)) => Ok(vec![Issue::OldSchema { version }]),
+        Err(e) => Err(e),
+    }
+}
+
+/// Upgrade the schema of a project file and fix its problems. Returns the issues fixed.
+pub async fn repair_file(db_path: &Path) -> Result<Vec<Issue>, DbError> {
+    let mut fixed = match connect_read_only(db_path).await {
+        Ok(pool) => {
+            pool.close().await;
+            Vec::new()
+        }
+        Err(DbError::OlderSchema(version)) => vec![Issue::OldSchema { version }],
+        Err(e) => return Err(e),
+    };
+    let mut db = EngineDb::new();
+    db.open_existing(db_path).await?;
+    fixed.extend(db.repair_integrity().await?);
+    Ok(fixed)
+}
+
+/// Open a database with the project schema, creating or upgrading it.
+pub async fn connect(conn_str: &str, create: bool) -> Result<Pool<Sqlite>, DbError> {
+    let options = SqliteConnectOptions::from_str(conn_str)?
+        .create_if_missing(create)
+        .foreign_keys(true);
+    let pool = SqlitePoolOptions::new().connect_with(options).await?;
+    let migrated = match migration::migrate(&pool).await {
+        Ok(version) => {
+            if version < SCHEMA_VERSION {
+                info!("DB:{} upgraded from version {} to {}", conn_str, version, SCHEMA_VERSION);
+            }
+            migration::check_schema(&pool).await
+        }
+        Err(e) => Err(e),
+    };
+    match migrated {
+        Ok(()) => Ok(pool),
+        Err(e) => {
+            pool.close().await;
+            Err(e)
+        }
+    }
+}
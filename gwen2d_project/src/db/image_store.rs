@@ -0,0 +1,112 @@
+use crate::db::engine_db::DbError;
+use image::ImageReader;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use sqlx::{Row, Sqlite, Transaction};
+use std::io::Cursor;
+
+/// Encoded image as stored once in the `image` table, whatever the number of frames using it.
+#[derive(PartialEq, Debug, Clone)]
+pub struct StoredImage {
+    /// SHA-256 of the encoded bytes, in hexadecimal.
+    pub hash: String,
+    pub width: u32,
+    pub height: u32,
+    /// Extension of the encoding format, like `png`.
+    pub format: String,
+    pub data: Vec<u8>,
+}
+
+impl StoredImage {
+    /// Describe encoded image bytes, reading only the header for the dimensions.
+    pub fn from_encoded(data: Vec<u8>) -> Result<Self, DbError> {
+        let reader = ImageReader::new(Cursor::new(&data))
+            .with_guessed_format()
+            .map_err(image::ImageError::IoError)?;
+        let format = reader
+            .format()
+            .and_then(|format| format.extensions_str().first())
+            .map(|extension| extension.to_string())
+            .unwrap_or_default();
+        let (width, height) = reader.into_dimensions()?;
+        Ok(StoredImage {
+            hash: content_hash(&data),
+            width,
+            height,
+            format,
+            data,
+        })
+    }
+}
+
+pub fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Id of the image with the same content, inserting it if it is not stored yet.
+pub async fn store(tx: &mut Transaction<'_, Sqlite>, image: &StoredImage) -> Result<i64, DbError> {
+    if let Some(row) = sqlx::query("SELECT id FROM image WHERE hash = ?")
+        .bind(&image.hash)
+        .fetch_optional(&mut **tx)
+        .await?
+    {
+        return Ok(row.try_get("id")?);
+    }
+    let id = sqlx::query("INSERT INTO image (hash, width, height, format, data) VALUES (?, ?, ?, ?, ?)")
+        .bind(&image.hash)
+        .bind(image.width)
+        .bind(image.height)
+        .bind(&image.format)
+        .bind(&image.data)
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
+
+/// Delete the images no frame references anymore. Returns the number of deleted images.
+pub async fn collect_garbage(tx: &mut Transaction<'_, Sqlite>) -> Result<u64, DbError> {
+    let deleted = sqlx::query(
+        "DELETE FROM image WHERE id NOT IN (SELECT image_id FROM frame WHERE image_id IS NOT NULL)",
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if deleted > 0 {
+        info!("DB:{} unused images deleted", deleted);
+    }
+    Ok(deleted)
+}
+
+/// Data step of the image store migration: move the images kept in `frame.img` to the image
+/// table, once per content.
+pub async fn move_frame_images(tx: &mut Transaction<'_, Sqlite>) -> Result<(), DbError> {
+    let rows = sqlx::query("SELECT id, img FROM frame WHERE img IS NOT NULL")
+        .fetch_all(&mut **tx)
+        .await?;
+    for row in rows {
+        let frame_id: i64 = row.try_get("id")?;
+        let data: Vec<u8> = row.try_get("img")?;
+        // An unreadable image is kept as is rather than blocking the upgrade of the project.
+        let image = match StoredImage::from_encoded(data.clone()) {
+            Ok(image) => image,
+            Err(e) => {
+                warn!("DB:frame {} image unreadable : {}", frame_id, e);
+                StoredImage {
+                    hash: content_hash(&data),
+                    width: 0,
+                    height: 0,
+                    format: String::new(),
+                    data,
+                }
+            }
+        };
+        let image_id = store(tx, &image).await?;
+        sqlx::query("UPDATE frame SET image_id = ? WHERE id = ?")
+            .bind(image_id)
+            .bind(frame_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,396 @@
+use crate::db::engine_db::DbError;
+use crate::db::image_store;
+use crate::db::migration::SCHEMA_VERSION;
+use log::{info, warn};
+use sqlx::{Pool, Row, Sqlite, Transaction};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// Problem found in a project database.
+#[derive(PartialEq, Debug, Clone)]
+pub enum Issue {
+    /// Row of `table` referencing a missing row of `parent`.
+    BrokenReference { table: String, id: i64, parent: String },
+    FrameWithoutImage { frame: i64 },
+    /// Image no frame uses anymore.
+    UnusedImage { image: i64 },
+    UndecodableImage { image: i64, reason: String },
+    /// Rows of `table` sharing a name where it must be unique, lowest id first.
+    DuplicateName { table: String, name: String, ids: Vec<i64> },
+    /// Schema written by an older release, upgraded by the repair before the other checks.
+    OldSchema { version: u32 },
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Issue::BrokenReference { table, id, parent } => {
+                write!(f, "{} {} : référence vers {} inexistante", table, id, parent)
+            }
+            Issue::FrameWithoutImage { frame } => write!(f, "frame {} : aucune image", frame),
+            Issue::UnusedImage { image } => write!(f, "image {} : utilisée par aucune frame", image),
+            Issue::UndecodableImage { image, reason } => write!(f, "image {} : illisible ({})", image, reason),
+            Issue::DuplicateName { table, name, ids } => {
+                write!(f, "{} {:?} : nom utilisé par les lignes {:?}", table, name, ids)
+            }
+            Issue::OldSchema { version } => {
+                write!(f, "schéma en version {}, la version actuelle est {}", version, SCHEMA_VERSION)
+            }
+        }
+    }
+}
+
+/// Look for broken foreign keys, orphan rows, undecodable images and duplicate names. The
+/// database is not modified.
+pub async fn check(pool: &Pool<Sqlite>) -> Result<Vec<Issue>, DbError> {
+    let mut tx = pool.begin().await?;
+    let issues = find_issues(&mut tx).await?;
+    tx.rollback().await?;
+    info!("DB:integrity check, {} issues", issues.len());
+    Ok(issues)
+}
+
+/// Fix the issues in a single transaction and return the ones fixed. Entities of a missing
+/// category move to the first category, duplicate names get a number and unused images are
+/// deleted; the rows which cannot be fixed are moved to the `quarantine` table with their frames.
+pub async fn repair(pool: &Pool<Sqlite>) -> Result<Vec<Issue>, DbError> {
+    let mut tx = pool.begin().await?;
+    let issues = find_issues(&mut tx).await?;
+    for issue in issues.iter() {
+        let reason = issue.to_string();
+        match issue {
+            Issue::BrokenReference { table, id, parent } if table == "entity" && parent == "category" => {
+                let category = default_category(&mut tx).await?;
+                sqlx::query("UPDATE entity SET category_id = ? WHERE id = ?")
+                    .bind(category)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            Issue::BrokenReference { table, id, .. } if table == "entity" => {
+                quarantine_entity(&mut tx, *id, &reason).await?
+            }
+            Issue::BrokenReference { table, id, .. } if table == "state" => {
+                quarantine_state(&mut tx, *id, &reason).await?
+            }
+            Issue::BrokenReference { table, id, .. } => quarantine(&mut tx, table, *id, &reason).await?,
+            Issue::FrameWithoutImage { frame } => quarantine(&mut tx, "frame", *frame, &reason).await?,
+            Issue::UndecodableImage { image, .. } => {
+                let frames: Vec<i64> = sqlx::query("SELECT id FROM frame WHERE image_id = ?")
+                    .bind(image)
+                    .fetch_all(&mut *tx)
+                    .await?
+                    .iter()
+                    .map(|row| row.get("id"))
+                    .collect();
+                for frame in frames {
+                    quarantine(&mut tx, "frame", frame, &reason).await?;
+                }
+                quarantine(&mut tx, "image", *image, &reason).await?;
+            }
+            Issue::DuplicateName { table, name, ids } => rename_duplicates(&mut tx, table, name, ids).await?,
+            // Collected once every reference is fixed.
+            Issue::UnusedImage { .. } => {}
+            // Never found in an open database, the schema is upgraded when opening.
+            Issue::OldSchema { .. } => {}
+        }
+    }
+    image_store::collect_garbage(&mut tx).await?;
+    tx.commit().await?;
+    info!("DB:integrity repair, {} issues fixed", issues.len());
+    Ok(issues)
+}
+
+async fn find_issues(tx: &mut Transaction<'_, Sqlite>) -> Result<Vec<Issue>, DbError> {
+    let mut issues = Vec::new();
+    for row in sqlx::query("PRAGMA foreign_key_check").fetch_all(&mut **tx).await? {
+        issues.push(Issue::BrokenReference {
+            table: row.try_get("table")?,
+            id: row.try_get::<Option<i64>, _>("rowid")?.unwrap_or_default(),
+            parent: row.try_get("parent")?,
+        });
+    }
+    for row in sqlx::query("SELECT id FROM frame WHERE image_id IS NULL ORDER BY id").fetch_all(&mut **tx).await? {
+        issues.push(Issue::FrameWithoutImage { frame: row.try_get("id")? });
+    }
+    for row in sqlx::query(
+        "SELECT id FROM image WHERE id NOT IN (SELECT image_id FROM frame WHERE image_id IS NOT NULL) ORDER BY id",
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    {
+        issues.push(Issue::UnusedImage { image: row.try_get("id")? });
+    }
+    for row in sqlx::query("SELECT id, data FROM image ORDER BY id").fetch_all(&mut **tx).await? {
+        let data: Vec<u8> = row.try_get("data")?;
+        if let Err(e) = image::load_from_memory(&data) {
+            issues.push(Issue::UndecodableImage {
+                image: row.try_get("id")?,
+                reason: e.to_string(),
+            });
+        }
+    }
+    for (table, query) in [
+        ("category", "SELECT name, group_concat(id) AS ids FROM category GROUP BY name HAVING COUNT(*) > 1"),
+        ("entity", "SELECT name, group_concat(id) AS ids FROM entity GROUP BY name HAVING COUNT(*) > 1"),
+        (
+            "state",
+            "SELECT name, group_concat(id) AS ids FROM state GROUP BY entity_id, name HAVING COUNT(*) > 1",
+        ),
+    ] {
+        for row in sqlx::query(query).fetch_all(&mut **tx).await? {
+            let ids: String = row.try_get("ids")?;
+            let mut ids: Vec<i64> = ids.split(',').filter_map(|id| id.parse().ok()).collect();
+            ids.sort();
+            issues.push(Issue::DuplicateName {
+                table: String::from(table),
+                name: row.try_get("name")?,
+                ids,
+            });
+        }
+    }
+    Ok(issues)
+}
+
+/// Move a row to the quarantine, as JSON with its blobs in hexadecimal, then delete it.
+async fn quarantine(tx: &mut Transaction<'_, Sqlite>, table: &str, id: i64, reason: &str) -> Result<(), DbError> {
+    let columns: Vec<String> = sqlx::query(&format!("PRAGMA table_info(\"{}\")", table))
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+    if columns.is_empty() {
+        return Ok(());
+    }
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|column| format!("'{0}', CASE typeof(\"{0}\") WHEN 'blob' THEN hex(\"{0}\") ELSE \"{0}\" END", column))
+        .collect();
+    let copied = sqlx::query(&format!(
+        "INSERT INTO quarantine (source, row_id, reason, content) SELECT ?, rowid, ?, json_object({}) FROM \"{}\" WHERE rowid = ?",
+        fields.join(", "),
+        table
+    ))
+    .bind(table)
+    .bind(reason)
+    .bind(id)
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if copied == 0 {
+        // Already quarantined with its parent.
+        return Ok(());
+    }
+    sqlx::query(&format!("DELETE FROM \"{}\" WHERE rowid = ?", table))
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+    warn!("DB:{} {} quarantined : {}", table, id, reason);
+    Ok(())
+}
+
+/// Quarantine a state and its frames, the frames first for the foreign keys.
+async fn quarantine_state(tx: &mut Transaction<'_, Sqlite>, id: i64, reason: &str) -> Result<(), DbError> {
+    let frames: Vec<i64> = sqlx::query("SELECT id FROM frame WHERE state_id = ?")
+        .bind(id)
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+    for frame in frames {
+        quarantine(tx, "frame", frame, reason).await?;
+    }
+    quarantine(tx, "state", id, reason).await
+}
+
+/// Quarantine an entity and its states.
+async fn quarantine_entity(tx: &mut Transaction<'_, Sqlite>, id: i64, reason: &str) -> Result<(), DbError> {
+    let states: Vec<i64> = sqlx::query("SELECT id FROM state WHERE entity_id = ?")
+        .bind(id)
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.get("id"))
+        .collect();
+    for state in states {
+        quarantine_state(tx, state, reason).await?;
+    }
+    quarantine(tx, "entity", id, reason).await
+}
+
+/// First category, created if the project has none.
+async fn default_category(tx: &mut Transaction<'_, Sqlite>) -> Result<i64, DbError> {
+    if let Some(row) = sqlx::query("SELECT id FROM category ORDER BY id LIMIT 1")
+        .fetch_optional(&mut **tx)
+        .await?
+    {
+        return Ok(row.try_get("id")?);
+    }
+    Ok(sqlx::query("INSERT INTO category (name) VALUES ('Aucune')")
+        .execute(&mut **tx)
+        .await?
+        .last_insert_rowid())
+}
+
+/// Keep the name of the first row and number the others, `name (2)`, `name (3)`..., skipping
+/// the names already used.
+async fn rename_duplicates(
+    tx: &mut Transaction<'_, Sqlite>,
+    table: &str,
+    name: &str,
+    ids: &[i64],
+) -> Result<(), DbError> {
+    let mut used: HashSet<String> = sqlx::query(&format!("SELECT name FROM \"{}\"", table))
+        .fetch_all(&mut **tx)
+        .await?
+        .iter()
+        .map(|row| row.get("name"))
+        .collect();
+    let mut number = 2;
+    for id in ids.iter().skip(1) {
+        let mut new_name = format!("{} ({})", name, number);
+        while used.contains(&new_name) {
+            number += 1;
+            new_name = format!("{} ({})", name, number);
+        }
+        number += 1;
+        sqlx::query(&format!("UPDATE \"{}\" SET name = ? WHERE id = ?", table))
+            .bind(&new_name)
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        used.insert(new_name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::engine_db::{self, EngineDb};
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+    use std::path::{Path, PathBuf};
+    use std::str::FromStr;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gwen2d_integrity_{}_{}.db", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgba8(2, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    /// Project with one broken row of each kind, written without the foreign keys like the
+    /// databases of the first editor releases.
+    async fn broken_project(path: &Path) -> Pool<Sqlite> {
+        EngineDb::new().create(path).await.unwrap();
+        let options = SqliteConnectOptions::from_str(&path.to_string_lossy()).unwrap().foreign_keys(false);
+        let pool = SqlitePoolOptions::new().connect_with(options).await.unwrap();
+        for query in [
+            "INSERT INTO category (id, name) VALUES (1, 'Personnages'), (2, 'Personnages')",
+            "INSERT INTO entity (id, category_id, name) VALUES (1, 1, 'joueur'), (2, 9, 'ennemi')",
+            "INSERT INTO state (id, entity_id, name) VALUES (1, 1, 'repos'), (2, 8, 'perdu')",
+            "INSERT INTO image (id, hash, width, height, format, data) VALUES (2, 'abc', 1, 1, 'png', x'00')",
+            "INSERT INTO frame (id, state_id, image_id) VALUES (1, 1, 1), (2, 2, 1), (3, 7, 1), (4, 1, NULL), (5, 1, 2)",
+        ] {
+            sqlx::query(query).execute(&pool).await.unwrap();
+        }
+        for (id, hash) in [(1, image_store::content_hash(&png())), (3, String::from("def"))] {
+            sqlx::query("INSERT INTO image (id, hash, width, height, format, data) VALUES (?, ?, 2, 2, 'png', ?)")
+                .bind(id)
+                .bind(hash)
+                .bind(png())
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        pool
+    }
+
+    #[tokio::test]
+    async fn check_finds_every_issue() {
+        let pool = broken_project(&temp_db("check")).await;
+        let issues = check(&pool).await.unwrap();
+        let broken = |table: &str, id: i64, parent: &str| Issue::BrokenReference {
+            table: String::from(table),
+            id,
+            parent: String::from(parent),
+        };
+        for expected in [
+            broken("entity", 2, "category"),
+            broken("state", 2, "entity"),
+            broken("frame", 3, "state"),
+            Issue::FrameWithoutImage { frame: 4 },
+            Issue::UnusedImage { image: 3 },
+            Issue::DuplicateName {
+                table: String::from("category"),
+                name: String::from("Personnages"),
+                ids: vec![1, 2],
+            },
+        ] {
+            assert!(issues.contains(&expected), "{} not found in {:?}", expected, issues);
+        }
+        assert!(issues.iter().any(|issue| matches!(issue, Issue::UndecodableImage { image: 2, .. })));
+        assert_eq!(issues.len(), 7);
+    }
+
+    #[tokio::test]
+    async fn repair_leaves_a_clean_project() {
+        let pool = broken_project(&temp_db("repair")).await;
+        repair(&pool).await.unwrap();
+        assert_eq!(check(&pool).await.unwrap(), Vec::new());
+
+        let category: i64 = sqlx::query("SELECT category_id FROM entity WHERE id = 2")
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+            .get("category_id");
+        assert_eq!(category, 1);
+        let names: Vec<String> = sqlx::query("SELECT name FROM category ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("name"))
+            .collect();
+        assert_eq!(names, vec!["Personnages", "Personnages (2)"]);
+        let frames: Vec<i64> = sqlx::query("SELECT id FROM frame ORDER BY id")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get("id"))
+            .collect();
+        assert_eq!(frames, vec![1]);
+        let quarantined: Vec<(String, i64)> = sqlx::query("SELECT source, row_id FROM quarantine ORDER BY source, row_id")
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| (row.get("source"), row.get("row_id")))
+            .collect();
+        let expected = [("frame", 2), ("frame", 3), ("frame", 4), ("frame", 5), ("image", 2), ("state", 2)];
+        assert_eq!(quarantined, expected.map(|(source, id)| (String::from(source), id)));
+    }
+
+    #[tokio::test]
+    async fn old_schema_is_reported_without_upgrade_then_repaired() {
+        let path = temp_db("old_schema");
+        std::fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/project_v1.db"), &path).unwrap();
+        let original = std::fs::read(&path).unwrap();
+        assert_eq!(engine_db::check_file(&path).await.unwrap(), vec![Issue::OldSchema { version: 1 }]);
+        assert_eq!(std::fs::read(&path).unwrap(), original, "the check must not write the project");
+
+        let fixed = engine_db::repair_file(&path).await.unwrap();
+        assert_eq!(fixed.first(), Some(&Issue::OldSchema { version: 1 }));
+        assert_eq!(engine_db::check_file(&path).await.unwrap(), Vec::new());
+    }
+}
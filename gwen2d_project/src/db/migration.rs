@@ -0,0 +1,312 @@
+use crate::db::engine_db::DbError;
+use crate::db::image_store;
+use log::info;
+use sqlx::{Executor, Pool, Row, Sqlite};
+
+/// Change of the project database schema, applied once in version order.
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub sql: &'static str,
+    /// Data changes SQL alone cannot do, run after `sql` in the same transaction.
+    pub step: Option<DataStep>,
+}
+
+/// Data change of a migration written in Rust.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DataStep {
+    /// Move the frame images to the content-addressed image table.
+    MoveFrameImages,
+}
+
+/// Every schema change, oldest first. Versions are numbered from 1 without gaps; a released
+/// migration must never be edited, add a new one instead.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("migrations/0001_initial.sql"),
+        step: None,
+    },
+    Migration {
+        version: 2,
+        name: "story",
+        sql: include_str!("migrations/0002_story.sql"),
+        step: None,
+    },
+    Migration {
+        version: 3,
+        name: "level_data",
+        sql: include_str!("migrations/0003_level_data.sql"),
+        step: None,
+    },
+    Migration {
+        version: 4,
+        name: "image_store",
+        sql: include_str!("migrations/0004_image_store.sql"),
+        step: Some(DataStep::MoveFrameImages),
+    },
+    Migration {
+        version: 5,
+        name: "frame_drop_img",
+        sql: include_str!("migrations/0005_frame_drop_img.sql"),
+        step: None,
+    },
+    Migration {
+        version: 6,
+        name: "quarantine",
+        sql: include_str!("migrations/0006_quarantine.sql"),
+        step: None,
+    },
+    Migration {
+        version: 7,
+        name: "script",
+        sql: include_str!("migrations/0007_script.sql"),
+        step: None,
+    },
+];
+
+/// Schema version written by this build.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Tables every project database has once migrated.
+pub const PROJECT_TABLES: &[&str] = &[
+    "category", "entity", "state", "frame", "level", "story", "image", "quarantine", "script",
+];
+
+const CREATE_VERSION_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_version (
+        version INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );
+"#;
+
+/// Version of the schema of a database, 0 for a database without any migration.
+pub async fn schema_version(pool: &Pool<Sqlite>) -> Result<u32, DbError> {
+    Ok(stored_schema_version(pool).await?.unwrap_or(0))
+}
+
+/// Version of the schema of a database without writing to it, `None` if it never had any
+/// migration.
+pub async fn stored_schema_version(pool: &Pool<Sqlite>) -> Result<Option<u32>, DbError> {
+    let found = sqlx::query("SELECT COUNT(*) AS n FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'")
+        .fetch_one(pool)
+        .await?;
+    if found.try_get::<i64, _>("n")? == 0 {
+        return Ok(None);
+    }
+    let row = sqlx::query("SELECT MAX(version) AS version FROM schema_version")
+        .fetch_one(pool)
+        .await?;
+    Ok(row.try_get::<Option<i64>, _>("version")?.map(|version| version as u32))
+}
+
+/// Apply the missing migrations, each one in its own transaction. Databases written by a newer
+/// build or by another application are left untouched. Returns the version of the database
+/// before the upgrade.
+pub async fn migrate(pool: &Pool<Sqlite>) -> Result<u32, DbError> {
+    let current = schema_version(pool).await?;
+    if current > SCHEMA_VERSION {
+        return Err(DbError::NewerSchema(current));
+    }
+    if current == 0 {
+        // Never create the project tables next to the tables of another application.
+        let foreign = sqlx::query(
+            "SELECT COUNT(*) AS n FROM sqlite_master WHERE type = 'table' \
+             AND name NOT IN ('schema_version', 'sqlite_sequence')",
+        )
+        .fetch_one(pool)
+        .await?;
+        if foreign.try_get::<i64, _>("n")? > 0 {
+            return Err(DbError::NotAProject(String::from("tables inconnues sans version de schéma")));
+        }
+    }
+    for migration in MIGRATIONS {
+        if migration.version <= current {
+            continue;
+        }
+        info!("DB:migration {} {}", migration.version, migration.name);
+        let mut tx = pool.begin().await?;
+        if migration.version == current + 1 {
+            tx.execute(CREATE_VERSION_TABLE).await?;
+        }
+        // A plain string query may hold several statements.
+        tx.execute(migration.sql).await?;
+        match migration.step {
+            Some(DataStep::MoveFrameImages) => image_store::move_frame_images(&mut tx).await?,
+            None => {}
+        }
+        sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+    Ok(current)
+}
+
+/// Check that the migrated database has every project table.
+pub async fn check_schema(pool: &Pool<Sqlite>) -> Result<(), DbError> {
+    for table in PROJECT_TABLES {
+        let found = sqlx::query("SELECT COUNT(*) AS n FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(table)
+            .fetch_one(pool)
+            .await?;
+        if found.try_get::<i64, _>("n")? == 0 {
+            return Err(DbError::NotAProject(format!("table {} manquante", table)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::engine_db::{self, EngineDb};
+    use std::path::{Path, PathBuf};
+
+    /// Project database created by the first editor release, schema version 1.
+    const FIXTURE_V1: &str = "tests/fixtures/project_v1.db";
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gwen2d_{}_{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    async fn columns(pool: &Pool<Sqlite>, table: &str) -> Vec<String> {
+        sqlx::query(&format!("PRAGMA table_info({})", table))
+            .fetch_all(pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get::<String, _>("name"))
+            .collect()
+    }
+
+    #[test]
+    fn migrations_are_numbered_in_order() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as u32 + 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn new_database_gets_latest_schema() {
+        let path = temp_db("new");
+        let mut db = EngineDb::new();
+        db.create(&path).await.unwrap();
+        let pool = db.pool().unwrap();
+        assert_eq!(schema_version(pool).await.unwrap(), SCHEMA_VERSION);
+        for table in PROJECT_TABLES {
+            assert!(!columns(pool, table).await.is_empty(), "missing table {}", table);
+        }
+        assert!(columns(pool, "level").await.contains(&String::from("data")));
+        // Opening again must not apply anything twice.
+        drop(db);
+        let mut db = EngineDb::new();
+        db.open_existing(&path).await.unwrap();
+        assert_eq!(schema_version(db.pool().unwrap()).await.unwrap(), SCHEMA_VERSION);
+    }
+
+    #[tokio::test]
+    async fn old_fixture_is_upgraded_and_keeps_its_rows() {
+        let path = temp_db("v1");
+        std::fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_V1), &path).unwrap();
+        let mut db = EngineDb::new();
+        db.open_existing(&path).await.unwrap();
+        let pool = db.pool().unwrap();
+        assert_eq!(schema_version(pool).await.unwrap(), SCHEMA_VERSION);
+
+        let entity: String = sqlx::query("SELECT name FROM entity").fetch_one(pool).await.unwrap().get("name");
+        assert_eq!(entity, "joueur");
+        let frames: i64 = sqlx::query("SELECT COUNT(*) AS n FROM frame").fetch_one(pool).await.unwrap().get("n");
+        assert_eq!(frames, 2);
+        assert!(!columns(pool, "frame").await.contains(&String::from("img")));
+        let images = sqlx::query(
+            "SELECT i.hash, i.width, i.format, i.data FROM frame f JOIN image i ON i.id = f.image_id ORDER BY f.id",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        assert_eq!(images.len(), 2);
+        for image in images.iter() {
+            assert_eq!(image.get::<String, _>("hash"), image_store::content_hash(&image.get::<Vec<u8>, _>("data")));
+            assert_eq!(image.get::<String, _>("format"), "png");
+            assert!(image.get::<i64, _>("width") > 0);
+        }
+        let level = sqlx::query("SELECT name, data FROM level").fetch_one(pool).await.unwrap();
+        assert_eq!(level.get::<String, _>("name"), "niveau 1");
+        assert_eq!(level.get::<Option<String>, _>("data"), None);
+        sqlx::query("INSERT INTO story (name, data) VALUES ('intro', '{}')")
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn newer_database_is_refused() {
+        let path = temp_db("newer");
+        let mut db = EngineDb::new();
+        db.create(&path).await.unwrap();
+        sqlx::query("INSERT INTO schema_version (version, name) VALUES (?, 'future')")
+            .bind(SCHEMA_VERSION + 1)
+            .execute(db.pool().unwrap())
+            .await
+            .unwrap();
+        drop(db);
+
+        let mut db = EngineDb::new();
+        match db.open_existing(&path).await {
+            Err(DbError::NewerSchema(version)) => assert_eq!(version, SCHEMA_VERSION + 1),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(!db.is_loaded());
+    }
+    #[tokio::test]
+    async fn foreign_database_is_left_untouched() {
+        let path = temp_db("foreign");
+        {
+            let options = sqlx::sqlite::SqliteConnectOptions::new().filename(&path).create_if_missing(true);
+            let pool = sqlx::SqlitePool::connect_with(options).await.unwrap();
+            sqlx::query("CREATE TABLE contact (id INTEGER PRIMARY KEY, name TEXT)")
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO contact (name) VALUES ('alice')")
+                .execute(&pool)
+                .await
+                .unwrap();
+            pool.close().await;
+        }
+        let original = std::fs::read(&path).unwrap();
+
+        let mut db = EngineDb::new();
+        match db.open_existing(&path).await {
+            Err(DbError::NotAProject(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), original, "a foreign file must not be written");
+    }
+
+    #[tokio::test]
+    async fn game_reads_only_upgraded_projects() {
+        let path = temp_db("read_only");
+        std::fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_V1), &path).unwrap();
+        let original = std::fs::read(&path).unwrap();
+        match engine_db::connect_read_only(&path).await {
+            Err(DbError::OlderSchema(version)) => assert_eq!(version, 1),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        assert_eq!(std::fs::read(&path).unwrap(), original, "the game must not write the project");
+
+        let mut db = EngineDb::new();
+        db.open_existing(&path).await.unwrap();
+        drop(db);
+        let pool = engine_db::connect_read_only(&path).await.unwrap();
+        assert_eq!(stored_schema_version(&pool).await.unwrap(), Some(SCHEMA_VERSION));
+        assert!(sqlx::query("DELETE FROM level").execute(&pool).await.is_err());
+    }
+}
@@ -0,0 +1,34 @@
+CREATE TABLE category (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    width INTEGER,
+    height INTEGER
+);
+
+CREATE TABLE entity (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    category_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    width INTEGER,
+    height INTEGER,
+    FOREIGN KEY (category_id) REFERENCES category(id)
+);
+
+CREATE TABLE state (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    entity_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    FOREIGN KEY (entity_id) REFERENCES entity(id)
+);
+
+CREATE TABLE frame (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    state_id INTEGER NOT NULL,
+    img BLOB,
+    FOREIGN KEY (state_id) REFERENCES state(id)
+);
+
+CREATE TABLE level (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE level ADD COLUMN data TEXT;
@@ -0,0 +1,10 @@
+CREATE TABLE image (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    hash TEXT NOT NULL UNIQUE,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    format TEXT NOT NULL,
+    data BLOB NOT NULL
+);
+
+ALTER TABLE frame ADD COLUMN image_id INTEGER REFERENCES image(id);
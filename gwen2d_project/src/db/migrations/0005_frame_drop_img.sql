@@ -0,0 +1 @@
+ALTER TABLE frame DROP COLUMN img;
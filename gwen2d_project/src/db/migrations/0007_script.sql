@@ -0,0 +1,5 @@
+CREATE TABLE script (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    source TEXT NOT NULL
+);
@@ -0,0 +1,488 @@
+use crate::db::engine_db::DbError;
+use crate::db::image_store;
+use crate::db::project_repository::{self, CategoryData, EntityData, ProjectData, StateData};
+use crate::model::{entity::EntityId, entity_category::CategoryId, entity_state::StateId};
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Row, Sqlite};
+use std::path::{Component, Path, PathBuf};
+
+/// Version of the export layout, written in `project.json`.
+pub const EXPORT_VERSION: u32 = 1;
+
+const PROJECT_FILE: &str = "project.json";
+const ENTITIES_DIR: &str = "entities";
+const LEVELS_DIR: &str = "levels";
+const STORIES_DIR: &str = "stories";
+const SCRIPTS_DIR: &str = "scripts";
+const FRAMES_DIR: &str = "frames";
+
+/// `project.json`, the entry point of an export.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct ProjectFile {
+    version: u32,
+    name: String,
+    categories: Vec<CategoryFile>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct CategoryFile {
+    id: i64,
+    name: String,
+}
+
+/// One file per entity in `entities/`. Frames are paths of PNG files relative to the export.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct EntityFile {
+    id: i64,
+    name: String,
+    category: i64,
+    states: Vec<StateFile>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct StateFile {
+    id: i64,
+    name: String,
+    frames: Vec<String>,
+}
+
+/// One file per level in `levels/`.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct LevelFile {
+    id: i64,
+    name: String,
+    data: Option<String>,
+}
+
+/// One file per story in `stories/`.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct StoryFile {
+    id: i64,
+    name: String,
+    data: String,
+}
+
+/// One file per behaviour script in `scripts/`, named after the entity kind it drives.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct ScriptFile {
+    id: i64,
+    name: String,
+    source: String,
+}
+
+/// Write the project to a directory of JSON files, one per entity, level, story and script,
+/// with the frames as PNG files named after their content hash. The levels, stories and scripts
+/// are read from the database. The directory must be empty or hold a previous export, which is replaced.
+pub async fn export(pool: &Pool<Sqlite>, name: &str, data: &ProjectData, dir: &Path) -> Result<(), DbError> {
+    let mut levels = Vec::new();
+    for row in sqlx::query("SELECT id, name, data FROM level ORDER BY id").fetch_all(pool).await? {
+        levels.push(LevelFile {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            data: row.try_get("data")?,
+        });
+    }
+    let mut stories = Vec::new();
+    for row in sqlx::query("SELECT id, name, data FROM story ORDER BY id").fetch_all(pool).await? {
+        stories.push(StoryFile {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            data: row.try_get("data")?,
+        });
+    }
+    let mut scripts = Vec::new();
+    for row in sqlx::query("SELECT id, name, source FROM script ORDER BY id").fetch_all(pool).await? {
+        scripts.push(ScriptFile {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            source: row.try_get("source")?,
+        });
+    }
+
+    prepare_dir(dir)?;
+    let project = ProjectFile {
+        version: EXPORT_VERSION,
+        name: String::from(name),
+        categories: data
+            .categories
+            .iter()
+            .map(|category| CategoryFile {
+                id: category.id.0,
+                name: category.name.clone(),
+            })
+            .collect(),
+    };
+    write_json(&dir.join(PROJECT_FILE), &project)?;
+    for entity in data.entities.iter() {
+        let mut states = Vec::new();
+        for state in entity.states.iter() {
+            let mut frames = Vec::new();
+            for png in state.frames.iter() {
+                let frame = format!("{}/{}.png", FRAMES_DIR, image_store::content_hash(png));
+                write(&dir.join(&frame), png)?;
+                frames.push(frame);
+            }
+            states.push(StateFile {
+                id: state.id.0,
+                name: state.name.clone(),
+                frames,
+            });
+        }
+        let file = EntityFile {
+            id: entity.id.0,
+            name: entity.name.clone(),
+            category: entity.category.0,
+            states,
+        };
+        write_json(&dir.join(ENTITIES_DIR).join(file_name(file.id, &file.name)), &file)?;
+    }
+    for level in levels.iter() {
+        write_json(&dir.join(LEVELS_DIR).join(file_name(level.id, &level.name)), level)?;
+    }
+    for story in stories.iter() {
+        write_json(&dir.join(STORIES_DIR).join(file_name(story.id, &story.name)), story)?;
+    }
+    for script in scripts.iter() {
+        write_json(&dir.join(SCRIPTS_DIR).join(file_name(script.id, &script.name)), script)?;
+    }
+    info!(
+        "DB:project exported to {}, {} entities, {} levels, {} stories and {} scripts",
+        dir.display(),
+        data.entities.len(),
+        levels.len(),
+        stories.len(),
+        scripts.len()
+    );
+    Ok(())
+}
+
+/// Read an export into an empty project database, in a single transaction, keeping the ids.
+/// Returns the name of the exported project.
+pub async fn import(pool: &Pool<Sqlite>, dir: &Path) -> Result<String, DbError> {
+    let project: ProjectFile = read_json(&dir.join(PROJECT_FILE))?;
+    if project.version > EXPORT_VERSION {
+        return Err(DbError::Export(format!(
+            "export en version {}, plus récente que la version {} gérée par cet éditeur",
+            project.version, EXPORT_VERSION
+        )));
+    }
+    let mut data = ProjectData {
+        categories: project
+            .categories
+            .into_iter()
+            .map(|category| CategoryData {
+                id: CategoryId(category.id),
+                name: category.name,
+            })
+            .collect(),
+        entities: Vec::new(),
+    };
+    for path in json_files(&dir.join(ENTITIES_DIR))? {
+        let entity: EntityFile = read_json(&path)?;
+        let mut states = Vec::new();
+        for state in entity.states {
+            let mut frames = Vec::new();
+            for frame in state.frames.iter() {
+                frames.push(read(&frame_path(dir, frame)?)?);
+            }
+            states.push(StateData {
+                id: StateId(state.id),
+                name: state.name,
+                frames,
+            });
+        }
+        data.entities.push(EntityData {
+            id: EntityId(entity.id),
+            name: entity.name,
+            category: CategoryId(entity.category),
+            states,
+        });
+    }
+    data.entities.sort_by_key(|entity| entity.id);
+    let mut levels: Vec<LevelFile> = Vec::new();
+    for path in json_files(&dir.join(LEVELS_DIR))? {
+        levels.push(read_json(&path)?);
+    }
+    let mut stories: Vec<StoryFile> = Vec::new();
+    for path in json_files(&dir.join(STORIES_DIR))? {
+        stories.push(read_json(&path)?);
+    }
+    let mut scripts: Vec<ScriptFile> = Vec::new();
+    for path in json_files(&dir.join(SCRIPTS_DIR))? {
+        scripts.push(read_json(&path)?);
+    }
+
+    let mut tx = pool.begin().await?;
+    let rows = sqlx::query("SELECT (SELECT COUNT(*) FROM category) + (SELECT COUNT(*) FROM level) AS n")
+        .fetch_one(&mut *tx)
+        .await?;
+    if rows.try_get::<i64, _>("n")? > 0 {
+        return Err(DbError::Export(String::from("la base de données du projet n'est pas vide")));
+    }
+    project_repository::save_in(&mut tx, &data).await?;
+    for level in levels.iter() {
+        sqlx::query("INSERT INTO level (id, name, data) VALUES (?, ?, ?)")
+            .bind(level.id)
+            .bind(&level.name)
+            .bind(&level.data)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for story in stories.iter() {
+        sqlx::query("INSERT INTO story (id, name, data) VALUES (?, ?, ?)")
+            .bind(story.id)
+            .bind(&story.name)
+            .bind(&story.data)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for script in scripts.iter() {
+        sqlx::query("INSERT INTO script (id, name, source) VALUES (?, ?, ?)")
+            .bind(script.id)
+            .bind(&script.name)
+            .bind(&script.source)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    info!(
+        "DB:project imported from {}, {} entities, {} levels, {} stories and {} scripts",
+        dir.display(),
+        data.entities.len(),
+        levels.len(),
+        stories.len(),
+        scripts.len()
+    );
+    Ok(project.name)
+}
+
+/// Empty the directories of a previous export, so that removed objects do not come back. A
+/// directory holding anything else than an export is refused.
+fn prepare_dir(dir: &Path) -> Result<(), DbError> {
+    if dir.exists() && !dir.join(PROJECT_FILE).exists() {
+        let mut entries = dir.read_dir().map_err(|e| io_error(dir, e))?;
+        if entries.next().is_some() {
+            return Err(DbError::Export(format!("le dossier {} n'est pas vide", dir.display())));
+        }
+    }
+    for sub_dir in [ENTITIES_DIR, LEVELS_DIR, STORIES_DIR, SCRIPTS_DIR, FRAMES_DIR] {
+        let path = dir.join(sub_dir);
+        if path.exists() {
+            std::fs::remove_dir_all(&path).map_err(|e| io_error(&path, e))?;
+        }
+        std::fs::create_dir_all(&path).map_err(|e| io_error(&path, e))?;
+    }
+    Ok(())
+}
+
+/// `<id>_<name>.json`, the id keeping the names unique and the name keeping them readable.
+fn file_name(id: i64, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    format!("{}_{}.json", id, name)
+}
+
+/// Path of a frame written in an entity file. It must stay in the export directory, an export
+/// is not trusted to read files elsewhere.
+fn frame_path(dir: &Path, frame: &str) -> Result<PathBuf, DbError> {
+    let relative = Path::new(frame);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(DbError::Export(format!("l'image {} est hors du dossier exporté", frame)));
+    }
+    Ok(dir.join(relative))
+}
+
+/// JSON files of a directory, sorted by name. A missing directory has none.
+fn json_files(dir: &Path) -> Result<Vec<PathBuf>, DbError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in dir.read_dir().map_err(|e| io_error(dir, e))? {
+        let path = entry.map_err(|e| io_error(dir, e))?.path();
+        if path.extension().is_some_and(|extension| extension == "json") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), DbError> {
+    let mut json = serde_json::to_string_pretty(value).map_err(|e| json_error(path, e))?;
+    json.push('\n');
+    write(path, json.as_bytes())
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, DbError> {
+    serde_json::from_slice(&read(path)?).map_err(|e| json_error(path, e))
+}
+
+fn write(path: &Path, content: &[u8]) -> Result<(), DbError> {
+    std::fs::write(path, content).map_err(|e| io_error(path, e))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, DbError> {
+    std::fs::read(path).map_err(|e| io_error(path, e))
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> DbError {
+    DbError::Export(format!("{} : {}", path.display(), e))
+}
+
+fn json_error(path: &Path, e: serde_json::Error) -> DbError {
+    DbError::Export(format!("{} : {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::engine_db::EngineDb;
+
+    /// Project database created by the first editor release, schema version 1.
+    const FIXTURE_V1: &str = "tests/fixtures/project_v1.db";
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gwen2d_export_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&path);
+        path
+    }
+
+    /// Every file of a directory with its content, by path relative to the directory.
+    fn tree(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir.to_path_buf()];
+        while let Some(current) = dirs.pop() {
+            for entry in current.read_dir().unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    files.push((path.strip_prefix(dir).unwrap().to_path_buf(), std::fs::read(&path).unwrap()));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+
+    async fn rows(pool: &Pool<Sqlite>, query: &str) -> Vec<(i64, String, Option<String>)> {
+        sqlx::query(query)
+            .fetch_all(pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| (row.get("id"), row.get("name"), row.get("data")))
+            .collect()
+    }
+
+    /// The fixture with a level description, a story, a script and a frame shared by two states.
+    async fn sample_project(path: &Path) -> EngineDb {
+        std::fs::copy(Path::new(env!("CARGO_MANIFEST_DIR")).join(FIXTURE_V1), path).unwrap();
+        let mut db = EngineDb::new();
+        db.open_existing(path).await.unwrap();
+        let pool = db.pool().unwrap();
+        sqlx::query("UPDATE level SET data = '{\"largeur\": 40}'").execute(pool).await.unwrap();
+        sqlx::query("INSERT INTO story (name, data) VALUES ('intro', 'Il était une fois')")
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO script (name, source) VALUES ('joueur', 'fn on_update(dt) { move_by(dt, 0.0); }')")
+            .execute(pool)
+            .await
+            .unwrap();
+        let mut data = db.load_project().await.unwrap();
+        let frame = data.entities[0].states[0].frames[0].clone();
+        data.entities[0].states.push(StateData {
+            id: StateId(100),
+            name: String::from("saut / chute"),
+            frames: vec![frame],
+        });
+        db.save_project(&data).await.unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn export_import_round_trip_is_lossless() {
+        let source = sample_project(&temp_path("source.db")).await;
+        let data = source.load_project().await.unwrap();
+        let first_export = temp_path("first");
+        export(source.pool().unwrap(), "exemple", &data, &first_export).await.unwrap();
+        // The shared frame is written once.
+        assert_eq!(std::fs::read_dir(first_export.join(FRAMES_DIR)).unwrap().count(), 2);
+
+        let mut imported = EngineDb::new();
+        let name = imported.import_project(&first_export, &temp_path("imported.db")).await.unwrap();
+        assert_eq!(name, "exemple");
+        assert_eq!(imported.load_project().await.unwrap(), data);
+        for query in [
+            "SELECT id, name, data FROM level",
+            "SELECT id, name, data FROM story",
+            "SELECT id, name, source AS data FROM script",
+        ] {
+            assert_eq!(rows(imported.pool().unwrap(), query).await, rows(source.pool().unwrap(), query).await);
+        }
+
+        let second_export = temp_path("second");
+        export(imported.pool().unwrap(), &name, &data, &second_export).await.unwrap();
+        assert_eq!(tree(&second_export), tree(&first_export));
+    }
+
+    #[tokio::test]
+    async fn export_replaces_previous_export() {
+        let db = sample_project(&temp_path("replaced.db")).await;
+        let mut data = db.load_project().await.unwrap();
+        let dir = temp_path("replaced");
+        export(db.pool().unwrap(), "exemple", &data, &dir).await.unwrap();
+        data.entities.clear();
+        export(db.pool().unwrap(), "exemple", &data, &dir).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.join(ENTITIES_DIR)).unwrap().count(), 0);
+        assert_eq!(std::fs::read_dir(dir.join(FRAMES_DIR)).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn export_refuses_other_directories() {
+        let db = sample_project(&temp_path("refused.db")).await;
+        let data = db.load_project().await.unwrap();
+        let dir = temp_path("refused");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "à garder").unwrap();
+        assert!(matches!(
+            export(db.pool().unwrap(), "exemple", &data, &dir).await,
+            Err(DbError::Export(_))
+        ));
+        assert!(dir.join("notes.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn import_refuses_frames_outside_the_export() {
+        let source = sample_project(&temp_path("escape_source.db")).await;
+        let data = source.load_project().await.unwrap();
+        let dir = temp_path("escape");
+        export(source.pool().unwrap(), "exemple", &data, &dir).await.unwrap();
+        let entity_file = json_files(&dir.join(ENTITIES_DIR)).unwrap().remove(0);
+        let original = std::fs::read_to_string(&entity_file).unwrap();
+        let frame = original.split('"').find(|s| s.starts_with(&format!("{}/", FRAMES_DIR))).unwrap().to_string();
+        // A valid frame outside the export, that a trusting import would read.
+        let outside = temp_path("escape_outside.png");
+        std::fs::copy(dir.join(&frame), &outside).unwrap();
+
+        let escaping = format!("../{}", outside.file_name().unwrap().to_string_lossy());
+        for path in [escaping, outside.to_string_lossy().replace('\\', "/")] {
+            std::fs::write(&entity_file, original.replace(&frame, &path)).unwrap();
+            let mut imported = EngineDb::new();
+            assert!(matches!(
+                imported.import_project(&dir, &temp_path("escape_imported.db")).await,
+                Err(DbError::Export(_))
+            ));
+        }
+    }
+}
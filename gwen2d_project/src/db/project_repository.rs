@@ -0,0 +1,462 @@
+use crate::db::engine_db::DbError;
+use crate::db::image_store::{self, StoredImage};
+use crate::model::{
+    entity::{Entity, EntityId},
+    entity_category::{CategoryId, EntityCategory},
+    entity_state::{self, EntityState, StateId},
+    project::Project,
+};
+use image::DynamicImage;
+use log::info;
+use sqlx::{Pool, Row, Sqlite, Transaction};
+use std::collections::{HashMap, HashSet};
+
+/// Content of a project as stored in the database, detached from the editor model so that it
+/// can be sent to the runtime threads. Ids are the primary keys of the rows and frames are PNG
+/// encoded.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct ProjectData {
+    pub categories: Vec<CategoryData>,
+    pub entities: Vec<EntityData>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct CategoryData {
+    pub id: CategoryId,
+    pub name: String,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct EntityData {
+    pub id: EntityId,
+    pub name: String,
+    pub category: CategoryId,
+    pub states: Vec<StateData>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct StateData {
+    pub id: StateId,
+    pub name: String,
+    pub frames: Vec<Vec<u8>>,
+}
+
+/// Copy of the editor model with the frames not encoded yet. Taking it is fast, the PNG encoding
+/// of [`ProjectSnapshot::encode`] can then run on a background task.
+#[derive(Debug, Clone)]
+pub struct ProjectSnapshot {
+    /// Project without the frames.
+    data: ProjectData,
+    /// Frames of each state, in the order of `data.entities` then of their states.
+    frames: Vec<Vec<Vec<DynamicImage>>>,
+}
+
+impl ProjectSnapshot {
+    pub fn new(project: &Project) -> Self {
+        let categories = project
+            .categories
+            .borrow()
+            .iter()
+            .map(|c| CategoryData {
+                id: c.id,
+                name: c.name(),
+            })
+            .collect();
+        let mut entities = Vec::new();
+        let mut frames = Vec::new();
+        for entity in project.entities.borrow().values() {
+            let mut states = Vec::new();
+            let mut state_frames = Vec::new();
+            for state in entity.states.borrow().values() {
+                states.push(StateData {
+                    id: state.id,
+                    name: state.name(),
+                    frames: Vec::new(),
+                });
+                state_frames.push(state.frames.clone());
+            }
+            entities.push(EntityData {
+                id: entity.id,
+                name: entity.name(),
+                category: entity.category,
+                states,
+            });
+            frames.push(state_frames);
+        }
+        Self {
+            data: ProjectData {
+                categories,
+                entities,
+            },
+            frames,
+        }
+    }
+
+    /// Encode the frames in PNG.
+    pub fn encode(self) -> Result<ProjectData, DbError> {
+        let mut data = self.data;
+        for (entity, entity_frames) in data.entities.iter_mut().zip(self.frames) {
+            for (state, frames) in entity.states.iter_mut().zip(entity_frames) {
+                state.frames = entity_state::encode_frames(&frames)?;
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl ProjectData {
+    /// Snapshot of the editor model, encoding the frames.
+    pub fn from_project(project: &Project) -> Result<Self, DbError> {
+        ProjectSnapshot::new(project).encode()
+    }
+
+    /// Editor model of the project, decoding the frames. A project without categories gets the
+    /// default one.
+    pub fn into_project(self, name: &str) -> Result<Project, DbError> {
+        let mut project = if self.categories.is_empty() {
+            Project::new(name.to_string())
+        } else {
+            Project::empty(name.to_string())
+        };
+        for category in self.categories {
+            project.insert_category(EntityCategory::new(category.id, &category.name));
+        }
+        for data in self.entities {
+            let entity = Entity::new(data.id, &data.name, data.category);
+            for state_data in data.states {
+                let mut state = EntityState::new(state_data.id, &state_data.name);
+                for png in state_data.frames.iter() {
+                    state.push_encoded_frame(png)?;
+                }
+                entity.states.borrow_mut().insert(state.id, state);
+            }
+            project.insert_entity(entity);
+        }
+        Ok(project)
+    }
+}
+
+/// Read the categories, entities, states and frames of the project, in id order.
+pub async fn load(pool: &Pool<Sqlite>) -> Result<ProjectData, DbError> {
+    let mut data = ProjectData::default();
+    for row in sqlx::query("SELECT id, name FROM category ORDER BY id").fetch_all(pool).await? {
+        data.categories.push(CategoryData {
+            id: CategoryId(row.try_get("id")?),
+            name: row.try_get("name")?,
+        });
+    }
+
+    let mut states_by_entity: HashMap<i64, Vec<StateData>> = HashMap::new();
+    for row in sqlx::query("SELECT id, entity_id, name FROM state ORDER BY id").fetch_all(pool).await? {
+        let state = StateData {
+            id: StateId(row.try_get("id")?),
+            name: row.try_get("name")?,
+            frames: Vec::new(),
+        };
+        states_by_entity.entry(row.try_get("entity_id")?).or_default().push(state);
+    }
+    let mut frames_by_state: HashMap<i64, Vec<Vec<u8>>> = HashMap::new();
+    for row in sqlx::query("SELECT f.state_id, i.data FROM frame f JOIN image i ON i.id = f.image_id ORDER BY f.id")
+        .fetch_all(pool)
+        .await?
+    {
+        frames_by_state.entry(row.try_get("state_id")?).or_default().push(row.try_get("data")?);
+    }
+
+    for row in sqlx::query("SELECT id, category_id, name FROM entity ORDER BY id").fetch_all(pool).await? {
+        let id: i64 = row.try_get("id")?;
+        let states = states_by_entity
+            .remove(&id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|mut state| {
+                state.frames = frames_by_state.remove(&state.id.0).unwrap_or_default();
+                state
+            })
+            .collect();
+        data.entities.push(EntityData {
+            id: EntityId(id),
+            name: row.try_get("name")?,
+            category: CategoryId(row.try_get("category_id")?),
+            states,
+        });
+    }
+    info!(
+        "DB:project loaded, {} categories and {} entities",
+        data.categories.len(),
+        data.entities.len()
+    );
+    Ok(data)
+}
+
+/// Id above every category, entity and state the database ever held, deleted rows included, so
+/// that the editor never gives their ids to new rows.
+pub async fn next_id(pool: &Pool<Sqlite>) -> Result<i64, DbError> {
+    // The AUTOINCREMENT tables remember the highest id they ever held.
+    let max_id: Option<i64> =
+        sqlx::query("SELECT MAX(seq) AS seq FROM sqlite_sequence WHERE name IN ('category', 'entity', 'state')")
+            .fetch_one(pool)
+            .await?
+            .try_get("seq")?;
+    Ok(max_id.unwrap_or(0) + 1)
+}
+
+/// JSON description of a level, as written by the editor. `None` if the level does not exist or
+/// has no description yet.
+pub async fn level_data(pool: &Pool<Sqlite>, id: i64) -> Result<Option<String>, DbError> {
+    let row = sqlx::query("SELECT data FROM level WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(match row {
+        Some(row) => row.try_get("data")?,
+        None => None,
+    })
+}
+
+/// Name and JSON content of the dialogues and cutscenes, by name.
+pub async fn stories(pool: &Pool<Sqlite>) -> Result<Vec<(String, String)>, DbError> {
+    let mut stories = Vec::new();
+    for row in sqlx::query("SELECT name, data FROM story ORDER BY name").fetch_all(pool).await? {
+        stories.push((row.try_get("name")?, row.try_get("data")?));
+    }
+    Ok(stories)
+}
+
+/// Behaviour scripts, as the entity kind they drive and their Rhai source, by kind.
+pub async fn scripts(pool: &Pool<Sqlite>) -> Result<Vec<(String, String)>, DbError> {
+    let mut scripts = Vec::new();
+    for row in sqlx::query("SELECT name, source FROM script ORDER BY name").fetch_all(pool).await? {
+        scripts.push((row.try_get("name")?, row.try_get("source")?));
+    }
+    Ok(scripts)
+}
+
+/// Write the project in a single transaction: rows are matched by id, missing ones inserted with
+/// the id of the model, changed ones updated and the ones no longer in the project deleted.
+/// Images no longer used by any frame are deleted last.
+pub async fn save(pool: &Pool<Sqlite>, data: &ProjectData) -> Result<(), DbError> {
+    let mut tx = pool.begin().await?;
+    save_in(&mut tx, data).await?;
+    tx.commit().await?;
+    info!(
+        "DB:project saved, {} categories and {} entities",
+        data.categories.len(),
+        data.entities.len()
+    );
+    Ok(())
+}
+
+/// Write the project within a transaction of the caller, see [`save`].
+pub async fn save_in(tx: &mut Transaction<'_, Sqlite>, data: &ProjectData) -> Result<(), DbError> {
+    let mut categories: HashMap<i64, String> = HashMap::new();
+    for row in sqlx::query("SELECT id, name FROM category").fetch_all(&mut **tx).await? {
+        categories.insert(row.try_get("id")?, row.try_get("name")?);
+    }
+    for category in data.categories.iter() {
+        match categories.remove(&category.id.0) {
+            Some(name) if name == category.name => {}
+            Some(_) => {
+                sqlx::query("UPDATE category SET name = ? WHERE id = ?")
+                    .bind(&category.name)
+                    .bind(category.id.0)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+            None => {
+                sqlx::query("INSERT INTO category (id, name) VALUES (?, ?)")
+                    .bind(category.id.0)
+                    .bind(&category.name)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+    }
+
+    let mut entities: HashMap<i64, (i64, String)> = HashMap::new();
+    for row in sqlx::query("SELECT id, category_id, name FROM entity").fetch_all(&mut **tx).await? {
+        entities.insert(row.try_get("id")?, (row.try_get("category_id")?, row.try_get("name")?));
+    }
+    let mut states: HashMap<i64, (i64, String)> = HashMap::new();
+    for row in sqlx::query("SELECT id, entity_id, name FROM state").fetch_all(&mut **tx).await? {
+        states.insert(row.try_get("id")?, (row.try_get("entity_id")?, row.try_get("name")?));
+    }
+    let mut kept_states = HashSet::new();
+    for entity in data.entities.iter() {
+        match entities.remove(&entity.id.0) {
+            Some((category, name)) if category == entity.category.0 && name == entity.name => {}
+            Some(_) => {
+                sqlx::query("UPDATE entity SET category_id = ?, name = ? WHERE id = ?")
+                    .bind(entity.category.0)
+                    .bind(&entity.name)
+                    .bind(entity.id.0)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+            None => {
+                sqlx::query("INSERT INTO entity (id, category_id, name) VALUES (?, ?, ?)")
+                    .bind(entity.id.0)
+                    .bind(entity.category.0)
+                    .bind(&entity.name)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+
+        for state in entity.states.iter() {
+            match states.get(&state.id.0) {
+                Some((entity_id, name)) if *entity_id == entity.id.0 && *name == state.name => {}
+                Some(_) => {
+                    sqlx::query("UPDATE state SET entity_id = ?, name = ? WHERE id = ?")
+                        .bind(entity.id.0)
+                        .bind(&state.name)
+                        .bind(state.id.0)
+                        .execute(&mut **tx)
+                        .await?;
+                }
+                None => {
+                    sqlx::query("INSERT INTO state (id, entity_id, name) VALUES (?, ?, ?)")
+                        .bind(state.id.0)
+                        .bind(entity.id.0)
+                        .bind(&state.name)
+                        .execute(&mut **tx)
+                        .await?;
+                }
+            }
+            kept_states.insert(state.id.0);
+            save_frames(tx, state.id.0, &state.frames).await?;
+        }
+    }
+
+    // Children first, for the foreign keys.
+    let removed_states: Vec<i64> = states.into_keys().filter(|id| !kept_states.contains(id)).collect();
+    for id in removed_states {
+        delete_by(tx, "DELETE FROM frame WHERE state_id = ?", id).await?;
+        delete_by(tx, "DELETE FROM state WHERE id = ?", id).await?;
+    }
+    for id in entities.into_keys() {
+        delete_by(tx, "DELETE FROM frame WHERE state_id IN (SELECT id FROM state WHERE entity_id = ?)", id)
+            .await?;
+        delete_by(tx, "DELETE FROM state WHERE entity_id = ?", id).await?;
+        delete_by(tx, "DELETE FROM entity WHERE id = ?", id).await?;
+    }
+    for id in categories.into_keys() {
+        delete_by(tx, "DELETE FROM category WHERE id = ?", id).await?;
+    }
+    image_store::collect_garbage(tx).await?;
+    Ok(())
+}
+
+/// Update the frames of a state in place, in order, adding or deleting the extra ones. Each frame
+/// references the stored image of the same content.
+async fn save_frames(
+    tx: &mut Transaction<'_, Sqlite>,
+    state_id: i64,
+    frames: &[Vec<u8>],
+) -> Result<(), DbError> {
+    let mut current: Vec<(i64, Option<String>)> = Vec::new();
+    for row in sqlx::query(
+        "SELECT f.id, i.hash FROM frame f LEFT JOIN image i ON i.id = f.image_id WHERE f.state_id = ? ORDER BY f.id",
+    )
+    .bind(state_id)
+    .fetch_all(&mut **tx)
+    .await?
+    {
+        current.push((row.try_get("id")?, row.try_get("hash")?));
+    }
+    for (i, png) in frames.iter().enumerate() {
+        let hash = image_store::content_hash(png);
+        if matches!(current.get(i), Some((_, Some(current_hash))) if *current_hash == hash) {
+            continue;
+        }
+        let image_id = image_store::store(tx, &StoredImage::from_encoded(png.clone())?).await?;
+        match current.get(i) {
+            Some((id, _)) => {
+                sqlx::query("UPDATE frame SET image_id = ? WHERE id = ?")
+                    .bind(image_id)
+                    .bind(id)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+            None => {
+                sqlx::query("INSERT INTO frame (state_id, image_id) VALUES (?, ?)")
+                    .bind(state_id)
+                    .bind(image_id)
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+    }
+    for (id, _) in current.iter().skip(frames.len()) {
+        delete_by(tx, "DELETE FROM frame WHERE id = ?", *id).await?;
+    }
+    Ok(())
+}
+
+async fn delete_by(tx: &mut Transaction<'_, Sqlite>, query: &str, id: i64) -> Result<(), DbError> {
+    sqlx::query(query).bind(id).execute(&mut **tx).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::engine_db::EngineDb;
+    use std::path::PathBuf;
+
+    fn temp_db(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("gwen2d_repository_{}_{}.db", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    /// One category with the entities `(id, state ids)`.
+    fn project(entities: &[(i64, &[i64])]) -> ProjectData {
+        ProjectData {
+            categories: vec![CategoryData {
+                id: CategoryId(1),
+                name: String::from("Aucune"),
+            }],
+            entities: entities
+                .iter()
+                .map(|(id, states)| EntityData {
+                    id: EntityId(*id),
+                    name: format!("entité {}", id),
+                    category: CategoryId(1),
+                    states: states
+                        .iter()
+                        .map(|state| StateData {
+                            id: StateId(*state),
+                            name: format!("état {}", state),
+                            frames: Vec::new(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn state_moved_to_another_entity_is_saved() {
+        let path = temp_db("moved");
+        let mut db = EngineDb::new();
+        db.create(&path).await.unwrap();
+        db.save_project(&project(&[(2, &[4]), (3, &[])])).await.unwrap();
+        db.save_project(&project(&[(2, &[]), (3, &[4])])).await.unwrap();
+        assert_eq!(db.load_project().await.unwrap(), project(&[(2, &[]), (3, &[4])]));
+    }
+
+    #[tokio::test]
+    async fn ids_of_deleted_rows_are_not_given_again() {
+        let path = temp_db("next_id");
+        let mut db = EngineDb::new();
+        db.create(&path).await.unwrap();
+        db.save_project(&project(&[(2, &[4]), (3, &[5])])).await.unwrap();
+        db.save_project(&project(&[(2, &[4])])).await.unwrap();
+
+        let data = db.load_project().await.unwrap();
+        let mut project = data.into_project("projet").unwrap();
+        project.reserve_ids_below(db.next_id().await.unwrap());
+        assert_eq!(project.add_category("Décors"), Ok(CategoryId(6)));
+    }
+}
@@ -0,0 +1,21 @@
+//! Format of a GWEN 2D project: the model edited in the editors and played by the engine, the
+//! SQLite schema with its migrations, and the load/save APIs. The editors and the game read and
+//! write projects only through this crate so that they always agree on the format.
+
+pub mod db {
+    pub mod autosave;
+    pub mod engine_db;
+    pub mod image_store;
+    pub mod integrity;
+    pub mod migration;
+    pub mod project_export;
+    pub mod project_repository;
+}
+
+pub mod model {
+    pub mod entity;
+    pub mod entity_category;
+    pub mod entity_state;
+    pub mod project;
+    pub mod template;
+}
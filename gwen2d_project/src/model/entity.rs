@@ -0,0 +1,31 @@
+use std::cell::RefCell;
+
+use super::{entity_category::CategoryId, entity_state::{EntityState, StateId}};
+use std::collections::BTreeMap;
+
+/// Identifier of an entity, the primary key of its row in the project database.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct EntityId(pub i64);
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct Entity {
+    pub id : EntityId,
+    pub name : String,
+    pub category : CategoryId,
+    pub states : RefCell<BTreeMap<StateId, EntityState>>
+}
+
+impl Entity {
+    pub fn new(id : EntityId, entity_name : &str, category : CategoryId) -> Self {
+        Entity {
+            id,
+            name : String::from(entity_name),
+            category,
+            states : RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+}
\ No newline at end of file
@@ -0,0 +1,30 @@
+
+/// Identifier of a category, the primary key of its row in the project database.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct CategoryId(pub i64);
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct EntityCategory {
+    pub id : CategoryId,
+    pub name : String,
+}
+
+impl EntityCategory {
+    pub fn new(id : CategoryId, category_name : &str) -> Self {
+        EntityCategory {
+            id,
+            name : String::from(category_name),
+        }
+    }
+
+    pub fn default(id : CategoryId) -> Self {
+        EntityCategory {
+            id,
+            name : "Aucune".to_string(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+}
\ No newline at end of file
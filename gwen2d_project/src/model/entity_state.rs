@@ -0,0 +1,49 @@
+use image::{DynamicImage, ImageFormat, ImageResult};
+use std::io::Cursor;
+
+/// Identifier of a state, the primary key of its row in the project database.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+pub struct StateId(pub i64);
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct EntityState {
+    pub id : StateId,
+    pub name : String,
+    pub frames : Vec<DynamicImage>
+}
+
+impl EntityState {
+    pub fn new(id : StateId, state_name : &str) -> Self {
+        EntityState {
+            id,
+            name : String::from(state_name),
+            frames : Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Frames encoded in PNG, as saved in the project.
+    pub fn encoded_frames(&self) -> ImageResult<Vec<Vec<u8>>> {
+        encode_frames(&self.frames)
+    }
+
+    /// Decode a saved frame and append it.
+    pub fn push_encoded_frame(&mut self, encoded : &[u8]) -> ImageResult<()> {
+        self.frames.push(image::load_from_memory(encoded)?);
+        Ok(())
+    }
+}
+
+/// Encode frames in PNG, as saved in the project.
+pub fn encode_frames(frames : &[DynamicImage]) -> ImageResult<Vec<Vec<u8>>> {
+    let mut encoded = Vec::new();
+    for frame in frames.iter() {
+        let mut png = Vec::new();
+        frame.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+        encoded.push(png);
+    }
+    Ok(encoded)
+}
@@ -0,0 +1,178 @@
+use std::{cell::RefCell, collections::BTreeMap};
+use log::info;
+use super::{
+    entity::{Entity, EntityId},
+    entity_category::{CategoryId, EntityCategory},
+    entity_state::{EntityState, StateId},
+};
+
+/// Project edited in the editor. Entities reference their category and hold their states by id,
+/// so a rename only changes the name of the renamed object.
+pub struct Project {
+    pub name : String,
+    pub categories : RefCell<Vec<EntityCategory>>,
+    pub entities : RefCell<BTreeMap<EntityId, Entity>>,
+    /// Next free id, shared by the categories, entities and states, above every id in the project.
+    next_id : i64,
+}
+
+impl Project {
+    pub fn new(project_name : String) -> Self {
+        let mut project = Project {
+            name : project_name,
+            categories : RefCell::new(Vec::new()),
+            entities : RefCell::new(BTreeMap::new()),
+            next_id : 1,
+        };
+        let id = CategoryId(project.allocate_id());
+        project.categories.borrow_mut().push(EntityCategory::default(id));
+        project
+    }
+
+    /// Project without any category, to be filled with the categories and entities of a saved
+    /// project.
+    pub fn empty(project_name : String) -> Self {
+        Project {
+            name : project_name,
+            categories : RefCell::new(Vec::new()),
+            entities : RefCell::new(BTreeMap::new()),
+            next_id : 1,
+        }
+    }
+
+    fn allocate_id(&mut self) -> i64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn reserve_id(&mut self, id : i64) {
+        self.next_id = self.next_id.max(id + 1);
+    }
+
+    /// Never give ids below `next_id` to the new categories, entities and states.
+    pub fn reserve_ids_below(&mut self, next_id : i64) {
+        self.next_id = self.next_id.max(next_id);
+    }
+
+    /// Add a category read from the project database, keeping its id.
+    pub fn insert_category(&mut self, category : EntityCategory) {
+        self.reserve_id(category.id.0);
+        self.categories.borrow_mut().push(category);
+    }
+
+    /// Add an entity and its states read from the project database, keeping their ids.
+    pub fn insert_entity(&mut self, entity : Entity) {
+        self.reserve_id(entity.id.0);
+        let state_ids : Vec<StateId> = entity.states.borrow().keys().copied().collect();
+        for id in state_ids {
+            self.reserve_id(id.0);
+        }
+        self.entities.borrow_mut().insert(entity.id, entity);
+    }
+
+    pub fn add_category(&mut self, name : &str) -> Result<CategoryId, String> {
+        check_name(name, self.categories.borrow().iter().map(|c| c.name.as_str()))?;
+        let id = CategoryId(self.allocate_id());
+        self.categories.borrow_mut().push(EntityCategory::new(id, name));
+        Ok(id)
+    }
+
+    pub fn add_entity(&mut self, category : CategoryId, name : &str) -> Result<EntityId, String> {
+        check_name(name, self.entities.borrow().values().map(|e| e.name.as_str()))?;
+        if self.category_name(category).is_none() {
+            return Err(String::from("catégorie inconnue"));
+        }
+        let id = EntityId(self.allocate_id());
+        self.entities.borrow_mut().insert(id, Entity::new(id, name, category));
+        Ok(id)
+    }
+
+    pub fn add_entity_state(&mut self, entity_id : EntityId, state_name : &str) -> Result<StateId, String> {
+        let id = StateId(self.allocate_id());
+        match self.entities.borrow().get(&entity_id) {
+            Some(entity) => {
+                check_name(state_name, entity.states.borrow().values().map(|s| s.name.as_str()))?;
+                entity.states.borrow_mut().insert(id, EntityState::new(id, state_name));
+                Ok(id)
+            },
+            None => {
+                info!("Error while creating state {}", state_name);
+                Err(String::from("entité inconnue"))
+            }
+        }
+    }
+
+    pub fn category_name(&self, id : CategoryId) -> Option<String> {
+        self.categories.borrow().iter().find(|c| c.id == id).map(|c| c.name())
+    }
+
+    pub fn entity_name(&self, id : EntityId) -> Option<String> {
+        self.entities.borrow().get(&id).map(|e| e.name())
+    }
+
+    pub fn state_name(&self, entity_id : EntityId, state_id : StateId) -> Option<String> {
+        self.entities.borrow().get(&entity_id)?.states.borrow().get(&state_id).map(|s| s.name())
+    }
+
+    /// First category, given to the entities created without choosing one.
+    pub fn default_category(&self) -> Option<CategoryId> {
+        self.categories.borrow().first().map(|c| c.id)
+    }
+
+    pub fn get_states(&self, entity_id : EntityId) -> Vec<EntityState> {
+        match self.entities.borrow().get(&entity_id) {
+            Some(entity) => entity.states.borrow().values().cloned().collect(),
+            None =>  Vec::new()
+        }
+    }
+
+    pub fn rename_category(&mut self, id : CategoryId, name : &str) -> Result<(), String> {
+        let mut categories = self.categories.borrow_mut();
+        check_name(name, categories.iter().filter(|c| c.id != id).map(|c| c.name.as_str()))?;
+        match categories.iter_mut().find(|c| c.id == id) {
+            Some(category) => {
+                category.name = String::from(name);
+                Ok(())
+            },
+            None => Err(String::from("catégorie inconnue"))
+        }
+    }
+
+    pub fn rename_entity(&mut self, id : EntityId, name : &str) -> Result<(), String> {
+        let mut entities = self.entities.borrow_mut();
+        check_name(name, entities.values().filter(|e| e.id != id).map(|e| e.name.as_str()))?;
+        match entities.get_mut(&id) {
+            Some(entity) => {
+                entity.name = String::from(name);
+                Ok(())
+            },
+            None => Err(String::from("entité inconnue"))
+        }
+    }
+
+    pub fn rename_state(&mut self, entity_id : EntityId, state_id : StateId, name : &str) -> Result<(), String> {
+        let entities = self.entities.borrow();
+        let entity = entities.get(&entity_id).ok_or_else(|| String::from("entité inconnue"))?;
+        let mut states = entity.states.borrow_mut();
+        check_name(name, states.values().filter(|s| s.id != state_id).map(|s| s.name.as_str()))?;
+        match states.get_mut(&state_id) {
+            Some(state) => {
+                state.name = String::from(name);
+                Ok(())
+            },
+            None => Err(String::from("état inconnu"))
+        }
+    }
+}
+
+/// Refuse an empty name or a name already used by a sibling.
+fn check_name<'a>(name : &str, mut siblings : impl Iterator<Item = &'a str>) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err(String::from("le nom est vide"));
+    }
+    if siblings.any(|sibling| sibling == name) {
+        return Err(format!("le nom {} est déjà utilisé", name));
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use super::{entity::EntityId, project::Project};
+
+/// Starting content of a new project.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ProjectTemplate {
+    Empty,
+    /// Player, enemy and coin entities with placeholder frames.
+    Platformer,
+}
+
+impl ProjectTemplate {
+    pub const ALL : [ProjectTemplate; 2] = [ProjectTemplate::Empty, ProjectTemplate::Platformer];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ProjectTemplate::Empty => "Projet vide",
+            ProjectTemplate::Platformer => "Exemple de jeu de plateforme",
+        }
+    }
+
+    pub fn build(&self, project_name : String) -> Project {
+        let mut project = Project::new(project_name);
+        if *self == ProjectTemplate::Platformer {
+            fill_platformer(&mut project).expect("platformer template names are unique");
+        }
+        project
+    }
+}
+
+fn fill_platformer(project : &mut Project) -> Result<(), String> {
+    let characters = project.add_category("Personnages")?;
+    let items = project.add_category("Objets")?;
+    let blue = Rgba([60, 110, 220, 255]);
+    let red = Rgba([210, 60, 50, 255]);
+    let yellow = Rgba([240, 200, 40, 255]);
+
+    let player = project.add_entity(characters, "joueur")?;
+    add_state(project, player, "repos", vec![block(16, 24, blue, 0)])?;
+    add_state(project, player, "course", vec![block(16, 24, blue, 0), block(16, 24, blue, 2)])?;
+    add_state(project, player, "saut", vec![block(16, 24, blue, 4)])?;
+
+    let enemy = project.add_entity(characters, "ennemi")?;
+    add_state(project, enemy, "marche", vec![block(16, 16, red, 0), block(16, 16, red, 2)])?;
+
+    let coin = project.add_entity(items, "piece")?;
+    add_state(project, coin, "rotation", vec![coin_frame(8, yellow), coin_frame(4, yellow), coin_frame(2, yellow)])?;
+    Ok(())
+}
+
+fn add_state(
+    project : &mut Project,
+    entity : EntityId,
+    name : &str,
+    frames : Vec<DynamicImage>,
+) -> Result<(), String> {
+    let state = project.add_entity_state(entity, name)?;
+    if let Some(entity) = project.entities.borrow().get(&entity) {
+        if let Some(state) = entity.states.borrow_mut().get_mut(&state) {
+            state.frames = frames;
+        }
+    }
+    Ok(())
+}
+
+/// Filled rectangle, `bottom_gap` transparent rows under it to suggest a move.
+fn block(width : u32, height : u32, color : Rgba<u8>, bottom_gap : u32) -> DynamicImage {
+    let image = RgbaImage::from_fn(width, height, |_, y| {
+        if y + bottom_gap < height { color } else { Rgba([0, 0, 0, 0]) }
+    });
+    DynamicImage::ImageRgba8(image)
+}
+
+/// Ellipse of a 16 pixels high coin seen with a half width of `radius_x` pixels.
+fn coin_frame(radius_x : u32, color : Rgba<u8>) -> DynamicImage {
+    let image = RgbaImage::from_fn(16, 16, |x, y| {
+        let dx = (x as f32 + 0.5 - 8.) / radius_x as f32;
+        let dy = (y as f32 + 0.5 - 8.) / 8.;
+        if dx * dx + dy * dy <= 1. { color } else { Rgba([0, 0, 0, 0]) }
+    });
+    DynamicImage::ImageRgba8(image)
+}